@@ -0,0 +1,180 @@
+use crate::runtime::gc::GcMode;
+use crate::runtime::vm::VM;
+
+pub const USAGE: &str = "\
+Usage: rox [options] [command]
+
+Commands:
+  run <file> [-- args...]       run a .lox script or .roxc bytecode file
+  repl                          start an interactive session (the default)
+  disasm <file>                 print the bytecode of a script
+  check <file>                  compile a script without running it
+  compile <file> [-o <out>]     write the bytecode of a script to a .roxc file
+  help                          show this message, also `-h` and `--help`
+  <file> [-- args...]           same as `run`
+
+Options:
+  --gc-mode=stress|log|off      when the garbage collector runs
+  --trace                       print every instruction as it runs
+  --stack-size=<n>              the most values the stack may hold
+  --max-frames=<n>              the deepest the call stack may get
+  --module-path=<dir>           also look for imported modules in <dir>, may be repeated
+
+Arguments after `--` are handed to the script as `os::args()`.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run {
+        path: String,
+        args: Vec<String>,
+    },
+    Repl,
+    Help,
+    Disasm(String),
+    Check(String),
+    Compile {
+        input: String,
+        output: Option<String>,
+    },
+}
+
+/// the parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub gc_mode: Option<GcMode>,
+    pub trace: bool,
+    pub stack_size: Option<usize>,
+    pub max_frames: Option<usize>,
+    pub module_paths: Vec<String>,
+}
+
+impl Cli {
+    /// parses the arguments following the program name, the error is a message
+    /// for the user.
+    pub fn parse(args: &[String]) -> Result<Cli, String> {
+        let (args, script_args) = match args.iter().position(|arg| arg == "--") {
+            Some(i) => (&args[..i], args[i + 1..].to_vec()),
+            None => (args, vec![]),
+        };
+
+        let mut cli = Cli {
+            command: Command::Repl,
+            gc_mode: None,
+            trace: false,
+            stack_size: None,
+            max_frames: None,
+            module_paths: vec![],
+        };
+        let mut positional: Vec<&str> = vec![];
+        let mut help = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                help = true;
+                continue;
+            }
+            let Some(option) = arg.strip_prefix("--") else {
+                positional.push(arg);
+                continue;
+            };
+            let (name, inline) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (option, None),
+            };
+            if name == "trace" && inline.is_none() {
+                cli.trace = true;
+                continue;
+            }
+            let value = match inline {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .ok_or_else(|| format!("Missing a value for `--{}`.", name))?,
+            };
+            match name {
+                "gc-mode" => cli.gc_mode = Some(parse_gc_mode(value)?),
+                "stack-size" => cli.stack_size = Some(parse_count(name, value)?),
+                "max-frames" => cli.max_frames = Some(parse_count(name, value)?),
+                "module-path" => cli.module_paths.push(value.to_owned()),
+                _ => return Err(format!("Unknown option `{}`.", arg)),
+            }
+        }
+
+        cli.command = match positional.as_slice() {
+            // asking for help wins over whatever else was given.
+            _ if help => Command::Help,
+            ["help"] => Command::Help,
+            [] | ["repl"] => Command::Repl,
+            ["run", path] => Command::Run {
+                path: path.to_string(),
+                args: script_args.clone(),
+            },
+            ["disasm", path] => Command::Disasm(path.to_string()),
+            ["check", path] => Command::Check(path.to_string()),
+            ["compile", input] => Command::Compile {
+                input: input.to_string(),
+                output: None,
+            },
+            ["compile", input, "-o", output] => Command::Compile {
+                input: input.to_string(),
+                output: Some(output.to_string()),
+            },
+            [path] if !is_command(path) => Command::Run {
+                path: path.to_string(),
+                args: script_args.clone(),
+            },
+            _ => return Err(USAGE.to_owned()),
+        };
+        if !script_args.is_empty() && !matches!(cli.command, Command::Run { .. } | Command::Help) {
+            return Err("Only `run` takes script arguments.".to_owned());
+        }
+        Ok(cli)
+    }
+
+    /// applies the options to `vm`.
+    pub fn configure(&self, vm: &mut VM) {
+        if let Some(gc_mode) = self.gc_mode {
+            vm.set_gc_mode(gc_mode);
+        }
+        if let Some(stack_size) = self.stack_size {
+            vm.set_stack_size(stack_size);
+        }
+        if let Some(max_frames) = self.max_frames {
+            vm.set_max_frames(max_frames);
+        }
+        for dir in &self.module_paths {
+            vm.add_module_path(dir);
+        }
+        vm.set_trace(self.trace);
+    }
+}
+
+fn is_command(word: &str) -> bool {
+    matches!(
+        word,
+        "run" | "repl" | "help" | "disasm" | "check" | "compile"
+    )
+}
+
+fn parse_gc_mode(value: &str) -> Result<GcMode, String> {
+    match value {
+        "stress" => Ok(GcMode::Stress),
+        "log" => Ok(GcMode::Log),
+        "off" => Ok(GcMode::Off),
+        _ => Err(format!(
+            "Unknown gc mode `{}`, expected stress, log or off.",
+            value
+        )),
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "`--{}` expects a positive number but got `{}`.",
+            name, value
+        )),
+    }
+}
@@ -1,16 +1,19 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
 use std::{mem, vec};
 
+use super::diagnostic::Diagnostic;
 use super::parser::Parser;
 use super::token::Kind;
 use crate::compile::token::Token;
-use crate::core::chunk::Chunk;
+use crate::core::chunk::{Chunk, Handler};
 use crate::core::opcode::OpCode;
 use crate::core::value::Value;
 use crate::data_structures::interner::{self};
 use crate::runtime::{lang::Function, lang::FunctionType};
-use crate::std::is_native_call;
+use crate::std::registry::{Arity, NativeRegistry};
 
 pub const FUNCTION_ARG_MAX: u32 = 255;
 pub const LONG_ARG_INDEX: u8 = 1; // index of a captured value sitting on slot > 255
@@ -22,6 +25,25 @@ pub static DEFAULT_ERR_RULE: ParseRule = ParseRule::default();
 pub const INIT_KEYWORD: &str = "init"; // for constructors e.g `java` Foo(bar, baz) {}
 pub const THIS_KEYWORD: &str = "this";
 pub const SUPER_KEYWORD: &str = "super";
+// name of the hidden local holding a switch's value, the space keeps user code from resolving it.
+const SWITCH_VALUE: &str = "switch value";
+// hidden locals of a `try` statement with a `finally` block: the error being handled or
+// the value being returned, and what to do once the `finally` block has run.
+const FINALLY_VALUE: &str = "finally value";
+const FINALLY_ACTION: &str = "finally action";
+// the action of a `finally` block entered by an error, the exits leaving the
+// `try` or `catch` block are numbered from 1.
+const FINALLY_RETHROW: i64 = 0;
+// name given to anonymous functions, shows up in stack traces as `lambda()`.
+const LAMBDA_NAME: &str = "lambda";
+// contextual keywords of imports, they remain valid names everywhere else.
+const FROM_KEYWORD: &str = "from";
+const AS_KEYWORD: &str = "as";
+// separates a module from the name of one of its globals, e.g `lib::name`.
+const MODULE_SEPARATOR: &str = "::";
+
+/// globals declared outside of the source being compiled and whether they are `const`.
+pub type KnownGlobals = HashMap<String, bool>;
 
 // name existing means it has been declared
 #[derive(Debug, Default, Clone, Copy)]
@@ -63,6 +85,44 @@ pub(crate) struct ClassCompiler<'src> {
     has_super: bool,
 }
 
+/// book keeping for the innermost enclosing loop, so `break` and `continue`
+/// know where to jump and which locals to discard before jumping.
+#[derive(Debug)]
+struct Loop {
+    // where `continue` jumps back to: the condition, or the increment of a `for`.
+    start: usize,
+    // locals declared deeper than this live in the loop body.
+    scope_depth: i32,
+    // `break` jumps are patched once the end of the loop is known.
+    breaks: Vec<usize>,
+    // `finally` blocks already pending when the loop started, a jump out of the loop
+    // runs the others first.
+    finally_blocks: usize,
+}
+
+/// a statement leaving a block early.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Exit {
+    Return,
+    // the index of the loop in `Compiler::loops`.
+    Break(usize),
+    Continue(usize),
+}
+
+/// the `finally` block of the `try` statement being compiled. Exits leaving its `try` or
+/// `catch` block jump to it with their action stored in a hidden local, and the code after
+/// the `finally` block carries on with the exit.
+#[derive(Debug)]
+struct Finally {
+    // slots of the `FINALLY_VALUE` and `FINALLY_ACTION` locals.
+    value: usize,
+    action: usize,
+    // locals declared deeper than this live in the `try` or `catch` block.
+    scope_depth: i32,
+    // the exit of action `i + 1` and its jumps to the `finally` block.
+    exits: Vec<(Exit, Vec<usize>)>,
+}
+
 /// index stores which local slot the upvalue is capturing.
 /// HACK: this should be an index since we already allow > 255 constants
 /// is_local: is true if it captures a local in its immediate scope.
@@ -95,14 +155,80 @@ pub struct Compiler<'src> {
     enclosing: Option<Box<Compiler<'src>>>,
     upvalues: Vec<UpValue>,
     class_stack: Rc<RefCell<Vec<ClassCompiler<'src>>>>,
+    // loops are per function, a `break` cannot leave the function it is in.
+    loops: Vec<Loop>,
+    // `finally` blocks of the `try` statements being compiled, innermost last. Like loops
+    // they don't reach into nested functions.
+    finally_blocks: Vec<Finally>,
+    // names that resolve to natives when no global declares them.
+    natives: Rc<NativeRegistry>,
+    // globals declared outside of the source, by an earlier script or the embedder.
+    known_globals: Rc<KnownGlobals>,
+    // the source is a repl entry, whose last expression statement is the script's result.
+    repl: bool,
+    // set by `named_variable` when a native is about to be called by name,
+    // so `call` can check the argument count.
+    called_native: Option<(&'src str, Arity)>,
 }
 
 impl<'src> Compiler<'src> {
     // associated function, like java static functions
     /// The VM passes a Chunk to the compiler which it fills with code.
     /// now the compiler will create and return a function that contains the
-    /// compiled top-level code.
-    pub fn compile(source: &str) -> Option<Rc<Function>> {
+    /// compiled top-level code, or every error found in the source.
+    pub fn compile(source: &str) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        Self::compile_with_natives(source, Rc::new(NativeRegistry::standard()))
+    }
+
+    /// compiles `source` against the natives an embedder registered with its vm.
+    pub fn compile_with_natives(
+        source: &str,
+        natives: Rc<NativeRegistry>,
+    ) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        Self::compile_with_globals(source, natives, &mut KnownGlobals::new())
+    }
+
+    /// like `compile_with_natives`, but `source` may use the globals in `known` without
+    /// declaring them. The globals `source` declares are added to `known` once it compiled,
+    /// so scripts run one after another on the same vm can build on each other.
+    pub fn compile_with_globals(
+        source: &str,
+        natives: Rc<NativeRegistry>,
+        known: &mut KnownGlobals,
+    ) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        Self::compile_source(source, natives, known, false, None)
+    }
+
+    /// compiles the top-level code of the module read from `path`, which stack traces
+    /// name after the file.
+    pub fn compile_module(
+        source: &str,
+        natives: Rc<NativeRegistry>,
+        path: &Path,
+    ) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        let name = path.display().to_string();
+        Self::compile_source(source, natives, &mut KnownGlobals::new(), false, Some(name))
+    }
+
+    /// compiles one entry of a repl session, see `compile_with_globals`. An expression
+    /// statement ending the entry returns its value from the script instead of discarding it.
+    pub fn compile_repl_entry(
+        source: &str,
+        natives: Rc<NativeRegistry>,
+        known: &mut KnownGlobals,
+    ) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        Self::compile_source(source, natives, known, true, None)
+    }
+
+    fn compile_source(
+        source: &str,
+        natives: Rc<NativeRegistry>,
+        known: &mut KnownGlobals,
+        repl: bool,
+        name: Option<String>,
+    ) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        let mut function = Function::new();
+        function.name = name;
         let mut compiler: Compiler = Compiler {
             // NOTE: parser is enclosed here for interior mutability. when compiling functions,
             // reference to the outer parser is needed to continue the single pass.
@@ -112,11 +238,17 @@ impl<'src> Compiler<'src> {
             globals: Rc::new(RefCell::new(vec![])),
             // interior mutabliity, this is so we can return the function after compiling
             // and don't have to worry about `dangling` ptr once compile is finished.
-            function: Function::new(),
+            function,
             function_type: FunctionType::default(),
             enclosing: None,
             upvalues: vec![],
             class_stack: Rc::new(RefCell::new(vec![])),
+            loops: vec![],
+            finally_blocks: vec![],
+            natives,
+            known_globals: Rc::new(known.clone()),
+            repl,
+            called_native: None,
         };
 
         // we need this for alignment, the function then looks for params/ args starting from index 1.
@@ -144,9 +276,14 @@ impl<'src> Compiler<'src> {
         // a.push_str(" world");  // ❌ can't mutate through Rc
         let function: Rc<Function> = compiler.end_compilation();
         if compiler.parser.borrow().had_error {
-            None
+            Err(std::mem::take(
+                &mut compiler.parser.borrow_mut().diagnostics,
+            ))
         } else {
-            Some(function)
+            for global in compiler.globals.borrow().iter() {
+                known.insert(global.name.lexeme.to_owned(), global.is_const);
+            }
+            Ok(function)
         }
     }
 
@@ -168,12 +305,17 @@ impl<'src> Compiler<'src> {
     }
 
     fn emit_return(&mut self) {
+        self.emit_return_value();
+        self.emit_opcode(OpCode::Return);
+    }
+
+    /// what a `return;` returns, `this` in an initializer.
+    fn emit_return_value(&mut self) {
         if self.function_type == FunctionType::Init {
             self.emit_opcode_operand(OpCode::GetLocal, 0);
         } else {
             self.emit_opcode(OpCode::NIL);
         }
-        self.emit_opcode(OpCode::Return);
     }
 
     fn emit_opcode(&mut self, op_code: OpCode) {
@@ -182,51 +324,49 @@ impl<'src> Compiler<'src> {
 
     // byte may be opcode or operand
     fn emit_byte(&mut self, byte: u8) {
-        let line = self.parser.borrow().previous.line;
-        self.current_chunk().write(byte, line);
+        let Token { line, column, .. } = self.parser.borrow().previous;
+        self.current_chunk().write_at(byte, line, column);
     }
 
     fn emit_opcodes(&mut self, op_1: OpCode, op_2: OpCode) {
         self.emit_bytes(op_1 as u8, op_2 as u8);
     }
 
-    // emits the opcode and the argument to this opcode
+    // emits the opcode and the argument to this opcode, indexes that do not fit
+    // in a byte are prefixed with `OpCode::Wide` and take 3 bytes.
     fn emit_opcode_operand(&mut self, opcode: OpCode, index: usize) {
-        self.emit_opcode(opcode);
-        // resolve constant operand
         if index > 255 {
-            let (bits0_7, bits8_15, bits16) = Chunk::resolve_index(index);
-            self.emit_byte(bits0_7);
-            self.emit_byte(bits8_15);
-            self.emit_byte(bits16);
+            self.emit_opcode(OpCode::Wide);
+            self.emit_opcode(opcode);
+            self.emit_long_operand(index);
         } else {
+            self.emit_opcode(opcode);
             self.emit_byte(index as u8);
         }
     }
 
+    fn emit_long_operand(&mut self, index: usize) {
+        let (bits0_7, bits8_15, bits16) = Chunk::resolve_index(index);
+        self.emit_byte(bits0_7);
+        self.emit_byte(bits8_15);
+        self.emit_byte(bits16);
+    }
+
     fn emit_bytes(&mut self, byte_1: u8, byte_2: u8) {
-        let line = self.parser.borrow().previous.line;
-        self.current_chunk().write(byte_1, line);
-        self.current_chunk().write(byte_2, line);
+        self.emit_byte(byte_1);
+        self.emit_byte(byte_2);
     }
 
     fn emit_constant(&mut self, value: Value) {
         // emits the opcode and its byte operand (the index of the value in the constants array.)
         let index: usize = self.current_chunk().add_if_absent(value);
-        // this lets us record the index that triggers the use of OpCode::Constant24, where reading 3 bytes
-        // must be read to get the index of a constant from the constant pool.
-        if self.current_chunk().index_const24 == usize::MAX && index > 255 {
-            self.current_chunk().save_index();
+        // Constant24 always has a 3 byte operand, it needs no `Wide` prefix.
+        if index > 255 {
+            self.emit_opcode(OpCode::Constant24);
+            self.emit_long_operand(index);
+        } else {
+            self.emit_opcode_operand(OpCode::Constant, index);
         }
-
-        self.emit_opcode_operand(
-            if index > 255 {
-                OpCode::Constant24
-            } else {
-                OpCode::Constant
-            },
-            index,
-        );
     }
 
     fn expression(&mut self) {
@@ -235,7 +375,7 @@ impl<'src> Compiler<'src> {
 
     fn end_compilation(&mut self) -> Rc<Function> {
         self.emit_return();
-        #[cfg(feature = "")] // #[cfg(feature="")] // custom features
+        #[cfg(feature = "debug_print_code")] // custom features
         // #[cfg(any(test, feature=""))] // analogous to a #ifdef block in C
         let name = self
             .function
@@ -243,15 +383,15 @@ impl<'src> Compiler<'src> {
             .as_deref()
             .unwrap_or("Script")
             .to_string();
-        #[cfg(feature = "")]
+        #[cfg(feature = "debug_print_code")]
         let status = if self.parser.borrow().had_error {
             "Failed to Compile"
         } else {
             "Compile successful"
         };
-        #[cfg(feature = "")]
+        #[cfg(feature = "debug_print_code")]
         let display_string = format!("{}  :  {}", name, status);
-        #[cfg(feature = "")]
+        #[cfg(feature = "debug_print_code")]
         Chunk::disassemble(self.current_chunk(), &display_string);
 
         self.function.free_unused_mem();
@@ -268,7 +408,9 @@ impl<'src> Compiler<'src> {
     fn declaration(&mut self) {
         if self.match_token(Kind::Class) {
             self.class_declaration();
-        } else if self.match_token(Kind::Fun) {
+        } else if self.check(Kind::Fun) && self.parser.borrow().peek_next() != Kind::LeftParen {
+            // `fun (` starts a lambda expression statement instead.
+            self.parser.borrow_mut().advance();
             self.func_declaration();
         } else if self.match_token(Kind::Var) {
             self.variable_declaration(false);
@@ -276,6 +418,13 @@ impl<'src> Compiler<'src> {
             self.variable_declaration(true);
         } else if self.match_token(Kind::Return) {
             self.return_statement();
+        } else if self.match_token(Kind::Import) {
+            self.import_declaration();
+        } else if self.check_contextual(FROM_KEYWORD)
+            && self.parser.borrow().peek_next() == Kind::String
+        {
+            self.parser.borrow_mut().advance();
+            self.import_names_declaration();
         } else {
             self.statement();
         }
@@ -285,6 +434,58 @@ impl<'src> Compiler<'src> {
         }
     }
 
+    /// true if the current token is the identifier `word`.
+    fn check_contextual(&self, word: &str) -> bool {
+        let current = self.parser.borrow().current;
+        current.kind == Kind::Identifier && current.lexeme == word
+    }
+
+    /// `import "path" as name;` binds the module loaded from `path` to `name`.
+    fn import_declaration(&mut self) {
+        let path = self.module_path("Expect module path after 'import'.");
+        if self.check_contextual(AS_KEYWORD) {
+            self.parser.borrow_mut().advance();
+        } else {
+            self.parser
+                .borrow_mut()
+                .error_at_current("Expect 'as' after module path.");
+        }
+        // a module binding is const like the globals read through it.
+        let global = self.parse_variable("Expect module name after 'as'.", true);
+        self.emit_opcode_operand(OpCode::Import, path);
+        self.define_variable(global, true);
+        self.consume(Kind::SemiColon, "Expect ';' after import.");
+    }
+
+    /// `from "path" import a, b;` binds the globals `a` and `b` of the module to the
+    /// same names, as consts since assigning them would not change the module.
+    fn import_names_declaration(&mut self) {
+        let path = self.module_path("Expect module path after 'from'.");
+        self.consume(Kind::Import, "Expect 'import' after module path.");
+        loop {
+            let global = self.parse_variable("Expect name to import.", true);
+            let name = self.parser.borrow().previous;
+            let member = self.identifier_constant(name);
+            // the module is cached after its first import, so importing it per name is cheap.
+            self.emit_opcode_operand(OpCode::Import, path);
+            self.emit_opcode_operand(OpCode::GetProperty, member);
+            self.define_variable(global, true);
+            if !self.match_token(Kind::Comma) {
+                break;
+            }
+        }
+        self.consume(Kind::SemiColon, "Expect ';' after imported names.");
+    }
+
+    /// the constant holding the path of an import.
+    fn module_path(&mut self, err_msg: &'static str) -> usize {
+        self.consume(Kind::String, err_msg);
+        let lexeme = self.parser.borrow().previous.lexeme;
+        let path = lexeme.trim_matches('"');
+        self.current_chunk()
+            .add_if_absent(Value::String(interner::intern(path)))
+    }
+
     fn return_statement(&mut self) {
         if self.function_type == FunctionType::Script {
             self.parser
@@ -294,7 +495,7 @@ impl<'src> Compiler<'src> {
 
         if self.match_token(Kind::SemiColon) {
             // if there is no return value, implictly return NIL
-            self.emit_return();
+            self.emit_return_value();
         } else {
             if self.function_type == FunctionType::Init {
                 self.parser
@@ -303,8 +504,8 @@ impl<'src> Compiler<'src> {
             }
             self.expression();
             self.consume(Kind::SemiColon, "Expect ';' after return value.");
-            self.emit_opcode(OpCode::Return);
         }
+        self.exit(Exit::Return);
     }
 
     fn class_declaration(&mut self) {
@@ -462,11 +663,45 @@ impl<'src> Compiler<'src> {
     }
 
     fn function(&mut self, func_type: FunctionType) {
+        let function_name = self.parser.borrow().previous.lexeme;
+        self.consume(Kind::LeftParen, "Expect '(' in function declaration.");
+        self.function_body(func_type, function_name, false);
+    }
+
+    /// `fun (a, b) { ... }` used as an expression.
+    fn lambda(&mut self) {
+        self.consume(Kind::LeftParen, "Expect '(' after 'fun'.");
+        self.function_body(FunctionType::Function, LAMBDA_NAME, false);
+    }
+
+    /// `(a, b) => expr`, called from `grouping` with the '(' already consumed.
+    /// The body is a single expression whose value is returned.
+    fn arrow_function(&mut self) {
+        self.function_body(FunctionType::Function, LAMBDA_NAME, true);
+    }
+
+    /// true if the tokens after a '(' are a parameter list followed by `=>`.
+    fn arrow_ahead(&self) -> bool {
+        let parser = self.parser.borrow();
+        let mut tokens = std::iter::once(parser.current).chain(parser.tokens_ahead());
+        let mut expect_name = true;
+        loop {
+            match tokens.next().map(|t| t.kind) {
+                Some(Kind::RightParen) => break,
+                Some(Kind::Identifier) if expect_name => expect_name = false,
+                Some(Kind::Comma) if !expect_name => expect_name = true,
+                _ => return false,
+            }
+        }
+        tokens.next().map(|t| t.kind) == Some(Kind::Arrow)
+    }
+
+    /// compiles parameters and body of a function whose '(' has been consumed,
+    /// and emits the closure into the enclosing chunk.
+    fn function_body(&mut self, func_type: FunctionType, function_name: &str, arrow: bool) {
         // we take out self because of weird lifetime issues and replace with default
         // enclosing is returned back into self.
-        let mut enclosing = std::mem::take(self);
-        let function_name = enclosing.parser.borrow().previous.lexeme;
-        enclosing.consume(Kind::LeftParen, "Expect '(' in function declaration.");
+        let enclosing = std::mem::take(self);
 
         let mut inner: Compiler = Compiler {
             parser: enclosing.parser.clone(),
@@ -476,8 +711,14 @@ impl<'src> Compiler<'src> {
             function: Function::new(),
             function_type: func_type,
             class_stack: enclosing.class_stack.clone(),
+            natives: enclosing.natives.clone(),
+            known_globals: enclosing.known_globals.clone(),
+            repl: false,
             enclosing: Some(Box::new(enclosing)),
             upvalues: vec![],
+            loops: vec![],
+            finally_blocks: vec![],
+            called_native: None,
         };
 
         inner.function.name = Some(function_name.to_owned());
@@ -492,11 +733,7 @@ impl<'src> Compiler<'src> {
         //    }
         // }
         let this = if func_type != FunctionType::Function {
-            Token {
-                kind: Kind::Identifier,
-                lexeme: THIS_KEYWORD,
-                line: 0,
-            }
+            Token::synthetic(THIS_KEYWORD, 0)
         } else {
             Token::default()
         };
@@ -507,6 +744,8 @@ impl<'src> Compiler<'src> {
             is_captured: false,
         });
 
+        // parameters are locals of the function's outermost scope.
+        inner.begin_scope();
         // consume parameters
         if !inner.check(Kind::RightParen) {
             loop {
@@ -520,16 +759,21 @@ impl<'src> Compiler<'src> {
                 // we probably should also make is_const true at some point and force unique function names.
                 let constant = inner.parse_variable("Expect parameter name", false);
                 inner.define_variable(constant, false);
-                if !self.match_token(Kind::Comma) {
+                if !inner.match_token(Kind::Comma) {
                     break;
                 }
             }
         }
 
         inner.consume(Kind::RightParen, "Expect ')' after parameters.");
-        inner.begin_scope();
-        inner.consume(Kind::LeftBrace, "Expect '{' before function body.");
-        inner.block();
+        if arrow {
+            inner.consume(Kind::Arrow, "Expect '=>' after parameters.");
+            inner.expression();
+            inner.emit_opcode(OpCode::Return);
+        } else {
+            inner.consume(Kind::LeftBrace, "Expect '{' before function body.");
+            inner.block();
+        }
         // inner.end_scope(); unclear why we do not need to end scope
 
         // Enclosing compiler holds this closure and emits the bytes and operands
@@ -571,7 +815,17 @@ impl<'src> Compiler<'src> {
     }
 
     fn call(&mut self) {
+        let native = self.called_native.take();
         let arg_count = self.argument_list();
+        if let Some((name, arity)) = native
+            && !arity.accepts(arg_count)
+        {
+            let msg = format!(
+                "Expected {} arguments but got {} calling `{}`.",
+                arity, arg_count, name
+            );
+            self.parser.borrow_mut().error(&msg);
+        }
         self.emit_opcode_operand(OpCode::Call, arg_count);
     }
 
@@ -617,7 +871,7 @@ impl<'src> Compiler<'src> {
         self.named_variable(name_token, can_assign)
     }
 
-    fn named_variable(&mut self, name: Token, can_assign: bool) {
+    fn named_variable(&mut self, name: Token<'src>, can_assign: bool) {
         let (get_op, set_op, arg, is_const) = match self.resolve_local(&name) {
             Some((index, is_const)) => (OpCode::GetLocal, OpCode::SetLocal, index, is_const),
             None => match self.resolve_upvalue(&name) {
@@ -625,27 +879,44 @@ impl<'src> Compiler<'src> {
                 // for UpValue, it is the index in the upvalues array.
                 Some((idx, is_const)) => (OpCode::GetUpValue, OpCode::SetUpValue, idx, is_const),
                 _ => {
-                    // it's index in its chunk constants pool.
-                    let idx: usize = self.identifier_constant(name);
-                    let gl: Global = match self
+                    let declared: Option<Global> = self
                         .globals
-                        .borrow_mut()
+                        .borrow()
                         .iter()
                         .find(|&&decl| decl.name.lexeme == name.lexeme)
-                    {
-                        Some(g) => *g,
+                        .copied();
+                    let gl: Global = match declared {
+                        Some(g) => g,
+                        None if self.known_globals.contains_key(name.lexeme) => Global {
+                            name,
+                            is_const: self.known_globals[name.lexeme],
+                        },
+                        None if self.natives.get(name.lexeme).is_none()
+                            && name.lexeme.contains(MODULE_SEPARATOR) =>
+                        {
+                            return self.module_global(name, can_assign);
+                        }
                         None => {
-                            if !is_native_call(name.lexeme) {
-                                let msg = format!(
-                                    "Undeclared variable `{}` is being assigned to",
-                                    name.lexeme
-                                );
-                                self.parser.borrow_mut().error(&msg);
+                            let arity = self.natives.get(name.lexeme).map(|n| n.arity);
+                            match arity {
+                                Some(arity) if self.check(Kind::LeftParen) => {
+                                    self.called_native = Some((name.lexeme, arity));
+                                }
+                                Some(_) => (),
+                                None => {
+                                    let msg = format!(
+                                        "Undeclared variable `{}` is being assigned to",
+                                        name.lexeme
+                                    );
+                                    self.parser.borrow_mut().error(&msg);
+                                }
                             }
                             Global::default()
                         }
                     };
 
+                    // it's index in its chunk constants pool.
+                    let idx: usize = self.identifier_constant(name);
                     (OpCode::GetGlobal, OpCode::SetGlobal, idx, gl.is_const)
                 }
             },
@@ -665,6 +936,33 @@ impl<'src> Compiler<'src> {
         }
     }
 
+    /// `module::name` reads the global `name` of the module bound to `module`, like
+    /// `module.name` does.
+    fn module_global(&mut self, name: Token<'src>, can_assign: bool) {
+        let (module, global) = name.lexeme.split_once(MODULE_SEPARATOR).unwrap_or_default();
+        self.named_variable(
+            Token {
+                lexeme: module,
+                ..name
+            },
+            false,
+        );
+        let global = self.identifier_constant(Token {
+            lexeme: global,
+            ..name
+        });
+
+        if can_assign && self.match_token(Kind::Equal) {
+            let msg = format!(
+                "Can't assign to `{}`, module globals are read-only.",
+                name.lexeme
+            );
+            self.parser.borrow_mut().error(&msg);
+            return;
+        }
+        self.emit_opcode_operand(OpCode::GetProperty, global);
+    }
+
     fn resolve_local(&mut self, name: &Token) -> Option<(usize, bool)> {
         for (idx, local) in self.locals.iter().enumerate().rev() {
             if name.lexeme == local.name.lexeme {
@@ -768,9 +1066,17 @@ impl<'src> Compiler<'src> {
                 Kind::Class
                 | Kind::Fun
                 | Kind::Var
+                | Kind::Const
+                | Kind::For
                 | Kind::If
                 | Kind::While
                 | Kind::Print
+                | Kind::Break
+                | Kind::Continue
+                | Kind::Switch
+                | Kind::Try
+                | Kind::Throw
+                | Kind::Import
                 | Kind::Return => return,
                 _ => (),
             }
@@ -791,6 +1097,16 @@ impl<'src> Compiler<'src> {
             self.while_statement();
         } else if self.match_token(Kind::For) {
             self.for_statement();
+        } else if self.match_token(Kind::Switch) {
+            self.switch_statement();
+        } else if self.match_token(Kind::Break) {
+            self.break_statement();
+        } else if self.match_token(Kind::Continue) {
+            self.continue_statement();
+        } else if self.match_token(Kind::Try) {
+            self.try_statement();
+        } else if self.match_token(Kind::Throw) {
+            self.throw_statement();
         } else {
             self.expr_statement();
         }
@@ -799,6 +1115,7 @@ impl<'src> Compiler<'src> {
     fn for_statement(&mut self) {
         self.begin_scope();
         self.consume(Kind::LeftParen, "Expect '(' after 'for'.");
+        // the initializer clause consumes its own ';'
         if self.match_token(Kind::SemiColon) {
             // no initializer.
         } else if self.match_token(Kind::Var) {
@@ -807,7 +1124,6 @@ impl<'src> Compiler<'src> {
             self.expr_statement();
         }
 
-        self.consume(Kind::SemiColon, "Expect ';'.");
         let mut loop_start = self.count();
         let mut exit_jump: Option<usize> = None;
 
@@ -819,12 +1135,8 @@ impl<'src> Compiler<'src> {
             self.emit_opcode(OpCode::Pop);
         }
 
-        self.emit_loop(loop_start);
-        if let Some(jump) = exit_jump {
-            self.patch_jump(jump);
-            self.emit_opcode(OpCode::Pop);
-        }
-
+        // the increment clause is compiled before the body but runs after it:
+        // jump over it to the body, and loop back to the condition after the increment.
         if !self.match_token(Kind::RightParen) {
             let body_jump = self.emit_jump(OpCode::Jump);
             let increment_start = self.count();
@@ -836,7 +1148,16 @@ impl<'src> Compiler<'src> {
             loop_start = increment_start;
             self.patch_jump(body_jump);
         }
+
+        self.begin_loop(loop_start);
         self.statement();
+        self.emit_loop(loop_start);
+
+        if let Some(jump) = exit_jump {
+            self.patch_jump(jump);
+            self.emit_opcode(OpCode::Pop);
+        }
+        self.end_loop();
         self.end_scope();
     }
 
@@ -850,12 +1171,306 @@ impl<'src> Compiler<'src> {
         // while true { block } (jump here.)
         let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
         self.emit_opcode(OpCode::Pop); // pops the condition of the stack 
+        self.begin_loop(loop_start);
         self.statement();
         // backward loop after boy is executed.
         self.emit_loop(loop_start);
 
         self.patch_jump(exit_jump); // update correct location to goto.
         self.emit_opcode(OpCode::Pop);
+        self.end_loop();
+    }
+
+    fn begin_loop(&mut self, start: usize) {
+        self.loops.push(Loop {
+            start,
+            scope_depth: self.scope_depth,
+            breaks: vec![],
+            finally_blocks: self.finally_blocks.len(),
+        });
+    }
+
+    // breaks land after the loop's condition has been popped.
+    fn end_loop(&mut self) {
+        if let Some(enclosing) = self.loops.pop() {
+            for jump in enclosing.breaks {
+                self.patch_jump(jump);
+            }
+        }
+    }
+
+    fn break_statement(&mut self) {
+        let Some(innermost) = self.loops.len().checked_sub(1) else {
+            self.parser
+                .borrow_mut()
+                .error("Can't use 'break' outside of a loop.");
+            return;
+        };
+        self.consume(Kind::SemiColon, "Expect ';' after 'break'.");
+        self.exit(Exit::Break(innermost));
+    }
+
+    fn continue_statement(&mut self) {
+        let Some(innermost) = self.loops.len().checked_sub(1) else {
+            self.parser
+                .borrow_mut()
+                .error("Can't use 'continue' outside of a loop.");
+            return;
+        };
+        self.consume(Kind::SemiColon, "Expect ';' after 'continue'.");
+        self.exit(Exit::Continue(innermost));
+    }
+
+    /// emits the jump of a `return`, whose value is on top of the stack, a `break` or
+    /// a `continue`. An exit leaving a `try` or `catch` block jumps to the innermost
+    /// pending `finally` block instead, which carries on with the exit once it has run.
+    fn exit(&mut self, exit: Exit) {
+        let pending = match exit {
+            Exit::Return => 0,
+            Exit::Break(loop_index) | Exit::Continue(loop_index) => {
+                self.loops[loop_index].finally_blocks
+            }
+        };
+        if self.finally_blocks.len() > pending {
+            let finally = self.finally_blocks.last().unwrap();
+            let (value, action, depth) = (finally.value, finally.action, finally.scope_depth);
+            if exit == Exit::Return {
+                self.emit_opcode_operand(OpCode::SetLocal, value);
+                self.emit_opcode(OpCode::Pop);
+            }
+            self.discard_locals(depth);
+            let finally = self.finally_blocks.last_mut().unwrap();
+            let index = match finally.exits.iter().position(|(e, _)| *e == exit) {
+                Some(index) => index,
+                None => {
+                    finally.exits.push((exit, vec![]));
+                    finally.exits.len() - 1
+                }
+            };
+            self.emit_constant(Value::Int(index as i64 + 1));
+            self.emit_opcode_operand(OpCode::SetLocal, action);
+            self.emit_opcode(OpCode::Pop);
+            let jump = self.emit_jump(OpCode::Jump);
+            self.finally_blocks.last_mut().unwrap().exits[index]
+                .1
+                .push(jump);
+            return;
+        }
+
+        match exit {
+            Exit::Return => self.emit_opcode(OpCode::Return),
+            Exit::Break(loop_index) => {
+                self.discard_locals(self.loops[loop_index].scope_depth);
+                let jump = self.emit_jump(OpCode::Jump);
+                self.loops[loop_index].breaks.push(jump);
+            }
+            Exit::Continue(loop_index) => {
+                self.discard_locals(self.loops[loop_index].scope_depth);
+                self.emit_loop(self.loops[loop_index].start);
+            }
+        }
+    }
+
+    /// true if the `try` statement whose block starts at the current token ends with a
+    /// `finally` block, its exits have to be compiled before the `finally` block is seen.
+    fn try_has_finally(&self) -> bool {
+        let parser = self.parser.borrow();
+        if parser.current.kind != Kind::LeftBrace {
+            return false;
+        }
+        let mut tokens = std::iter::once(parser.current).chain(parser.tokens_ahead());
+        let mut depth = 0u32;
+        while let Some(token) = tokens.next() {
+            match token.kind {
+                Kind::LeftBrace => depth += 1,
+                Kind::RightBrace if depth > 1 => depth -= 1,
+                // the end of the `try` or `catch` block.
+                Kind::RightBrace if depth == 1 => match tokens.next().map(|t| t.kind) {
+                    Some(Kind::Catch) => depth = 0,
+                    Some(Kind::Finally) => return true,
+                    _ => return false,
+                },
+                Kind::RightBrace => return false,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    /// try { ... } catch (e) { ... } finally { ... }
+    /// An error in the try block is caught by the catch block, an error in either of
+    /// them runs the finally block and is thrown again. The finally block also runs
+    /// after the try (or catch) block ends, and before a `return`, `break` or `continue`
+    /// leaving them. Each exit is given an action number, see `exit`.
+    /// ```text
+    ///          Nil, Nil                                 <- value and action locals
+    ///          try block
+    ///          Jump -> finally
+    /// catch:   catch block, the error is its local      <- errors in the try block
+    ///          Jump -> finally
+    /// rethrow: SetLocal value, Pop                      <- errors in try or catch
+    ///          Constant 0, SetLocal action, Pop
+    /// finally: finally block                            <- exits jump here
+    ///          for each action: GetLocal action, Constant n, Equal, JumpIfFalse -> next, Pop
+    ///                           Throw the value, or carry on with the exit n
+    ///                  next:    Pop
+    ///          Pop, Pop
+    /// ```
+    fn try_statement(&mut self) {
+        let has_finally = self.try_has_finally();
+        self.begin_scope();
+        if has_finally {
+            let line = self.parser.borrow().previous.line;
+            for name in [FINALLY_VALUE, FINALLY_ACTION] {
+                self.emit_opcode(OpCode::NIL);
+                self.add_local(Token::synthetic(name, line), true);
+                self.mark_initialized(true);
+            }
+            self.finally_blocks.push(Finally {
+                value: self.locals.len() - 2,
+                action: self.locals.len() - 1,
+                scope_depth: self.scope_depth,
+                exits: vec![],
+            });
+        }
+        // errors are handled with only the locals declared before the `try` on the stack.
+        let depth = self.locals.len() as u32;
+        let start = self.count() as u32;
+
+        self.consume(Kind::LeftBrace, "Expect '{' after 'try'.");
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        let try_end = self.count() as u32;
+        let mut normal_jumps = vec![self.emit_jump(OpCode::Jump)];
+
+        let has_catch = self.match_token(Kind::Catch);
+        if has_catch {
+            let target = self.count() as u32;
+            self.current_chunk().handlers.push(Handler {
+                start,
+                end: try_end,
+                target,
+                depth,
+            });
+            self.consume(Kind::LeftParen, "Expect '(' after 'catch'.");
+            self.consume(Kind::Identifier, "Expect error variable name.");
+            let name = self.parser.borrow().previous;
+            self.consume(Kind::RightParen, "Expect ')' after error variable.");
+            self.consume(Kind::LeftBrace, "Expect '{' after catch clause.");
+            self.begin_scope();
+            // the vm pushed the error where the local lives.
+            self.add_local(name, false);
+            self.mark_initialized(false);
+            self.block();
+            self.end_scope();
+            normal_jumps.push(self.emit_jump(OpCode::Jump));
+        }
+        let protected_end = self.count() as u32;
+
+        let finally = if has_finally {
+            self.finally_blocks.pop()
+        } else {
+            None
+        };
+        let Some(finally) = finally else {
+            if !has_catch {
+                self.parser
+                    .borrow_mut()
+                    .error_at_current("Expect 'catch' or 'finally' after try block.");
+            }
+            for jump in normal_jumps {
+                self.patch_jump(jump);
+            }
+            self.end_scope();
+            return;
+        };
+        self.consume(Kind::Finally, "Expect 'finally' after catch block.");
+
+        let target = self.count() as u32;
+        self.current_chunk().handlers.push(Handler {
+            start,
+            end: protected_end,
+            target,
+            depth,
+        });
+        self.emit_opcode_operand(OpCode::SetLocal, finally.value);
+        self.emit_opcode(OpCode::Pop);
+        self.emit_constant(Value::Int(FINALLY_RETHROW));
+        self.emit_opcode_operand(OpCode::SetLocal, finally.action);
+        self.emit_opcode(OpCode::Pop);
+
+        let exit_jumps = finally.exits.iter().flat_map(|(_, jumps)| jumps);
+        for jump in normal_jumps.into_iter().chain(exit_jumps.copied()) {
+            self.patch_jump(jump);
+        }
+        self.consume(Kind::LeftBrace, "Expect '{' after 'finally'.");
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+
+        let actions = finally.exits.iter().map(|(exit, _)| Some(*exit));
+        for (number, exit) in std::iter::once(None).chain(actions).enumerate() {
+            self.emit_opcode_operand(OpCode::GetLocal, finally.action);
+            self.emit_constant(Value::Int(number as i64));
+            self.emit_opcode(OpCode::Equal);
+            let next = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_opcode(OpCode::Pop);
+            match exit {
+                None => {
+                    self.emit_opcode_operand(OpCode::GetLocal, finally.value);
+                    self.emit_opcode(OpCode::Throw);
+                }
+                Some(Exit::Return) => {
+                    self.emit_opcode_operand(OpCode::GetLocal, finally.value);
+                    self.exit(Exit::Return);
+                }
+                Some(exit) => self.exit(exit),
+            }
+            self.patch_jump(next);
+            self.emit_opcode(OpCode::Pop);
+        }
+        self.end_scope();
+    }
+
+    fn throw_statement(&mut self) {
+        self.expression();
+        self.consume(Kind::SemiColon, "Expect ';' after thrown value.");
+        self.emit_opcode(OpCode::Throw);
+    }
+
+    /// emits the code to discard the locals deeper than `depth` before jumping out of
+    /// their scope. Unlike `end_scope` the locals stay declared, since the rest of the
+    /// block after a `break` or `continue` is still compiled.
+    fn discard_locals(&mut self, depth: i32) {
+        let mut pending = 0u8;
+        for idx in (0..self.locals.len()).rev() {
+            if self.locals[idx].depth <= depth {
+                break;
+            }
+            if self.locals[idx].is_captured {
+                self.emit_pops(pending);
+                pending = 0;
+                self.emit_opcode(OpCode::CloseUpValue);
+            } else if pending == u8::MAX {
+                self.emit_pops(pending);
+                pending = 1;
+            } else {
+                pending += 1;
+            }
+        }
+        self.emit_pops(pending);
+    }
+
+    fn emit_pops(&mut self, n: u8) {
+        match n {
+            0 => (),
+            1 => self.emit_opcode(OpCode::Pop),
+            _ => {
+                self.emit_opcode(OpCode::PopN);
+                self.emit_byte(n);
+            }
+        }
     }
 
     fn emit_loop(&mut self, start: usize) {
@@ -894,6 +1509,211 @@ impl<'src> Compiler<'src> {
         self.patch_jump(else_jump);
     }
 
+    // switch (value) { case a: ... case b, c: ... default: ... }
+    // cases do not fall through, so `break` still refers to the enclosing loop.
+    fn switch_statement(&mut self) {
+        self.consume(Kind::LeftParen, "Expect '(' after 'switch'.");
+        self.expression();
+        self.consume(Kind::RightParen, "Expect ')' after switch value.");
+
+        // the scanner is just past the '{', so the lookahead sees the cases.
+        let labels = self.small_int_labels();
+        self.consume(Kind::LeftBrace, "Expect '{' before switch cases.");
+        match labels {
+            Some(labels) => self.switch_jump_table(&labels),
+            None => self.switch_comparisons(),
+        }
+        self.consume(Kind::RightBrace, "Expect '}' after switch cases.");
+    }
+
+    /// every case label of the switch body if they are all integer literals in 0..=255,
+    /// such switches dispatch with a single `OpCode::JumpTable`.
+    fn small_int_labels(&self) -> Option<Vec<u8>> {
+        let parser = self.parser.borrow();
+        let mut tokens = parser.tokens_ahead();
+        let mut labels = vec![];
+        let mut depth = 1;
+
+        while let Some(token) = tokens.next() {
+            match token.kind {
+                Kind::LeftBrace => depth += 1,
+                Kind::RightBrace if depth == 1 => break,
+                Kind::RightBrace => depth -= 1,
+                Kind::Case if depth == 1 => loop {
+                    let label = tokens.next()?;
+                    let value: f64 = label.lexeme.parse().ok()?;
+                    if label.kind != Kind::Number || value.fract() != 0.0 || value > 255.0 {
+                        return None;
+                    }
+                    labels.push(value as u8);
+                    match tokens.next()?.kind {
+                        Kind::Comma => (),
+                        Kind::Colon => break,
+                        _ => return None,
+                    }
+                },
+                _ => (),
+            }
+        }
+        if labels.is_empty() {
+            None
+        } else {
+            Some(labels)
+        }
+    }
+
+    fn switch_comparisons(&mut self) {
+        // the value stays on the stack as a hidden local, each label compares against it.
+        self.begin_scope();
+        let line = self.parser.borrow().previous.line;
+        self.add_local(Token::synthetic(SWITCH_VALUE, line), true);
+        self.mark_initialized(true);
+        let slot = self.locals.len() - 1;
+
+        let mut end_jumps = vec![];
+        let mut has_default = false;
+        loop {
+            if self.match_token(Kind::Case) {
+                if has_default {
+                    self.parser
+                        .borrow_mut()
+                        .error("Can't have a case after the default case.");
+                }
+                let mut body_jumps = vec![];
+                loop {
+                    self.emit_opcode_operand(OpCode::GetLocal, slot);
+                    self.expression();
+                    self.emit_opcode(OpCode::Equal);
+                    let next_label = self.emit_jump(OpCode::JumpIfFalse);
+                    self.emit_opcode(OpCode::Pop);
+                    body_jumps.push(self.emit_jump(OpCode::Jump));
+                    self.patch_jump(next_label);
+                    self.emit_opcode(OpCode::Pop);
+                    if !self.match_token(Kind::Comma) {
+                        break;
+                    }
+                }
+                self.consume(Kind::Colon, "Expect ':' after case value.");
+                let next_case = self.emit_jump(OpCode::Jump);
+
+                for jump in body_jumps {
+                    self.patch_jump(jump);
+                }
+                self.case_body();
+                end_jumps.push(self.emit_jump(OpCode::Jump));
+                self.patch_jump(next_case);
+            } else if self.match_token(Kind::Default) {
+                self.default_case(&mut has_default);
+            } else {
+                break;
+            }
+        }
+
+        for jump in end_jumps {
+            self.patch_jump(jump);
+        }
+        self.end_scope();
+    }
+
+    fn switch_jump_table(&mut self, labels: &[u8]) {
+        let low = labels.iter().copied().min().unwrap_or(0);
+        let high = labels.iter().copied().max().unwrap_or(0);
+        let entries = (high - low) as usize + 1;
+
+        self.emit_opcode(OpCode::JumpTable);
+        self.emit_bytes(low, high);
+        // the default offset followed by the entries, patched once the cases are compiled.
+        let table = self.count();
+        for _ in 0..=entries {
+            self.emit_bytes(0xFF, 0xFF);
+        }
+        let table_end = self.count();
+
+        let mut targets: Vec<Option<usize>> = vec![None; entries];
+        let mut default_target = None;
+        let mut end_jumps = vec![];
+        let mut has_default = false;
+        loop {
+            if self.match_token(Kind::Case) {
+                if has_default {
+                    self.parser
+                        .borrow_mut()
+                        .error("Can't have a case after the default case.");
+                }
+                let mut values = vec![];
+                loop {
+                    self.consume(Kind::Number, "Expect case value.");
+                    let lexeme = self.parser.borrow().previous.lexeme;
+                    values.push(lexeme.parse::<f64>().unwrap_or(0.0) as usize);
+                    if !self.match_token(Kind::Comma) {
+                        break;
+                    }
+                }
+                self.consume(Kind::Colon, "Expect ':' after case value.");
+
+                let target = self.count();
+                for value in values {
+                    let entry = &mut targets[value - low as usize];
+                    if entry.is_some() {
+                        self.parser
+                            .borrow_mut()
+                            .error("Duplicate case value in switch.");
+                    }
+                    *entry = Some(target);
+                }
+                self.case_body();
+                end_jumps.push(self.emit_jump(OpCode::Jump));
+            } else if self.match_token(Kind::Default) {
+                default_target = Some(self.count());
+                self.default_case(&mut has_default);
+            } else {
+                break;
+            }
+        }
+
+        for jump in end_jumps {
+            self.patch_jump(jump);
+        }
+        // values without a case and the default fall through to the end without a default case.
+        let default_target = default_target.unwrap_or(self.count());
+        let offsets = std::iter::once(default_target)
+            .chain(targets.iter().map(|t| t.unwrap_or(default_target)));
+        for (i, target) in offsets.enumerate() {
+            let jump = target - table_end;
+            if jump > u16::MAX as usize {
+                self.parser
+                    .borrow_mut()
+                    .error("Too much code to jump over.");
+            }
+            self.current_chunk().code[table + 2 * i] = (jump & 0xFF) as u8;
+            self.current_chunk().code[table + 2 * i + 1] = (jump >> 8) as u8;
+        }
+    }
+
+    fn default_case(&mut self, has_default: &mut bool) {
+        if *has_default {
+            self.parser
+                .borrow_mut()
+                .error("Switch can only have one default case.");
+        }
+        *has_default = true;
+        self.consume(Kind::Colon, "Expect ':' after 'default'.");
+        self.case_body();
+    }
+
+    // each case body is a scope of its own, it ends at the next case or the closing '}'.
+    fn case_body(&mut self) {
+        self.begin_scope();
+        while !self.check(Kind::Case)
+            && !self.check(Kind::Default)
+            && !self.check(Kind::RightBrace)
+            && !self.check(Kind::EOF)
+        {
+            self.declaration();
+        }
+        self.end_scope();
+    }
+
     // the 'lhs' of the expression has been compiled with its value on the stack.
     // if the value is false the entire and must be false and the 'rhs' is skipped.o
     // otherwise we discard the lhs and evaluate the rhs as the result of the whole and expression.
@@ -987,17 +1807,41 @@ impl<'src> Compiler<'src> {
         }
 
         self.consume(Kind::RightSqBracket, "Expect `]` after array values.");
-        self.emit_opcode(OpCode::Array);
-        // structure is [Array][(0|1)][(1byte|3bytes)]
-        if items > 255 {
+        self.emit_count(OpCode::Array, items);
+    }
+
+    /// `{key: value, ...}`, keys are arbitrary expressions evaluated before their value.
+    fn map(&mut self) {
+        let mut entries: usize = 0;
+        if !self.check(Kind::RightBrace) {
+            loop {
+                self.expression();
+                self.consume(Kind::Colon, "Expect ':' after map key.");
+                self.expression();
+                entries += 1;
+                if !self.match_token(Kind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(Kind::RightBrace, "Expect '}' after map entries.");
+        self.emit_count(OpCode::Map, entries);
+    }
+
+    /// collection literals carry their element count,
+    /// structure is [opcode][(0|1)][(1byte|3bytes)]
+    fn emit_count(&mut self, opcode: OpCode, count: usize) {
+        self.emit_opcode(opcode);
+        if count > 255 {
             self.emit_byte(LONG_ARG_INDEX);
-            let (bits0_7, bits8_15, bits16) = Chunk::resolve_index(items);
+            let (bits0_7, bits8_15, bits16) = Chunk::resolve_index(count);
             self.emit_byte(bits0_7);
             self.emit_byte(bits8_15);
             self.emit_byte(bits16);
         } else {
             self.emit_byte(SHORT_ARG_INDEX);
-            self.emit_byte(items as u8);
+            self.emit_byte(count as u8);
         }
     }
 
@@ -1017,7 +1861,12 @@ impl<'src> Compiler<'src> {
     fn expr_statement(&mut self) {
         self.expression();
         self.consume(Kind::SemiColon, "Expect ';' after expression.");
-        self.emit_opcode(OpCode::Pop);
+        if self.repl && self.scope_depth == 0 && self.check(Kind::EOF) {
+            // the repl shows the value of the entry's last expression.
+            self.emit_opcode(OpCode::Return);
+        } else {
+            self.emit_opcode(OpCode::Pop);
+        }
     }
 
     fn print_statement(&mut self) {
@@ -1026,20 +1875,44 @@ impl<'src> Compiler<'src> {
         self.emit_opcode(OpCode::Print);
     }
 
+    /// literals with a fractional part are `Number`s, all others are `Int`s.
     fn number(&mut self) {
-        let value: f64 = self.parser.borrow().previous.lexeme.parse::<f64>().unwrap();
-        self.emit_constant(Value::Number(value));
+        let lexeme = self.parser.borrow().previous.lexeme;
+        if lexeme.contains('.') {
+            self.emit_constant(Value::Number(lexeme.parse::<f64>().unwrap()));
+            return;
+        }
+        match lexeme.parse::<i64>() {
+            Ok(value) => self.emit_constant(Value::Int(value)),
+            Err(_) => self
+                .parser
+                .borrow_mut()
+                .error("Integer literal is too large."),
+        }
     }
 
     // grouping does not need to emit any byte code. its syntax to insert a
     // lower-precedence expression where a higher one is expected.
     fn grouping(&mut self) {
+        if self.arrow_ahead() {
+            self.arrow_function();
+            return;
+        }
         self.expression();
         self.consume(Kind::RightParen, "Expect ')' after expression.");
     }
 
     fn unary(&mut self) {
         let operator: Kind = self.parser.borrow().previous.kind;
+        // the literal of the smallest integer only fits once it is negated.
+        if operator == Kind::Minus && self.check(Kind::Number) {
+            let lexeme = self.parser.borrow().current.lexeme;
+            if lexeme.parse::<u64>() == Ok(i64::MIN.unsigned_abs()) {
+                self.parser.borrow_mut().advance();
+                self.emit_constant(Value::Int(i64::MIN));
+                return;
+            }
+        }
         // compile the operand
         self.parse_precedence(Precedence::Unary);
 
@@ -1067,6 +1940,7 @@ impl<'src> Compiler<'src> {
             Kind::Minus => self.emit_byte(OpCode::Subtract as u8),
             Kind::Star => self.emit_byte(OpCode::Multiply as u8),
             Kind::Slash => self.emit_byte(OpCode::Divide as u8),
+            Kind::Percent => self.emit_opcode(OpCode::Modulo),
             Kind::BangEquals => self.emit_opcodes(OpCode::Equal, OpCode::Not),
             Kind::EqualEquals => self.emit_opcode(OpCode::Equal),
             Kind::Greater => self.emit_opcode(OpCode::Greater),
@@ -1282,11 +2156,14 @@ impl ParseRule {
     }
 }
 
+// one rule per token kind.
+const RULE_COUNT: usize = Kind::EOF as usize + 1;
+
 // The Pratt Parser decides how much of the expression to consume when parsing the right-hand side (RHS)
 // of a binary operator.
-static RULES: [ParseRule; 40] = {
+static RULES: [ParseRule; RULE_COUNT] = {
     let default = ParseRule::default();
-    let mut rules = [default; 40];
+    let mut rules = [default; RULE_COUNT];
 
     rules[(Kind::Minus as u8) as usize] = ParseRule::new(
         |compiler, _| compiler.unary(),
@@ -1308,6 +2185,8 @@ static RULES: [ParseRule; 40] = {
         ParseRule::new_infix(|compiler, _| compiler.binary(), Precedence::Factor);
     rules[(Kind::Star as u8) as usize] =
         ParseRule::new_infix(|compiler, _| compiler.binary(), Precedence::Factor);
+    rules[(Kind::Percent as u8) as usize] =
+        ParseRule::new_infix(|compiler, _| compiler.binary(), Precedence::Factor);
     rules[(Kind::True as u8) as usize] =
         ParseRule::new_prefix(|compiler, _| compiler.literal(), Precedence::None);
     rules[(Kind::False as u8) as usize] =
@@ -1350,6 +2229,10 @@ static RULES: [ParseRule; 40] = {
         |compiler, _| compiler.call(),
         Precedence::Call,
     );
+    rules[(Kind::Fun as u8) as usize] =
+        ParseRule::new_prefix(|compiler, _| compiler.lambda(), Precedence::None);
+    rules[(Kind::LeftBrace as u8) as usize] =
+        ParseRule::new_prefix(|compiler, _| compiler.map(), Precedence::None);
     rules[(Kind::LeftSqBracket as u8) as usize] = ParseRule::new(
         |compiler, _| compiler.arrays(),
         |compiler, can_assign| compiler.array_access(can_assign),
@@ -0,0 +1,80 @@
+use std::fmt::{self, Display, Write};
+
+use crate::compile::token::{Kind, Span, Token};
+
+/// A compile error reported by the parser. The parser keeps going after
+/// `synchronize()` so a single compilation may produce several of these.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub line: u32,
+    pub column: u32,
+    pub span: Span,
+    // clox style description of where the error is, e.g ` at 'foo'` or ` at end`.
+    pub location: String,
+}
+
+impl Diagnostic {
+    pub fn at(token: &Token, message: &str) -> Self {
+        let location = match token.kind {
+            Kind::EOF => " at end".to_string(),
+            // the lexeme of an error token is the scanner's message, not source text.
+            Kind::Error => String::new(),
+            _ => format!(" at '{}'", token.lexeme),
+        };
+
+        Self {
+            message: message.to_string(),
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            location,
+        }
+    }
+
+    /// renders the diagnostic rustc-style, with the offending source line
+    /// and a caret underline below the span.
+    /// ```text
+    /// error: Expect ';' after value.
+    ///  --> 1:10
+    ///   |
+    /// 1 | print 1 +
+    ///   |          ^
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+
+        // tokens spanning several lines (strings) are only underlined on their first line.
+        let end = self.span.end.clamp(start, line_end);
+        let carets = (end - start).max(1);
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        let mut out = String::new();
+        let _ = writeln!(out, "error: {}", self.message);
+        let _ = writeln!(out, "{gutter}--> {}:{}", self.line, self.column);
+        let _ = writeln!(out, "{gutter} |");
+        let _ = writeln!(out, "{} | {}", self.line, text);
+        let _ = write!(
+            out,
+            "{gutter} | {}{}",
+            " ".repeat(start - line_start),
+            "^".repeat(carets)
+        );
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Error{}: {}",
+            self.line, self.location, self.message
+        )
+    }
+}
@@ -1,3 +1,4 @@
+use crate::compile::diagnostic::Diagnostic;
 use crate::compile::scanner::Scanner;
 use crate::compile::token::Kind;
 use crate::compile::token::Token;
@@ -15,6 +16,8 @@ pub struct Parser<'src> {
     pub previous: Token<'src>,
     pub had_error: bool,
     pub panic_mode: bool,
+    // every error reported, the first error of each statement only (see panic_mode).
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl<'src> Parser<'src> {
@@ -25,6 +28,7 @@ impl<'src> Parser<'src> {
             previous: Token::default(),
             had_error: false,
             panic_mode: false,
+            diagnostics: Vec::new(),
         }
     }
 
@@ -37,10 +41,23 @@ impl<'src> Parser<'src> {
             if self.current.kind != Kind::Error {
                 break;
             }
-            self.error_at_current("Scanner error.");
+            // the lexeme of an error token carries the scanner's message.
+            self.error_at_current(self.current.lexeme);
         }
     }
 
+    /// the tokens after `current` up to the end of the source, scanned without
+    /// moving the parser. Scanner errors are yielded as `Kind::Error` tokens.
+    pub fn tokens_ahead(&self) -> impl Iterator<Item = Token<'src>> + use<'src> {
+        let mut scanner = self.scanner.clone();
+        std::iter::from_fn(move || scanner.scan_token()).take_while(|t| t.kind != Kind::EOF)
+    }
+
+    /// kind of the token right after `current`.
+    pub fn peek_next(&self) -> Kind {
+        self.tokens_ahead().next().map_or(Kind::EOF, |t| t.kind)
+    }
+
     pub fn consume(&mut self, kind: Kind, msg: &'static str) {
         if self.current.kind == kind {
             if kind == Kind::EOF {
@@ -65,18 +82,12 @@ impl<'src> Parser<'src> {
         self.error_at(self.previous, message);
     }
 
-    fn error_at(&mut self, token: Token<'src>, message: &str) {
+    pub fn error_at(&mut self, token: Token<'src>, message: &str) {
         if self.panic_mode {
             return;
         }
         self.panic_mode = true;
-        eprint!("[line {}] Error", token.line);
-        match token.kind {
-            Kind::EOF => eprint!(" at the end"),
-            Kind::Error => todo!(),
-            _ => eprint!("  at  {}", token.lexeme),
-        }
-        eprintln!(" : {}", message);
+        self.diagnostics.push(Diagnostic::at(&token, message));
         self.had_error = true;
     }
 }
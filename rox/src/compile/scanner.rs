@@ -1,13 +1,27 @@
 use crate::compile::token::Kind;
+use crate::compile::token::Span;
 use crate::compile::token::Token;
 
+/// every reserved word of the language.
+pub const KEYWORDS: [&str; 27] = [
+    "and", "break", "case", "catch", "class", "const", "continue", "default", "else", "false",
+    "finally", "for", "fun", "if", "import", "nil", "or", "print", "return", "super", "switch",
+    "this", "throw", "true", "try", "var", "while",
+];
+pub const UNTERMINATED_STRING: &str = "Unterminated string found.";
+
 // a Scanner struct must not outlive the source string it points to.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Scanner<'src> {
     pub source: &'src str,
     start: usize,
     current: usize,
     line: u32,
+    // byte offset where the current line begins, used to compute columns.
+    line_start: usize,
+    // position of the token being scanned, a token may span several lines (strings).
+    start_line: u32,
+    start_column: u32,
 }
 
 impl<'src> Scanner<'src> {
@@ -17,12 +31,17 @@ impl<'src> Scanner<'src> {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
         }
     }
 
     pub fn scan_token(&mut self) -> Option<Token<'src>> {
         self.skip_whitespace();
         self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = (self.start - self.line_start + 1) as u32;
 
         if self.is_at_end() {
             return Some(self.make_token(Kind::EOF));
@@ -37,8 +56,10 @@ impl<'src> Scanner<'src> {
         if Self::is_alpha(ch) {
             return Some(self.identifier());
         }
-        
-        if ch == '/' && let Some('/') = self.peek() {
+
+        if ch == '/'
+            && let Some('/') = self.peek()
+        {
             self.skip_comment();
         }
 
@@ -50,11 +71,13 @@ impl<'src> Scanner<'src> {
             '[' => self.make_token(Kind::LeftSqBracket),
             ']' => self.make_token(Kind::RightSqBracket),
             ';' => self.make_token(Kind::SemiColon),
+            ':' => self.make_token(Kind::Colon),
             ',' => self.make_token(Kind::Comma),
             '.' => self.make_token(Kind::Dot),
             '-' => self.make_token(Kind::Minus),
             '+' => self.make_token(Kind::Plus),
             '*' => self.make_token(Kind::Star),
+            '%' => self.make_token(Kind::Percent),
             '/' => self.make_token(Kind::Slash),
             '!' => {
                 if self.match_next_char('=') {
@@ -66,6 +89,8 @@ impl<'src> Scanner<'src> {
             '=' => {
                 if self.match_next_char('=') {
                     self.make_token(Kind::EqualEquals)
+                } else if self.match_next_char('>') {
+                    self.make_token(Kind::Arrow)
                 } else {
                     self.make_token(Kind::Equal)
                 }
@@ -90,12 +115,18 @@ impl<'src> Scanner<'src> {
     }
 
     fn identifier(&mut self) -> Token<'src> {
-        while Self::is_alpha(self.peek().unwrap())
-            || self.peek().unwrap().is_ascii_digit()
-            || self.peek().unwrap() == ':'
-        {
-            // allow ::qualifier for native functions.
-            self.advance();
+        loop {
+            match self.peek() {
+                Some(ch) if Self::is_alpha(ch) || ch.is_ascii_digit() => {
+                    self.advance();
+                }
+                // allow ::qualifier for native functions, a single ':' ends the identifier.
+                Some(':') if self.peek_next() == Some(':') => {
+                    self.advance();
+                    self.advance();
+                }
+                _ => break,
+            }
         }
         let kind = self.identifier_type();
         self.make_token(kind)
@@ -112,16 +143,30 @@ impl<'src> Scanner<'src> {
             "and" => Kind::And,
             "class" => Kind::Class,
             "else" => Kind::Else,
+            "false" => Kind::False,
+            "for" => Kind::For,
             "if" => Kind::If,
             "nil" => Kind::Nil,
             "or" => Kind::Or,
             "print" => Kind::Print,
             "return" => Kind::Return,
             "super" => Kind::Super,
+            "this" => Kind::This,
+            "true" => Kind::True,
             "var" => Kind::Var,
             "while" => Kind::While,
             "const" => Kind::Const,
             "fun" => Kind::Fun,
+            "break" => Kind::Break,
+            "continue" => Kind::Continue,
+            "switch" => Kind::Switch,
+            "case" => Kind::Case,
+            "default" => Kind::Default,
+            "try" => Kind::Try,
+            "catch" => Kind::Catch,
+            "finally" => Kind::Finally,
+            "throw" => Kind::Throw,
+            "import" => Kind::Import,
             _ => Kind::Identifier,
         }
     }
@@ -136,8 +181,8 @@ impl<'src> Scanner<'src> {
         }
 
         if let Some('.') = self.peek() {
-            let nxt = self.source.as_bytes()[self.current + 1] as char;
-            if nxt.is_ascii_digit() {
+            let nxt = self.source.as_bytes().get(self.current + 1).copied();
+            if nxt.is_some_and(|b| b.is_ascii_digit()) {
                 self.advance(); // consume '.'
                 self.advance(); // consume 'nxt'
                 while let Some(ch) = self.peek() {
@@ -165,10 +210,7 @@ impl<'src> Scanner<'src> {
     fn skip_comment(&mut self) {
         loop {
             match self.peek() {
-                Some('\n') => {
-                    self.line += 1;
-                    break;
-                },
+                Some('\n') => break,
                 Some(_) => {
                     self.advance();
                 }
@@ -186,11 +228,11 @@ impl<'src> Scanner<'src> {
                 }
                 Some('\n') => {
                     let _ = self.advance();
-                    self.line += 1;
+                    self.newline();
                 }
                 Some('/') => {
                     if let Some('/') = self.source[self.current + 1..].chars().next() {
-                        while self.peek().unwrap() != '\n' && !self.is_at_end() {
+                        while self.peek().is_some_and(|ch| ch != '\n') {
                             let _ = self.advance();
                         }
                     } else {
@@ -202,15 +244,20 @@ impl<'src> Scanner<'src> {
         }
     }
 
+    // bookkeeping after consuming a '\n'
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn string(&mut self) -> Token<'src> {
-        while self.peek().unwrap() != '"' && !self.is_at_end() {
-            if self.peek().unwrap() == '\n' {
-                self.line += 1;
+        while self.peek().is_some_and(|ch| ch != '"') {
+            if self.advance() == '\n' {
+                self.newline();
             }
-            let _ = self.advance();
         }
         if self.is_at_end() {
-            return self.error_token("Unterminated string found.");
+            return self.error_token(UNTERMINATED_STRING);
         }
         // consume terminating '"'
         self.advance();
@@ -221,15 +268,19 @@ impl<'src> Scanner<'src> {
         self.source[self.current..].chars().next()
     }
 
+    fn peek_next(&self) -> Option<char> {
+        self.source[self.current..].chars().nth(1)
+    }
+
     // NOTE: On why using the Iterator to get the next char is efficient
     // Rust does not allocate: Tiny Struct Chars { ptr ,end } (2 pointers on the stack)
     // Cost per call: no heap allcoation, no copying, just a few instructions,
     fn advance(&mut self) -> char {
-        // Cleaner and faster is using byte scanning, Lox only uses ascii
-        // let current = self.source[self.current..].chars().next();
-        let b = self.source.as_bytes()[self.current];
-        self.current += 1;
-        b as char
+        // Lox syntax is ascii, but strings and comments may hold any utf-8
+        // so we step over whole chars.
+        let ch = self.peek().unwrap_or('\0');
+        self.current += ch.len_utf8();
+        ch
     }
 
     fn is_at_end(&self) -> bool {
@@ -240,7 +291,16 @@ impl<'src> Scanner<'src> {
         Token {
             kind,
             lexeme: &self.source[self.start..self.current],
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span(),
+        }
+    }
+
+    fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.current,
         }
     }
 
@@ -253,7 +313,9 @@ impl<'src> Scanner<'src> {
         Token {
             kind: Kind::Error,
             lexeme: msg,
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span(),
         }
     }
 }
@@ -1,16 +1,31 @@
+/// byte offsets of a token in the source string, `end` is exclusive.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Token<'src> {
     pub kind: Kind,
     pub lexeme: &'src str,
     pub line: u32,
+    // 1-based byte column of the first character of the token.
+    pub column: u32,
+    // for error tokens the lexeme is the error message, the span still
+    // points at the offending source.
+    pub span: Span,
 }
 
 impl Token<'static> {
+    /// tokens the compiler makes up (e.g `this`, `super`) do not exist in the source.
     pub const fn synthetic(lexeme: &'static str, line: u32) -> Self {
         Self {
             kind: Kind::Identifier,
             lexeme,
             line,
+            column: 0,
+            span: Span { start: 0, end: 0 },
         }
     }
 }
@@ -21,6 +36,8 @@ impl Default for Token<'static> {
             kind: Kind::False,
             lexeme: "",
             line: u32::MAX,
+            column: 0,
+            span: Span::default(),
         }
     }
 }
@@ -40,13 +57,16 @@ pub enum Kind {
     Minus,
     Plus,
     SemiColon,
+    Colon,
     Slash,
     Star,
+    Percent,
     // 1 or 2 character tokens
     Bang,
     BangEquals,
     Equal,
     EqualEquals,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
@@ -73,6 +93,16 @@ pub enum Kind {
     Var,
     While,
     Const,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Import,
 
     Error,
     EOF,
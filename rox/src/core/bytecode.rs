@@ -0,0 +1,239 @@
+use std::rc::Rc;
+
+use crate::{
+    core::{
+        chunk::{Chunk, ColumnTable, Handler, LineRun},
+        value::Value,
+    },
+    data_structures::interner,
+    runtime::lang::Function,
+    std::lox_errors::BytecodeError,
+};
+
+/// every `.roxc` file starts with these bytes followed by the format version.
+pub const MAGIC: &[u8; 4] = b"ROXC";
+/// bump whenever the layout below or the instruction set changes.
+pub const FORMAT_VERSION: u16 = 8;
+
+// tags of serialized constants.
+const TAG_NIL: u8 = 0;
+const TAG_BOOLEAN: u8 = 1;
+const TAG_NUMBER: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_FUNCTION: u8 = 4;
+const TAG_INT: u8 = 5;
+
+// Layout, all integers are little-endian:
+// file     := MAGIC version:u16 chunk
+// chunk    := code:bytes lines:(count:u32 run*) columns:bytes
+//             handlers:(count:u32 handler*) constants:(count:u32 constant*)
+// run      := start:u32 line:u32
+// columns are stored as `ColumnTable` encodes them.
+// handler  := start:u32 end:u32 target:u32 depth:u32
+// constant := tag:u8 payload
+// function := arity:u8 (0 | 1 name:string) upvalue_count:u32 chunk
+// bytes    := len:u32 byte*, strings are bytes holding utf-8.
+impl Chunk {
+    /// encodes the chunk of a compiled script, nested functions are stored inline
+    /// in the constants pool and interned strings by their contents.
+    pub fn serialize(&self) -> Result<Vec<u8>, BytecodeError> {
+        let mut out = Vec::with_capacity(self.code.len() * 2);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        write_chunk(&mut out, self)?;
+        Ok(out)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Chunk, BytecodeError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(BytecodeError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+
+        let chunk = reader.chunk()?;
+        if reader.pos != bytes.len() {
+            return Err(BytecodeError::TrailingBytes(bytes.len() - reader.pos));
+        }
+        Ok(chunk)
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) -> Result<(), BytecodeError> {
+    write_bytes(out, &chunk.code);
+
+    out.extend_from_slice(&(chunk.lines.len() as u32).to_le_bytes());
+    for run in &chunk.lines {
+        out.extend_from_slice(&run.start.to_le_bytes());
+        out.extend_from_slice(&run.line.to_le_bytes());
+    }
+    write_bytes(out, chunk.columns.as_bytes());
+
+    out.extend_from_slice(&(chunk.handlers.len() as u32).to_le_bytes());
+    for handler in &chunk.handlers {
+        out.extend_from_slice(&handler.start.to_le_bytes());
+        out.extend_from_slice(&handler.end.to_le_bytes());
+        out.extend_from_slice(&handler.target.to_le_bytes());
+        out.extend_from_slice(&handler.depth.to_le_bytes());
+    }
+
+    out.extend_from_slice(&(chunk.constants.len() as u32).to_le_bytes());
+    for constant in &chunk.constants {
+        write_constant(out, constant)?;
+    }
+    Ok(())
+}
+
+fn write_constant(out: &mut Vec<u8>, constant: &Value) -> Result<(), BytecodeError> {
+    match constant {
+        Value::Nil => out.push(TAG_NIL),
+        Value::Boolean(b) => out.extend_from_slice(&[TAG_BOOLEAN, *b as u8]),
+        Value::Number(n) => {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&n.to_bits().to_le_bytes());
+        }
+        Value::Int(i) => {
+            out.push(TAG_INT);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::String(symbol) => {
+            out.push(TAG_STRING);
+            let string = interner::get_string(*symbol).unwrap_or_default();
+            write_bytes(out, string.as_bytes());
+        }
+        Value::LoxFunction(function) => {
+            out.push(TAG_FUNCTION);
+            out.push(function.arity);
+            match &function.name {
+                Some(name) => {
+                    out.push(1);
+                    write_bytes(out, name.as_bytes());
+                }
+                None => out.push(0),
+            }
+            out.extend_from_slice(&(function.upvalue_count as u32).to_le_bytes());
+            write_chunk(out, &function.chunk)?;
+        }
+        // runtime values never end up in a compiled chunk.
+        other => return Err(BytecodeError::UnsupportedConstant(format!("{:?}", other))),
+    }
+    Ok(())
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(BytecodeError::UnexpectedEof)?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], BytecodeError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<&'a str, BytecodeError> {
+        std::str::from_utf8(self.bytes()?).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, BytecodeError> {
+        let code = self.bytes()?.to_vec();
+
+        let run_count = self.u32()? as usize;
+        // counts come from the file, don't trust them for preallocation.
+        let mut lines = Vec::with_capacity(run_count.min(code.len()));
+        for _ in 0..run_count {
+            lines.push(LineRun {
+                start: self.u32()?,
+                line: self.u32()?,
+            });
+        }
+        let columns =
+            ColumnTable::from_bytes(self.bytes()?).ok_or(BytecodeError::BadColumnTable)?;
+
+        let handler_count = self.u32()? as usize;
+        let mut handlers = Vec::with_capacity(handler_count.min(code.len()));
+        for _ in 0..handler_count {
+            handlers.push(Handler {
+                start: self.u32()?,
+                end: self.u32()?,
+                target: self.u32()?,
+                depth: self.u32()?,
+            });
+        }
+
+        let constant_count = self.u32()?;
+        let mut constants = vec![];
+        for _ in 0..constant_count {
+            constants.push(self.constant()?);
+        }
+
+        Ok(Chunk {
+            code,
+            constants,
+            lines,
+            columns,
+            handlers,
+        })
+    }
+
+    fn constant(&mut self) -> Result<Value, BytecodeError> {
+        match self.u8()? {
+            TAG_NIL => Ok(Value::Nil),
+            TAG_BOOLEAN => Ok(Value::Boolean(self.u8()? != 0)),
+            TAG_NUMBER => Ok(Value::Number(f64::from_bits(self.u64()?))),
+            TAG_INT => Ok(Value::Int(self.u64()? as i64)),
+            TAG_STRING => Ok(Value::String(interner::intern(self.string()?))),
+            TAG_FUNCTION => {
+                let arity = self.u8()?;
+                let name = match self.u8()? {
+                    0 => None,
+                    _ => Some(self.string()?.to_owned()),
+                };
+                let upvalue_count = self.u32()? as usize;
+                let chunk = self.chunk()?;
+                Ok(Value::LoxFunction(Rc::new(Function {
+                    arity,
+                    chunk,
+                    name,
+                    upvalue_count,
+                })))
+            }
+            tag => Err(BytecodeError::UnknownConstant(tag)),
+        }
+    }
+}
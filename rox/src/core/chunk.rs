@@ -1,8 +1,123 @@
 use std::fmt::Display;
 
 use crate::{core::opcode::*, core::value::Value, data_structures::interner};
+/// source line shared by a run of consecutive bytes in `Chunk::code`,
+/// the run lasts until the `start` of the next one.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
-pub struct Line(pub u32);
+pub struct LineRun {
+    pub start: u32,
+    pub line: u32,
+}
+
+/// the column of every byte in `Chunk::code`. Columns change with almost every
+/// token, so rather than splitting the line runs they are kept as a stream of
+/// changes, each a varint of the bytes since the previous change followed by the
+/// zigzag varint of the column delta. A column of 0 is unknown.
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd, Eq)]
+pub struct ColumnTable {
+    bytes: Vec<u8>,
+    // where the last change happened, the next one is encoded relative to it.
+    last_offset: u32,
+    last_column: u32,
+}
+
+impl ColumnTable {
+    pub const fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            last_offset: 0,
+            last_column: 0,
+        }
+    }
+
+    /// decodes a table written by `as_bytes`, None if it is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut table = Self {
+            bytes: bytes.to_vec(),
+            ..Self::new()
+        };
+        let mut pos = 0;
+        while pos < bytes.len() {
+            (table.last_offset, table.last_column) =
+                next_change(bytes, &mut pos, table.last_offset, table.last_column)?;
+        }
+        Some(table)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// the byte at `offset`, which must be past every byte recorded so far,
+    /// and the ones after it are at `column`.
+    fn record(&mut self, offset: u32, column: u32) {
+        if column == self.last_column {
+            return;
+        }
+        let delta = column as i64 - self.last_column as i64;
+        write_varint(&mut self.bytes, (offset - self.last_offset) as u64);
+        write_varint(&mut self.bytes, ((delta << 1) ^ (delta >> 63)) as u64);
+        self.last_offset = offset;
+        self.last_column = column;
+    }
+
+    pub fn column_for_offset(&self, offset: usize) -> u32 {
+        let (mut pos, mut at, mut column) = (0, 0, 0);
+        while let Some((next_at, next_column)) = next_change(&self.bytes, &mut pos, at, column) {
+            if next_at as usize > offset {
+                break;
+            }
+            (at, column) = (next_at, next_column);
+        }
+        column
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.bytes.shrink_to_fit();
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// the (offset, column) of the change encoded at `pos`, relative to the previous one.
+fn next_change(bytes: &[u8], pos: &mut usize, offset: u32, column: u32) -> Option<(u32, u32)> {
+    let offset_delta = read_varint(bytes, pos)?;
+    let zigzag = read_varint(bytes, pos)?;
+    let column_delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+    let offset = u32::try_from(offset as u64 + offset_delta).ok()?;
+    let column = u32::try_from(column as i64 + column_delta).ok()?;
+    Some((offset, column))
+}
+
+/// the handler of a `try` region. An error raised by the instructions in
+/// `start..end` truncates the frame's stack to its first `depth` slots, pushes
+/// the error and continues at `target`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
+pub struct Handler {
+    pub start: u32,
+    pub end: u32,
+    pub target: u32,
+    pub depth: u32,
+}
 
 // CHALLENGE: to generate a minimal instruction set eliminating
 // either OP_NEGATE or OP_SUBSTRACT: 4 - 3 * -2
@@ -13,21 +128,16 @@ pub struct Line(pub u32);
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
-    pub lines: Vec<Line>,
-    // HACK: index_const24 records the size of the bytecode array when the constants pool
-    // exceeds 255 (the value at which Constant24 must be used as the operand to store and read constants.)
-    // this allows the compiler & vm to compare the instruction ptr with this size
-    // if the ip is >= index_const24 we have to read the next 3 bytes to get the correct index.
-    pub index_const24: usize,
+    // run-length encoded, a line usually compiles to many bytes.
+    pub lines: Vec<LineRun>,
+    pub columns: ColumnTable,
+    // innermost `try` first, the first handler covering an instruction catches its errors.
+    pub handlers: Vec<Handler>,
 }
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(
-            f,
-            "\nindex_const24 = {} \n {:?}\n {:?}",
-            self.index_const24, self.code, self.constants
-        )
+        writeln!(f, "\n {:?}\n {:?}", self.code, self.constants)
     }
 }
 
@@ -43,45 +153,102 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             lines: Vec::new(),
-            index_const24: usize::MAX, // Sentinel.
+            columns: ColumnTable::new(),
+            handlers: Vec::new(),
         }
     }
 
-    pub fn save_index(&mut self) {
-        self.index_const24 = self.code.len()
-    }
-
     // write allows user to write both opcodes and their operands
     // write chunk only takes in opcodes
     pub fn write(&mut self, byte: u8, line: u32) {
+        self.write_at(byte, line, 0);
+    }
+
+    /// like `write` but also records the column of the token the byte was compiled from.
+    pub fn write_at(&mut self, byte: u8, line: u32, column: u32) {
+        let offset = self.code.len() as u32;
+        if self.lines.last().is_none_or(|run| run.line != line) {
+            self.lines.push(LineRun {
+                start: offset,
+                line,
+            });
+        }
+        self.columns.record(offset, column);
         self.code.push(byte);
-        self.lines.push(Line(line));
+    }
+
+    /// source line of the byte at `offset`, 0 if the chunk has no line for it.
+    pub fn line_for_offset(&self, offset: usize) -> u32 {
+        let index = self
+            .lines
+            .partition_point(|run| run.start as usize <= offset);
+        index.checked_sub(1).map_or(0, |i| self.lines[i].line)
+    }
+
+    /// (line, column) of the byte at `offset`.
+    pub fn position_for_offset(&self, offset: usize) -> (u32, u32) {
+        match self.line_for_offset(offset) {
+            0 => (0, 0),
+            line => (line, self.columns.column_for_offset(offset)),
+        }
+    }
+
+    /// the handler catching errors raised by the instruction at `offset`.
+    pub fn handler_for(&self, offset: usize) -> Option<&Handler> {
+        self.handlers
+            .iter()
+            .find(|h| (h.start as usize..h.end as usize).contains(&offset))
     }
 
     pub fn write_chunk(&mut self, op_code: OpCode, line: u32) {
         self.write(op_code as u8, line);
     }
 
-    #[cfg(feature = "")]
+    /// prints the disassembly of `chunk` to stdout.
     pub fn disassemble(chunk: &Chunk, name: &str) {
-        println!("====={name}=====");
+        print!("{}", Self::disassembly(chunk, name));
+    }
+
+    /// the annotated bytecode of `chunk`, one instruction per line.
+    pub fn disassembly(chunk: &Chunk, name: &str) -> String {
+        let mut out = format!("====={name}=====\n");
         let mut i = 0usize;
 
         while i < chunk.code.len() {
-            i = Self::disassemble_instruction(chunk, i);
+            i = Self::disassemble_instruction(chunk, i, &mut out);
         }
+        for handler in &chunk.handlers {
+            out.push_str(&format!(
+                "try {:04}..{:04} -> {:04} (depth {})\n",
+                handler.start, handler.end, handler.target, handler.depth
+            ));
+        }
+        out
     }
 
-    pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> usize {
-        print!("{:04} ", offset);
-        let line = chunk.lines[offset].0;
+    /// appends the instruction at `offset` to `out` and returns the offset of the next one.
+    pub fn disassemble_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+        out.push_str(&format!("{:04} ", offset));
+        let line = chunk.line_for_offset(offset);
 
-        if offset > 0 && line == chunk.lines[offset - 1].0 {
-            print!("    | ");
+        if offset > 0 && line == chunk.line_for_offset(offset - 1) {
+            out.push_str("    | ");
         } else {
-            print!("{:4} ", line);
+            out.push_str(&format!("{:4} ", line));
         }
 
+        // the instruction after a `Wide` prefix has a 3 byte index operand.
+        match OpCode::try_from(chunk.code[offset]) {
+            Ok(OpCode::Wide) => {
+                out.push_str("WIDE");
+                chunk.disassemble_operation(offset + 1, true, out)
+            }
+            _ => chunk.disassemble_operation(offset, false, out),
+        }
+    }
+
+    fn disassemble_operation(&self, offset: usize, wide: bool, out: &mut String) -> usize {
+        let chunk = self;
         let instruction = chunk.code[offset];
         let op = OpCode::try_from(instruction).expect("instruction not understood");
 
@@ -91,190 +258,200 @@ impl Chunk {
         // to get the (operand) index of the value, we may need to look at index1, index2, index3
         match op {
             OpCode::Return => {
-                println!(" RETURN");
+                out.push_str(" RETURN\n");
                 offset + 1
             }
-            OpCode::ArrayGetItem => Self::simple_instruction("OP_ARRAY_ACCESS", offset),
-            OpCode::ArraySetItem => todo!(),
-            OpCode::Class => chunk.constant_instruction("OP_CLASS", offset),
-            OpCode::GetProperty => chunk.constant_instruction("OP_GET_PROPERTY", offset),
-            OpCode::SetProperty => chunk.constant_instruction("OP_SET_PROPERTY", offset),
-            OpCode::Method => chunk.constant_instruction("OP_METHOD", offset),
+            OpCode::Wide => Self::simple_instruction("OP_WIDE", offset, out),
+            OpCode::ArrayGetItem => Self::simple_instruction("OP_ARRAY_ACCESS", offset, out),
+            OpCode::ArraySetItem => Self::simple_instruction("OP_ARRAY_SET", offset, out),
+            OpCode::Class => chunk.constant_instruction("OP_CLASS", offset, wide, out),
+            OpCode::GetProperty => chunk.constant_instruction("OP_GET_PROPERTY", offset, wide, out),
+            OpCode::SetProperty => chunk.constant_instruction("OP_SET_PROPERTY", offset, wide, out),
+            OpCode::Method => chunk.constant_instruction("OP_METHOD", offset, wide, out),
             OpCode::Array => {
-                let index = chunk.code[offset + 1];
-                println!("OP_Array initialized with {index} values");
-                offset + 2
+                // [Array][LONG_ARG_INDEX | SHORT_ARG_INDEX][1b | 3b item count]
+                let (items, width) = chunk.index_operand(offset + 1, chunk.code[offset + 1] == 1);
+                out.push_str(&format!("OP_Array initialized with {items} values\n"));
+                offset + 2 + width
+            }
+            OpCode::Map => {
+                let (entries, width) = chunk.index_operand(offset + 1, chunk.code[offset + 1] == 1);
+                out.push_str(&format!("OP_Map initialized with {entries} entries\n"));
+                offset + 2 + width
             }
-            OpCode::Constant => {
-                let index = chunk.read_constant(offset);
-                println!("  OP_CONSTANT\t{}\t{}", index, chunk.constants[index]);
-                offset + 2
+            OpCode::Constant | OpCode::Constant24 => {
+                let (index, width) =
+                    chunk.index_operand(offset, wide || matches!(op, OpCode::Constant24));
+                out.push_str(&format!(
+                    "  {:?}\t{}\t{}\n",
+                    op, index, chunk.constants[index]
+                ));
+                offset + 1 + width
             }
-            OpCode::Constant24 => {
-                // 24 bit operand.
-                let index = chunk.read_long_constant(offset);
-                let constant = &chunk.constants[index];
-                println!("  OP_CONSTANT_LONG\t{}\t{constant}", index);
-                offset + 4 // consume op_code_long, byte, byte, byte 
-            } // _ => panic!()
-            OpCode::Negate | OpCode::Add | OpCode::Divide | OpCode::Multiply | OpCode::Subtract => {
+            OpCode::Negate
+            | OpCode::Add
+            | OpCode::Divide
+            | OpCode::Multiply
+            | OpCode::Subtract
+            | OpCode::Modulo => {
                 // It is impossible to know what value is being negated at disassembly time.
                 // e.g OP_CONSTANT 1, OP_CONSTANT_LONG 2, OP_ADD, OP_NEGATE
                 // how do we know what expression the sign is being applied onto.
-                println!("  OP_{:?}", op);
+                out.push_str(&format!("  OP_{:?}\n", op));
                 offset + 1
             }
-            OpCode::True => Self::simple_instruction("OP_TRUE", offset),
-            OpCode::False => Self::simple_instruction("OP_FALSE", offset),
-            OpCode::NIL => Self::simple_instruction("OP_NIL", offset),
-            OpCode::Not => Self::simple_instruction("OP_NOT", offset),
-            OpCode::Equal => Self::simple_instruction("OP_EQUAL", offset),
-            OpCode::Greater => Self::simple_instruction("OP_GREATER", offset),
-            OpCode::Less => Self::simple_instruction("OP_LESS", offset),
-            OpCode::Print => Self::simple_instruction("OP_PRINT", offset),
-            OpCode::Pop => Self::simple_instruction("OP_POP", offset),
-            OpCode::DefineGlobal => chunk.constant_instruction("OP_DEFINE_GLOBAL", offset),
-            OpCode::GetGlobal => chunk.constant_instruction("OP_GET_GLOBAL", offset),
-            OpCode::SetGlobal => chunk.constant_instruction("OP_SET_GLOBAL", offset),
-            OpCode::PopN => chunk.constant_instruction("OP_POP_N", offset),
-            OpCode::GetLocal => {
-                let slot = chunk.code[offset + 1];
-                // -1 because operand to this opcode is the index in its local stack
-                // which is offset by 1 because there is always a dummy local.
-                println!("OP_GET_LOCAL {:04}", slot);
-                offset + 2
+            OpCode::True => Self::simple_instruction("OP_TRUE", offset, out),
+            OpCode::False => Self::simple_instruction("OP_FALSE", offset, out),
+            OpCode::NIL => Self::simple_instruction("OP_NIL", offset, out),
+            OpCode::Not => Self::simple_instruction("OP_NOT", offset, out),
+            OpCode::Equal => Self::simple_instruction("OP_EQUAL", offset, out),
+            OpCode::Greater => Self::simple_instruction("OP_GREATER", offset, out),
+            OpCode::Less => Self::simple_instruction("OP_LESS", offset, out),
+            OpCode::Print => Self::simple_instruction("OP_PRINT", offset, out),
+            OpCode::Pop => Self::simple_instruction("OP_POP", offset, out),
+            OpCode::DefineGlobal => {
+                chunk.constant_instruction("OP_DEFINE_GLOBAL", offset, wide, out)
             }
-            OpCode::SetLocal => chunk.byte_instruction("OP_SET_LOCAL", offset, false),
-            OpCode::JumpIfFalse => chunk.jump_instruction("OP_JUMP_IF_FALSE", 1, offset),
-            OpCode::Jump => chunk.jump_instruction("OP_JUMP", 1, offset),
-            OpCode::Loop => chunk.jump_instruction("OP_LOOP", -1, offset),
+            OpCode::GetGlobal => chunk.constant_instruction("OP_GET_GLOBAL", offset, wide, out),
+            OpCode::SetGlobal => chunk.constant_instruction("OP_SET_GLOBAL", offset, wide, out),
+            OpCode::PopN => chunk.byte_instruction("OP_POP_N", offset, false, out),
+            OpCode::GetLocal => chunk.byte_instruction("OP_GET_LOCAL", offset, wide, out),
+            OpCode::SetLocal => chunk.byte_instruction("OP_SET_LOCAL", offset, wide, out),
+            OpCode::JumpIfFalse => chunk.jump_instruction("OP_JUMP_IF_FALSE", 1, offset, out),
+            OpCode::Jump => chunk.jump_instruction("OP_JUMP", 1, offset, out),
+            OpCode::Loop => chunk.jump_instruction("OP_LOOP", -1, offset, out),
+            OpCode::JumpTable => chunk.jump_table_instruction(offset, out),
             // arity is a byte instruction, because arguments are limited to =255
-            OpCode::Call => chunk.byte_instruction("OP_CALL: arity = ", offset, false),
+            OpCode::Call => chunk.byte_instruction("OP_CALL: arity = ", offset, false, out),
             OpCode::Closure => {
-                let (mut off_t, constant) = if offset < chunk.index_const24 {
-                    (offset + 2, chunk.code[offset + 1] as usize)
-                } else {
-                    let bytes = &chunk.code[offset + 1..offset + 4];
-                    let index = Self::inverse_resolve(bytes[0], bytes[1], bytes[2]);
-                    (offset + 4, index)
-                };
-                print!("OP_CLOSURE {:04}", constant);
+                let (constant, width) = chunk.index_operand(offset, wide);
+                let mut off_t = offset + 1 + width;
                 let function = Value::as_function(&chunk.constants[constant]);
+                out.push_str(&format!(
+                    "   OP_CLOSURE\t{}\t{}\n",
+                    constant, chunk.constants[constant]
+                ));
                 for _ in 0..function.upvalue_count {
                     // encoding [is_long][idx_1b or idx_3b][is_local]
                     // is_long ? idx_3b : idx_1b (3b = 3bytes. upvalue may point to slot > 255.)
-                    let is_long = chunk.code[off_t];
-                    off_t += 1;
-
-                    let index: usize = if is_long == 1 {
-                        let index = Self::inverse_resolve(
-                            chunk.code[off_t],
-                            chunk.code[off_t + 1],
-                            chunk.code[off_t + 2],
-                        );
-                        off_t += 3;
-                        index
-                    } else {
-                        let index = chunk.code[off_t] as usize;
-                        off_t += 1;
-                        index
-                    };
+                    let start = off_t;
+                    let (index, width) = chunk.index_operand(off_t, chunk.code[off_t] == 1);
+                    off_t += 1 + width;
 
                     let is_local = chunk.code[off_t];
                     off_t += 1;
 
-                    println!(
-                        "{:04}    |              {} {}",
-                        off_t - 2,
+                    out.push_str(&format!(
+                        "{:04}     |              {} {}\n",
+                        start,
                         if is_local == 1 { " local" } else { "upvalue" },
                         index
-                    );
+                    ));
                 }
                 off_t
             }
-            OpCode::GetUpValue => chunk.byte_instruction("OP_GET_UPVALUE", offset, false), // operand is code pool
-            OpCode::SetUpValue => chunk.byte_instruction("OP_SET_UPVALUE", offset, false), // also here
-            OpCode::CloseUpValue => Self::simple_instruction("OP_CLOSE_VALUE", offset),
-            OpCode::Invoke => chunk.invoke_instruction("OP_INVOKE", offset),
-            OpCode::Inherit => Self::simple_instruction("OP_INHERIT", offset),
-            OpCode::GetSuper => chunk.constant_instruction("OP_GET_SUPER", offset),
-            OpCode::SuperInvoke => chunk.invoke_instruction("OP_SUPER_INVOKE", offset),
+            OpCode::GetUpValue => chunk.byte_instruction("OP_GET_UPVALUE", offset, wide, out), // operand is code pool
+            OpCode::SetUpValue => chunk.byte_instruction("OP_SET_UPVALUE", offset, wide, out), // also here
+            OpCode::CloseUpValue => Self::simple_instruction("OP_CLOSE_VALUE", offset, out),
+            OpCode::Invoke => chunk.invoke_instruction("OP_INVOKE", offset, wide, out),
+            OpCode::Inherit => Self::simple_instruction("OP_INHERIT", offset, out),
+            OpCode::GetSuper => chunk.constant_instruction("OP_GET_SUPER", offset, wide, out),
+            OpCode::SuperInvoke => chunk.invoke_instruction("OP_SUPER_INVOKE", offset, wide, out),
+            OpCode::Throw => Self::simple_instruction("OP_THROW", offset, out),
+            OpCode::Import => chunk.constant_instruction("OP_IMPORT", offset, wide, out),
         }
     }
 
-    fn simple_instruction(name: &str, offset: usize) -> usize {
-        println!("   {name}");
+    /// the index operand following the byte at `offset` and its width in bytes.
+    fn index_operand(&self, offset: usize, wide: bool) -> (usize, usize) {
+        if wide {
+            let bytes = &self.code[offset + 1..offset + 4];
+            (Self::inverse_resolve(bytes[0], bytes[1], bytes[2]), 3)
+        } else {
+            (self.code[offset + 1] as usize, 1)
+        }
+    }
+
+    fn simple_instruction(name: &str, offset: usize, out: &mut String) -> usize {
+        out.push_str(&format!("   {name}\n"));
         offset + 1
     }
 
-    /// in_const_pool = true, if the operand to this bytecode is an index to the constants pool
-    /// For some instructions like closures, the index points into the runtime structure.
-    /// causing index out of bounds.
-    fn byte_instruction(&self, name: &str, offset: usize, in_const_pool: bool) -> usize {
-        // the operand to this opcode is not always in the constants pool, it may be an index
-        // in the upvalues or locals list of another function
-        let slot = self.code[offset + 1];
-        print!("{name} \t");
-        if in_const_pool {
-            println!("{:04}", self.constants[slot as usize]);
-        } else {
-            println!("{}", slot);
-        }
-        offset + 2
+    /// the operand to this opcode is not in the constants pool, it is a count or an index
+    /// in the upvalues or locals list of the function.
+    fn byte_instruction(&self, name: &str, offset: usize, wide: bool, out: &mut String) -> usize {
+        let (slot, width) = self.index_operand(offset, wide);
+        out.push_str(&format!("{name} \t"));
+        out.push_str(&format!("{}\n", slot));
+        offset + 1 + width
     }
 
-    fn jump_instruction(&self, name: &str, sign: i32, offset: usize) -> usize {
+    fn jump_instruction(&self, name: &str, sign: i32, offset: usize, out: &mut String) -> usize {
         let b8_15 = self.code[offset + 2] as u32;
         let jump = self.code[offset + 1] as u32 | (b8_15 << 8);
-        print!("   {name}\t");
-        println!("{offset:4} {}", (offset as i32 + 3 + sign * jump as i32));
+        out.push_str(&format!("   {name}\t"));
+        out.push_str(&format!(
+            "{offset:4} {}\n",
+            (offset as i32 + 3 + sign * jump as i32)
+        ));
         offset + 3
     }
 
-    fn invoke_instruction(&self, name: &str, offset: usize) -> usize {
-        let constant = self.code[offset + 1]; // name 
-        if let Value::String(s) = self.constants[constant as usize] {
-            let arg_count = self.code[offset + 2]; // name 
+    fn jump_table_instruction(&self, offset: usize, out: &mut String) -> usize {
+        let low = self.code[offset + 1] as usize;
+        let high = self.code[offset + 2] as usize;
+        let read = |at: usize| self.code[at] as usize | (self.code[at + 1] as usize) << 8;
+        let end = offset + 5 + 2 * (high - low + 1);
+        out.push_str(&format!(
+            "   OP_JUMP_TABLE\t{offset:4} [{low}, {high}] default -> {}\n",
+            end + read(offset + 3)
+        ));
+        for value in low..=high {
+            let target = end + read(offset + 5 + 2 * (value - low));
+            out.push_str(&format!(
+                "{:04}    |              {value} -> {target}\n",
+                offset
+            ));
+        }
+        end
+    }
+
+    fn invoke_instruction(&self, name: &str, offset: usize, wide: bool, out: &mut String) -> usize {
+        let (constant, width) = self.index_operand(offset, wide); // name
+        if let Value::String(s) = self.constants[constant] {
+            let arg_count = self.code[offset + 1 + width];
             let info = format!(
                 "{:^16} ({:4} args) {:4}",
                 name,
                 arg_count,
                 interner::get_string(s).unwrap(),
             );
-            println!("{info}");
-            offset + 3
+            out.push_str(&format!("{info}\n"));
+            offset + 2 + width
         } else {
             panic!(
                 "Expected to find method name but found {}",
-                self.constants[constant as usize]
+                self.constants[constant]
             );
         }
     }
 
-    // TODO: fix this, compare with impl in the book!
-    fn constant_instruction(&self, name: &str, offset: usize) -> usize {
-        print!("   {name}\t");
-        let index = self.code[offset + 1]; // index of value is embeded in the bytecode stream.
-
-        #[cfg(any(test, debug_assertions))]
-        if let Value::String(id) = self.constants[index as usize] {
-            println!("{}", interner::get_string(id).unwrap())
+    fn constant_instruction(
+        &self,
+        name: &str,
+        offset: usize,
+        wide: bool,
+        out: &mut String,
+    ) -> usize {
+        out.push_str(&format!("   {name}\t"));
+        // index of value is embeded in the bytecode stream.
+        let (index, width) = self.index_operand(offset, wide);
+        match self.constants[index] {
+            Value::String(id) => out.push_str(&format!("{}\n", interner::get_string(id).unwrap())),
+            ref value => out.push_str(&format!("{}\n", value)),
         }
 
-        offset + 2 // consume current bytecode and operand index.
-    }
-
-    // reads the corresponding value of the OP_CONSTANT24 operand 24 bits and
-    // returns a usize to index into the constants array
-    fn read_long_constant(&self, offset: usize) -> usize {
-        let bytes = &self.code[offset + 1..offset + 4];
-        let idx = (bytes[0] as u32) | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16; // 24 bits
-        idx as usize
-    }
-
-    fn read_constant(&self, offset: usize) -> usize {
-        let idx = self.code[offset + 1];
-        idx as usize
+        offset + 1 + width // consume current bytecode and operand index.
     }
 
     // constants have an additional operand the index in the constants buffer;
@@ -283,25 +460,16 @@ impl Chunk {
         let idx = self.add_if_absent(value);
         // if the index of stored constant is > 256, we use the OP_CONSTANT_LONG
         if idx < 256 {
-            self.code.push(OpCode::Constant as u8);
-            self.code.push(idx as u8);
-            self.lines.push(Line(line)); // line num for constant bytecode 
-            self.lines.push(Line(line)); // line num for constant value
+            self.write_chunk(OpCode::Constant, line);
+            self.write(idx as u8, line);
         } else {
-            self.code.push(OpCode::Constant24 as u8);
+            self.write_chunk(OpCode::Constant24, line);
             // resolve byte index.
             let (bits0_7, bits8_15, bits16) = Self::resolve_index(idx);
-            self.code.push(bits0_7);
-            self.code.push(bits8_15);
-            self.code.push(bits16);
-            // line num for constant bytecode and 3 line nums for the index.
-            self.lines.push(Line(line));
-            self.lines.push(Line(line));
-            self.lines.push(Line(line));
-            self.lines.push(Line(line));
+            self.write(bits0_7, line);
+            self.write(bits8_15, line);
+            self.write(bits16, line);
         }
-        // !NOTE: remove this assertion when run-length encoding is implemented.
-        assert_eq!(self.code.len(), self.lines.len())
     }
 
     pub fn add_constant(&mut self, value: Value) -> usize {
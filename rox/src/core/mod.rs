@@ -1,3 +1,5 @@
+pub mod bytecode;
 pub mod chunk;
 pub mod opcode;
 pub mod value;
+pub mod verify;
@@ -46,13 +46,28 @@ pub enum OpCode {
     SuperInvoke = 38,
     Array = 39,
     ArrayGetItem = 40,
-    ArraySetItem = 41, // Design choice on why OpCodes for !=, <=, >= are not implemented.
-                       // the bytecode instructions does not need to follow closely to the user's
-                       // source code. The VM has total freedom to use whatever instruction set and code sequence
-                       // as long as they have the right behavior.
-                       // Semantically: a != b  === !(a == b)
-                       // a <= b === !(a > b)
-                       // a >= b === !(a < b). except for floating-point NaN
+    ArraySetItem = 41,
+    // [JumpTable][low][high][default: u16][entry: u16 * (high - low + 1)]
+    // offsets are relative to the end of the table.
+    JumpTable = 42,
+    // prefix: the index operand (constant, local, upvalue) of the next instruction is 24 bits.
+    Wide = 43, // Design choice on why OpCodes for !=, <=, >= are not implemented.
+    // the bytecode instructions does not need to follow closely to the user's
+    // source code. The VM has total freedom to use whatever instruction set and code sequence
+    // as long as they have the right behavior.
+    // Semantically: a != b  === !(a == b)
+    // a <= b === !(a > b)
+    // a >= b === !(a < b). except for floating-point NaN
+
+    // [Map][LONG_ARG_INDEX | SHORT_ARG_INDEX][1b | 3b entry count]
+    // keys and values alternate on the stack.
+    Map = 44,
+    // pops a value and raises it as an exception, see `Chunk::handlers`.
+    Throw = 45,
+    // pushes the module loaded from the path in the constant operand, see `VM::import`.
+    Import = 46,
+    // remainder of integer (or float) division.
+    Modulo = 47,
 }
 
 impl Display for OpCode {
@@ -110,6 +125,12 @@ impl TryFrom<u8> for OpCode {
             39 => Ok(Self::Array),
             40 => Ok(Self::ArrayGetItem),
             41 => Ok(Self::ArraySetItem),
+            42 => Ok(Self::JumpTable),
+            43 => Ok(Self::Wide),
+            44 => Ok(Self::Map),
+            45 => Ok(Self::Throw),
+            46 => Ok(Self::Import),
+            47 => Ok(Self::Modulo),
             _ => Err(()),
         }
     }
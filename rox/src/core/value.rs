@@ -1,7 +1,9 @@
 #![allow(unreachable_patterns)]
 use std::{
-    fmt::Display,
-    ops::{Add, Div, Mul, Neg, Sub},
+    cell::RefCell,
+    cmp::Ordering,
+    fmt::{Debug, Display},
+    ops::{Add, Div, Mul, Neg, Rem, Sub},
     rc::Rc,
 };
 
@@ -9,8 +11,8 @@ use string_interner::symbol::SymbolU32;
 
 use crate::{
     data_structures::interner::{self},
-    runtime::lang::Function,
-    std::VmResult,
+    runtime::{lang::Function, native::NativeContext},
+    std::{NativeResult, VmResult, registry::Arity},
 };
 
 /// Java-style reference id to an object stored on the heap.
@@ -25,6 +27,14 @@ impl crate::runtime::gc::Trace for ObjId {
     }
 }
 
+impl crate::runtime::gc::Trace for Value {
+    fn trace(&self, heap: &mut crate::runtime::heap::Heap) {
+        if let Value::Object(id) = self {
+            heap.mark_object(*id);
+        }
+    }
+}
+
 // stub struct
 #[derive(Debug, PartialEq, PartialOrd)]
 pub struct Closure;
@@ -38,11 +48,17 @@ pub enum Value {
     #[default]
     Nil,
     Number(f64),
+    // integer literals and the results of integer arithmetic, which is checked
+    // rather than wrapping. Mixed with a `Number` the result is a `Number`.
+    Int(i64),
     LoxFunction(Rc<Function>),
     // interned strings allow us to compare addreses(symbols) which is more efficient
     // than comparing the values(contents) of the strings themselves.
     String(SymbolU32),
-    NativeFunction(NativeFn),
+    // natives carry their arity, the compiler only checks calls made by name.
+    NativeFunction(NativeFn, Arity),
+    // a native backed by a closure of the embedding application.
+    HostFunction(HostFn, Arity),
     Object(ObjId), // pointer into the GC Heap
     // this variant is for convenience and not in the book.
     // It is for Native function use only.
@@ -53,15 +69,15 @@ impl Value {
     // we could do the same for strings, but we already have native functions for that.
     pub fn less_than(lhs: &Value, rhs: &Value) -> Option<Value> {
         match (lhs, rhs) {
-            (Value::Number(ln), Value::Number(rn)) => Some(Value::Boolean(ln < rn)),
-            _ => None,
+            (Value::Int(ln), Value::Int(rn)) => Some(Value::Boolean(ln < rn)),
+            _ => Some(Value::Boolean(lhs.as_f64()? < rhs.as_f64()?)),
         }
     }
 
     pub fn greater_than(lhs: &Value, rhs: &Value) -> Option<Value> {
         match (lhs, rhs) {
-            (Value::Number(ln), Value::Number(rn)) => Some(Value::Boolean(ln > rn)),
-            _ => None,
+            (Value::Int(ln), Value::Int(rn)) => Some(Value::Boolean(ln > rn)),
+            _ => Some(Value::Boolean(lhs.as_f64()? > rhs.as_f64()?)),
         }
     }
 
@@ -73,18 +89,42 @@ impl Value {
         matches!(value, Value::Nil)
     }
 
+    /// true for both `Number`s and `Int`s.
     pub fn is_number(value: &Value) -> bool {
-        matches!(value, Value::Number(_))
+        matches!(value, Value::Number(_) | Value::Int(_))
+    }
+
+    /// the value of a `Number` or an `Int`, which may lose precision past 2^53.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// an `Int`, or a `Number` without a fractional part that fits an `i64`.
+    pub fn as_whole(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            // 2^63 itself is out of range, `as` would saturate it to i64::MAX.
+            Value::Number(n)
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+            {
+                Some(*n as i64)
+            }
+            _ => None,
+        }
     }
 
     pub fn is_native(value: &Value) -> bool {
-        matches!(value, Value::NativeFunction(_))
+        matches!(value, Value::NativeFunction(..) | Value::HostFunction(..))
     }
 
     pub fn is_object(value: &Value) -> bool {
         matches!(value, Value::LoxFunction(_))
             || matches!(value, Value::Object(_))
-            || matches!(value, Value::NativeFunction(_))
+            || Value::is_native(value)
     }
 
     pub fn is_string(&self) -> bool {
@@ -95,6 +135,7 @@ impl Value {
         match value {
             Value::Index(i) => *i,
             Value::Number(n) => *n as usize,
+            Value::Int(i) => *i as usize,
             _ => panic!("Expected Variant Number | Index  but got {:?}", value),
         }
     }
@@ -108,7 +149,7 @@ impl Value {
     }
 
     pub fn as_native(value: &Value) -> Option<NativeFn> {
-        if let Value::NativeFunction(f) = value {
+        if let Value::NativeFunction(f, _) = value {
             Some(*f)
         } else {
             None
@@ -116,10 +157,9 @@ impl Value {
     }
 
     pub fn as_number(value: &Value) -> f64 {
-        if let Value::Number(n) = value {
-            *n
-        } else {
-            panic!("Expected Variant boolean but got {:?}", value);
+        match value.as_f64() {
+            Some(n) => n,
+            None => panic!("Expected Variant Number | Int but got {:?}", value),
         }
     }
 
@@ -145,6 +185,9 @@ impl Value {
             (Value::Boolean(av), Value::Boolean(bv)) => av == bv,
             (Value::Nil, Value::Nil) => true,
             (Value::Number(av), Value::Number(bv)) => av == bv,
+            (Value::Int(av), Value::Int(bv)) => av == bv,
+            // 1 == 1.0, like their map keys.
+            (Value::Int(i), Value::Number(n)) | (Value::Number(n), Value::Int(i)) => i as f64 == n,
             (Value::String(lsz), Value::String(rsz)) => lsz == rsz,
             (Value::Nil, _) => false, // allow java style value != null.
             (_, Value::Nil) => false,
@@ -165,12 +208,14 @@ impl Display for Value {
         match &self {
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Number(n) => write!(f, "{}", n),
+            Value::Int(i) => write!(f, "{}", i),
             Value::Nil => write!(f, "[NIL]"),
             Value::String(id) => {
                 let s = interner::get_string(*id).unwrap();
                 write!(f, "{}", s)
             }
-            Value::NativeFunction(n) => write!(f, "{}", n),
+            Value::NativeFunction(n, _) => write!(f, "{}", n),
+            Value::HostFunction(n, _) => write!(f, "{}", n),
             Value::LoxFunction(n) => match &n.name {
                 Some(name) => write!(f, "<fn {}>", name),
                 None => write!(f, "<script>"),
@@ -189,6 +234,7 @@ impl Neg for Value {
         match self {
             Value::Boolean(_) => None,
             Value::Number(n) => Some(Value::Number(-n)),
+            Value::Int(i) => i.checked_neg().map(Value::Int),
             Value::Nil => Some(Value::Nil),
             _ => None,
         }
@@ -200,7 +246,10 @@ impl Add for Value {
 
     fn add(self, other: Self) -> Self::Output {
         match (&self, &other) {
-            (Value::Number(l), Value::Number(r)) => Some(Value::Number(l + r)),
+            (Value::Int(l), Value::Int(r)) => l.checked_add(*r).map(Value::Int),
+            (Value::Number(_) | Value::Int(_), Value::Number(_) | Value::Int(_)) => {
+                Some(Value::Number(self.as_f64()? + other.as_f64()?))
+            }
             (Value::Nil, _) => Some(Value::Nil), // allow obj + Nil instead of throwing error at runtime
             (_, Value::Nil) => Some(Value::Nil),
             (Value::String(lhs), Value::String(rhs)) => {
@@ -216,18 +265,20 @@ impl Add for Value {
                 }
             }
             // String concatenation: This needed for print statments.
-            (Value::String(lhs), Value::Number(n)) => match interner::get_string(*lhs) {
-                Some(mut string) => {
-                    string.push_str(&n.to_string());
-                    let symbol = interner::intern(&string);
-                    Some(Value::String(symbol))
+            (Value::String(lhs), Value::Number(_) | Value::Int(_)) => {
+                match interner::get_string(*lhs) {
+                    Some(mut string) => {
+                        string.push_str(&other.to_string());
+                        let symbol = interner::intern(&string);
+                        Some(Value::String(symbol))
+                    }
+                    None => None,
                 }
-                None => None,
-            },
-            (Value::Number(n), Value::String(lhs)) => {
+            }
+            (Value::Number(_) | Value::Int(_), Value::String(lhs)) => {
                 match interner::get_string(*lhs) {
                     Some(string) => {
-                        let mut new_string = n.to_string(); // order matters here.
+                        let mut new_string = self.to_string(); // order matters here.
                         new_string.push_str(&string);
                         let symbol = interner::intern(&new_string);
                         Some(Value::String(symbol))
@@ -243,10 +294,14 @@ impl Add for Value {
 impl Div for Value {
     type Output = Option<Self>;
 
+    // `/` divides like floats, two ints stay an int only when the quotient is whole
+    // (and fits one), see `math::div` for integer division.
     fn div(self, other: Self) -> Self::Output {
         match (&self, &other) {
-            (Value::Number(l), Value::Number(r)) => Some(Value::Number(l / r)),
-            _ => None,
+            (Value::Int(l), Value::Int(r)) if l.checked_rem(*r) == Some(0) => {
+                Some(Value::Int(l / r))
+            }
+            _ => Some(Value::Number(self.as_f64()? / other.as_f64()?)),
         }
     }
 }
@@ -256,8 +311,8 @@ impl Mul for Value {
 
     fn mul(self, other: Self) -> Self::Output {
         match (&self, &other) {
-            (Value::Number(l), Value::Number(r)) => Some(Value::Number(l * r)),
-            _ => None,
+            (Value::Int(l), Value::Int(r)) => l.checked_mul(*r).map(Value::Int),
+            _ => Some(Value::Number(self.as_f64()? * other.as_f64()?)),
         }
     }
 }
@@ -267,15 +322,27 @@ impl Sub for Value {
 
     fn sub(self, other: Self) -> Self::Output {
         match (&self, &other) {
-            (Value::Number(l), Value::Number(r)) => Some(Value::Number(l - r)),
-            _ => None,
+            (Value::Int(l), Value::Int(r)) => l.checked_sub(*r).map(Value::Int),
+            _ => Some(Value::Number(self.as_f64()? - other.as_f64()?)),
+        }
+    }
+}
+
+// the remainder takes the sign of the dividend, like `%` in rust.
+impl Rem for Value {
+    type Output = Option<Self>;
+
+    fn rem(self, other: Self) -> Self::Output {
+        match (&self, &other) {
+            (Value::Int(l), Value::Int(r)) => l.checked_rem(*r).map(Value::Int),
+            _ => Some(Value::Number(self.as_f64()? % other.as_f64()?)),
         }
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialOrd)]
 #[allow(unpredictable_function_pointer_comparisons)]
-pub struct NativeFn(pub for<'a> fn(usize, &'a [Value]) -> VmResult);
+pub struct NativeFn(pub for<'a> fn(usize, &'a [Value]) -> NativeResult);
 
 impl Display for NativeFn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -288,3 +355,43 @@ impl PartialEq for NativeFn {
         std::ptr::fn_addr_eq(self.0, other.0)
     }
 }
+
+pub type HostFnBox = Box<dyn FnMut(&mut NativeContext, &[Value]) -> VmResult>;
+
+/// a native that, unlike `NativeFn`, can capture state of the embedding application
+/// and reach the heap through its `NativeContext`. Clones share the same closure,
+/// which is borrowed while it runs and so can't be re-entered from a callback.
+/// NOTE: heap objects the closure holds on to between calls are not roots, they
+/// should be kept reachable from lox (e.g in a global).
+#[derive(Clone)]
+pub struct HostFn(pub Rc<RefCell<HostFnBox>>);
+
+impl HostFn {
+    pub fn new(function: impl FnMut(&mut NativeContext, &[Value]) -> VmResult + 'static) -> Self {
+        Self(Rc::new(RefCell::new(Box::new(function))))
+    }
+}
+
+impl Debug for HostFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostFn@{:p}", Rc::as_ptr(&self.0))
+    }
+}
+
+impl Display for HostFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+impl PartialEq for HostFn {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for HostFn {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
@@ -0,0 +1,454 @@
+use crate::{
+    compile::compiler::LONG_ARG_INDEX,
+    core::{chunk::Chunk, opcode::OpCode, value::Value},
+    std::lox_errors::{VerifyError, VerifyErrorKind},
+};
+
+/// Statically checks the top-level chunk of a script and every function nested
+/// in its constants before the vm runs it. The vm trusts its bytecode, so chunks
+/// that did not come straight out of `Compiler::compile` (e.g loaded from a `.roxc`
+/// file) should go through here first.
+pub fn verify(chunk: &Chunk) -> Result<(), VerifyError> {
+    verify_function(chunk, "script", 0, 0)
+}
+
+fn verify_function(
+    chunk: &Chunk,
+    name: &str,
+    arity: u8,
+    upvalue_count: usize,
+) -> Result<(), VerifyError> {
+    let verifier = Verifier {
+        chunk,
+        name,
+        upvalue_count,
+    };
+    let instructions = verifier.decode_all()?;
+    verifier.check_stack(&instructions, arity as usize + 1)?;
+
+    for constant in &chunk.constants {
+        if let Value::LoxFunction(function) = constant {
+            verify_function(
+                &function.chunk,
+                function.name.as_deref().unwrap_or("script"),
+                function.arity,
+                function.upvalue_count,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// what the stack check needs to know about a decoded instruction.
+#[derive(Debug)]
+struct Instruction {
+    op: OpCode,
+    len: usize,
+    // values the instruction needs on the stack, and how many it leaves in their place.
+    pops: usize,
+    pushes: usize,
+    // local slot read or written, upvalues captured from the enclosing frame's locals.
+    locals: Vec<usize>,
+    // jump targets other than the next instruction.
+    jumps: Vec<usize>,
+}
+
+struct Verifier<'a> {
+    chunk: &'a Chunk,
+    name: &'a str,
+    upvalue_count: usize,
+}
+
+impl Verifier<'_> {
+    fn error(&self, offset: usize, kind: VerifyErrorKind) -> VerifyError {
+        VerifyError {
+            function: self.name.to_string(),
+            offset,
+            kind,
+        }
+    }
+
+    fn byte(&self, offset: usize, at: usize) -> Result<u8, VerifyError> {
+        self.chunk
+            .code
+            .get(at)
+            .copied()
+            .ok_or_else(|| self.error(offset, VerifyErrorKind::TruncatedOperand))
+    }
+
+    fn short(&self, offset: usize, at: usize) -> Result<usize, VerifyError> {
+        Ok(self.byte(offset, at)? as usize | (self.byte(offset, at + 1)? as usize) << 8)
+    }
+
+    fn long(&self, offset: usize, at: usize) -> Result<usize, VerifyError> {
+        Ok(Chunk::inverse_resolve(
+            self.byte(offset, at)?,
+            self.byte(offset, at + 1)?,
+            self.byte(offset, at + 2)?,
+        ))
+    }
+
+    /// the index operand at `at` and its width, 3 bytes after a `Wide` prefix.
+    fn index(&self, offset: usize, at: usize, wide: bool) -> Result<(usize, usize), VerifyError> {
+        if wide {
+            Ok((self.long(offset, at)?, 3))
+        } else {
+            Ok((self.byte(offset, at)? as usize, 1))
+        }
+    }
+
+    fn constant(
+        &self,
+        offset: usize,
+        at: usize,
+        wide: bool,
+    ) -> Result<(&Value, usize), VerifyError> {
+        let (index, width) = self.index(offset, at, wide)?;
+        let value = self
+            .chunk
+            .constants
+            .get(index)
+            .ok_or_else(|| self.error(offset, VerifyErrorKind::ConstantOutOfRange(index)))?;
+        Ok((value, width))
+    }
+
+    fn string_constant(&self, offset: usize, wide: bool) -> Result<usize, VerifyError> {
+        match self.constant(offset, offset + 1, wide)? {
+            (Value::String(_), width) => Ok(width),
+            _ => Err(self.error(offset, VerifyErrorKind::ExpectedString)),
+        }
+    }
+
+    fn decode_all(&self) -> Result<Vec<Option<Instruction>>, VerifyError> {
+        // runs start at the first byte and move forward through the code.
+        let starts = self.chunk.lines.iter().map(|run| run.start as usize);
+        let ordered = starts
+            .clone()
+            .zip(starts.skip(1))
+            .all(|(start, next)| start < next);
+        let covered = match self.chunk.lines.first() {
+            Some(first) => first.start == 0,
+            None => self.chunk.code.is_empty(),
+        };
+        let in_bounds = self
+            .chunk
+            .lines
+            .last()
+            .is_none_or(|run| (run.start as usize) < self.chunk.code.len());
+        if !(ordered && covered && in_bounds) {
+            return Err(self.error(0, VerifyErrorKind::LineTableMismatch));
+        }
+
+        // indexed by offset, None for the operand bytes inside an instruction.
+        let mut instructions: Vec<Option<Instruction>> = vec![];
+        let mut offset = 0;
+        while offset < self.chunk.code.len() {
+            let instruction = match OpCode::try_from(self.chunk.code[offset]) {
+                Ok(OpCode::Wide) => {
+                    let mut instruction = self.decode(offset + 1, true)?;
+                    instruction.len += 1;
+                    instruction
+                }
+                _ => self.decode(offset, false)?,
+            };
+            let len = instruction.len;
+            instructions.push(Some(instruction));
+            instructions.extend((1..len).map(|_| None));
+            offset += len;
+        }
+        instructions.truncate(self.chunk.code.len());
+        Ok(instructions)
+    }
+
+    fn decode(&self, offset: usize, wide: bool) -> Result<Instruction, VerifyError> {
+        let byte = self.byte(offset, offset)?;
+        let op = OpCode::try_from(byte)
+            .map_err(|_| self.error(offset, VerifyErrorKind::InvalidOpcode(byte)))?;
+        let widens = matches!(
+            op,
+            OpCode::Constant
+                | OpCode::DefineGlobal
+                | OpCode::GetGlobal
+                | OpCode::SetGlobal
+                | OpCode::GetLocal
+                | OpCode::SetLocal
+                | OpCode::GetUpValue
+                | OpCode::SetUpValue
+                | OpCode::Class
+                | OpCode::GetProperty
+                | OpCode::SetProperty
+                | OpCode::Method
+                | OpCode::Invoke
+                | OpCode::GetSuper
+                | OpCode::SuperInvoke
+                | OpCode::Closure
+                | OpCode::Import
+        );
+        if wide && !widens {
+            return Err(self.error(offset, VerifyErrorKind::MalformedOperand));
+        }
+
+        let simple = |pops, pushes, len| Instruction {
+            op,
+            len,
+            pops,
+            pushes,
+            locals: vec![],
+            jumps: vec![],
+        };
+        let jump = |target: usize| -> Result<usize, VerifyError> {
+            let jump = self.short(offset, offset + 1)?;
+            Ok(target + jump)
+        };
+
+        let instruction = match op {
+            OpCode::Return => simple(1, 0, 1),
+            OpCode::Wide => return Err(self.error(offset, VerifyErrorKind::MalformedOperand)),
+            OpCode::Constant | OpCode::Constant24 => {
+                let long = wide || matches!(op, OpCode::Constant24);
+                let (_, width) = self.constant(offset, offset + 1, long)?;
+                simple(0, 1, 1 + width)
+            }
+            OpCode::NIL | OpCode::True | OpCode::False => simple(0, 1, 1),
+            OpCode::Negate | OpCode::Not => simple(1, 1, 1),
+            OpCode::Add
+            | OpCode::Divide
+            | OpCode::Multiply
+            | OpCode::Subtract
+            | OpCode::Modulo
+            | OpCode::Equal
+            | OpCode::Greater
+            | OpCode::Less
+            | OpCode::ArrayGetItem => simple(2, 1, 1),
+            OpCode::ArraySetItem => simple(3, 1, 1),
+            OpCode::Print | OpCode::Pop | OpCode::CloseUpValue => simple(1, 0, 1),
+            OpCode::PopN => simple(self.byte(offset, offset + 1)? as usize, 0, 2),
+            OpCode::DefineGlobal => simple(1, 0, 1 + self.string_constant(offset, wide)?),
+            OpCode::GetGlobal | OpCode::Class | OpCode::Import => {
+                simple(0, 1, 1 + self.string_constant(offset, wide)?)
+            }
+            OpCode::SetGlobal => simple(1, 1, 1 + self.string_constant(offset, wide)?),
+            OpCode::GetProperty => simple(1, 1, 1 + self.string_constant(offset, wide)?),
+            OpCode::SetProperty => simple(2, 1, 1 + self.string_constant(offset, wide)?),
+            // [class][closure] -> [class]
+            OpCode::Method => simple(2, 1, 1 + self.string_constant(offset, wide)?),
+            // [superclass][subclass] -> [superclass]
+            OpCode::Inherit => simple(2, 1, 1),
+            // [this][superclass] -> [bound method]
+            OpCode::GetSuper => simple(2, 1, 1 + self.string_constant(offset, wide)?),
+            OpCode::Invoke => {
+                let width = self.string_constant(offset, wide)?;
+                let args = self.byte(offset, offset + 1 + width)? as usize;
+                simple(args + 1, 1, 2 + width)
+            }
+            // [this][args...][superclass] -> [result]
+            OpCode::SuperInvoke => {
+                let width = self.string_constant(offset, wide)?;
+                let args = self.byte(offset, offset + 1 + width)? as usize;
+                simple(args + 2, 1, 2 + width)
+            }
+            OpCode::Call => {
+                let args = self.byte(offset, offset + 1)? as usize;
+                simple(args + 1, 1, 2)
+            }
+            OpCode::GetLocal | OpCode::SetLocal => {
+                let (slot, width) = self.index(offset, offset + 1, wide)?;
+                let pops = match op {
+                    OpCode::GetLocal => 0,
+                    _ => 1,
+                };
+                Instruction {
+                    locals: vec![slot],
+                    ..simple(pops, 1, 1 + width)
+                }
+            }
+            OpCode::GetUpValue | OpCode::SetUpValue => {
+                let (index, width) = self.index(offset, offset + 1, wide)?;
+                if index >= self.upvalue_count {
+                    return Err(self.error(offset, VerifyErrorKind::UpvalueOutOfRange(index)));
+                }
+                match op {
+                    OpCode::GetUpValue => simple(0, 1, 1 + width),
+                    _ => simple(1, 1, 1 + width),
+                }
+            }
+            OpCode::Jump => Instruction {
+                jumps: vec![jump(offset + 3)?],
+                ..simple(0, 0, 3)
+            },
+            OpCode::JumpIfFalse => Instruction {
+                jumps: vec![jump(offset + 3)?],
+                ..simple(1, 1, 3)
+            },
+            OpCode::Loop => {
+                let back = self.short(offset, offset + 1)?;
+                let target = (offset + 3)
+                    .checked_sub(back)
+                    .ok_or_else(|| self.error(offset, VerifyErrorKind::BadJumpTarget(0)))?;
+                Instruction {
+                    jumps: vec![target],
+                    ..simple(0, 0, 3)
+                }
+            }
+            OpCode::JumpTable => {
+                let low = self.byte(offset, offset + 1)? as usize;
+                let high = self.byte(offset, offset + 2)? as usize;
+                if high < low {
+                    return Err(self.error(offset, VerifyErrorKind::MalformedOperand));
+                }
+                let len = 5 + 2 * (high - low + 1);
+                let end = offset + len;
+                let mut jumps = vec![];
+                for entry in 0..=(high - low + 1) {
+                    jumps.push(end + self.short(offset, offset + 3 + 2 * entry)?);
+                }
+                Instruction {
+                    jumps,
+                    ..simple(1, 0, len)
+                }
+            }
+            OpCode::Array => {
+                let (items, len) = match self.byte(offset, offset + 1)? {
+                    LONG_ARG_INDEX => (self.long(offset, offset + 2)?, 5),
+                    _ => (self.byte(offset, offset + 2)? as usize, 3),
+                };
+                simple(items, 1, len)
+            }
+            OpCode::Map => {
+                let (entries, len) = match self.byte(offset, offset + 1)? {
+                    LONG_ARG_INDEX => (self.long(offset, offset + 2)?, 5),
+                    _ => (self.byte(offset, offset + 2)? as usize, 3),
+                };
+                simple(2 * entries, 1, len)
+            }
+            OpCode::Closure => self.decode_closure(offset, wide)?,
+            OpCode::Throw => simple(1, 0, 1),
+        };
+        Ok(instruction)
+    }
+
+    // [Closure][constant]([is_long][1b | 3b index][is_local])*
+    fn decode_closure(&self, offset: usize, wide: bool) -> Result<Instruction, VerifyError> {
+        let (function, width) = match self.constant(offset, offset + 1, wide)? {
+            (Value::LoxFunction(function), width) => (function, width),
+            _ => return Err(self.error(offset, VerifyErrorKind::ExpectedFunction)),
+        };
+
+        let mut at = offset + 1 + width;
+        let mut locals = vec![];
+        for _ in 0..function.upvalue_count {
+            let index = match self.byte(offset, at)? {
+                1 => {
+                    at += 4;
+                    self.long(offset, at - 3)?
+                }
+                0 => {
+                    at += 2;
+                    self.byte(offset, at - 1)? as usize
+                }
+                _ => return Err(self.error(offset, VerifyErrorKind::MalformedOperand)),
+            };
+            match self.byte(offset, at)? {
+                1 => locals.push(index),
+                0 if index < self.upvalue_count => (),
+                0 => return Err(self.error(offset, VerifyErrorKind::UpvalueOutOfRange(index))),
+                _ => return Err(self.error(offset, VerifyErrorKind::MalformedOperand)),
+            }
+            at += 1;
+        }
+
+        Ok(Instruction {
+            op: OpCode::Closure,
+            len: at - offset,
+            pops: 0,
+            pushes: 1,
+            locals,
+            jumps: vec![],
+        })
+    }
+
+    /// walks every path through the chunk, the stack depth where paths meet must agree.
+    fn check_stack(
+        &self,
+        instructions: &[Option<Instruction>],
+        start_depth: usize,
+    ) -> Result<(), VerifyError> {
+        if instructions.is_empty() {
+            return Err(self.error(0, VerifyErrorKind::FallsOffEnd));
+        }
+
+        let mut depths: Vec<Option<usize>> = vec![None; instructions.len()];
+        let mut worklist = vec![(0, start_depth)];
+        // a handler starts with the error pushed on top of the locals it keeps.
+        for (index, handler) in self.chunk.handlers.iter().enumerate() {
+            let boundary = |at: u32| {
+                at as usize == instructions.len()
+                    || instructions.get(at as usize).is_some_and(Option::is_some)
+            };
+            let target = handler.target as usize;
+            if handler.start > handler.end
+                || !boundary(handler.start)
+                || !boundary(handler.end)
+                || instructions.get(target).is_none_or(Option::is_none)
+            {
+                return Err(self.error(target, VerifyErrorKind::BadHandler(index)));
+            }
+            worklist.push((target, handler.depth as usize + 1));
+        }
+
+        while let Some((offset, depth)) = worklist.pop() {
+            let Some(instruction) = instructions.get(offset).and_then(Option::as_ref) else {
+                return Err(self.error(offset, VerifyErrorKind::FallsOffEnd));
+            };
+            match depths[offset] {
+                Some(seen) if seen == depth => continue,
+                Some(seen) => {
+                    return Err(self.error(
+                        offset,
+                        VerifyErrorKind::StackMismatch {
+                            expected: seen,
+                            found: depth,
+                        },
+                    ));
+                }
+                None => depths[offset] = Some(depth),
+            }
+
+            if instruction.pops > depth {
+                return Err(self.error(offset, VerifyErrorKind::StackUnderflow));
+            }
+            // the locals the instruction reads exclude what it pops itself.
+            let live = depth - instruction.pops;
+            if let Some(&slot) = instruction.locals.iter().find(|&&slot| slot >= live) {
+                return Err(self.error(offset, VerifyErrorKind::LocalOutOfRange(slot)));
+            }
+            let after = live + instruction.pushes;
+
+            for &target in &instruction.jumps {
+                if instructions.get(target).is_none_or(Option::is_none) {
+                    return Err(self.error(offset, VerifyErrorKind::BadJumpTarget(target)));
+                }
+                worklist.push((target, after));
+            }
+            let falls_through = !matches!(
+                instruction.op,
+                OpCode::Return | OpCode::Jump | OpCode::Loop | OpCode::JumpTable | OpCode::Throw
+            );
+            if falls_through {
+                worklist.push((offset + instruction.len, after));
+            }
+        }
+
+        // the vm truncates the stack to the handler's depth, it can't grow it.
+        for handler in &self.chunk.handlers {
+            let range = handler.start as usize..handler.end as usize;
+            if let Some(offset) = range
+                .into_iter()
+                .find(|&at| depths[at].is_some_and(|depth| depth < handler.depth as usize))
+            {
+                return Err(self.error(offset, VerifyErrorKind::StackUnderflow));
+            }
+        }
+        Ok(())
+    }
+}
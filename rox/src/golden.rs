@@ -0,0 +1,227 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::compile::compiler::Compiler;
+use crate::runtime::output::SharedBuffer;
+use crate::runtime::vm::VM;
+
+/// what a `.lox` test script says it does, written as comments in the
+/// Crafting Interpreters test suite convention:
+/// ```text
+/// print 1 + 2; // expect: 3
+/// -"a";        // expect runtime error: Operand must be a number.
+/// var = 1;     // Error at '=': Expect variable name.
+/// // [line 7] Error at end: Expect '}' after block.
+/// ```
+/// `// nontest` marks a script (e.g a benchmark) the runner should skip, and
+/// expectations meant for jlox only (`// [java line 3] ...`) are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expectations {
+    pub output: Vec<String>,
+    /// `[line N] Error ...` lines, in the order the compiler should report them.
+    pub compile_errors: Vec<String>,
+    /// the message and the line the innermost frame was on.
+    pub runtime_error: Option<(String, u32)>,
+    pub skip: bool,
+}
+
+impl Expectations {
+    pub fn parse(source: &str) -> Self {
+        let mut expected = Self::default();
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            // like the upstream runner the markers are looked for anywhere in the
+            // line, so a `//` in a string literal isn't taken for the comment.
+            if line.contains("// nontest") {
+                expected.skip = true;
+            }
+            if let Some(text) = after(line, "// expect:") {
+                // `// expect:` alone expects an empty line.
+                let text = text.strip_prefix(' ').unwrap_or(text);
+                expected.output.push(text.to_owned());
+            } else if let Some(message) = after(line, "// expect runtime error: ") {
+                expected.runtime_error = Some((message.to_owned(), line_number));
+            } else if let Some(error) = after(line, "// Error") {
+                expected
+                    .compile_errors
+                    .push(format!("[line {}] Error{}", line_number, error));
+            } else if let Some(error) =
+                after(line, "// [line ").or_else(|| after(line, "// [c line "))
+            {
+                expected.compile_errors.push(format!("[line {}", error));
+            }
+        }
+        expected
+    }
+}
+
+/// the rest of `line` after the first `marker`.
+fn after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    line.find(marker).map(|start| &line[start + marker.len()..])
+}
+
+/// what a script actually did when it ran, comparable to its `Expectations`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Outcome {
+    pub output: Vec<String>,
+    pub compile_errors: Vec<String>,
+    pub runtime_error: Option<(String, u32)>,
+}
+
+/// compiles and runs `source` on a fresh vm with its output captured.
+pub fn run(source: &str) -> Outcome {
+    run_at(source, None)
+}
+
+/// like `run`, with the imports of `source` resolved relative to `path`.
+pub fn run_at(source: &str, path: Option<&Path>) -> Outcome {
+    if let Err(diagnostics) = Compiler::compile(source) {
+        return Outcome {
+            compile_errors: diagnostics.iter().map(|d| d.to_string()).collect(),
+            ..Outcome::default()
+        };
+    }
+
+    let out = SharedBuffer::new();
+    let mut vm = VM::init().with_output(out.clone(), SharedBuffer::new());
+    if let Some(path) = path {
+        vm.set_script_path(path);
+    }
+    let runtime_error = vm
+        .interpret_with_diagnostics(source.to_owned())
+        .err()
+        .map(|error| {
+            let line = error.stack.first().map_or(0, |frame| frame.line);
+            (error.message, line)
+        });
+    Outcome {
+        output: out.contents().lines().map(str::to_owned).collect(),
+        compile_errors: vec![],
+        runtime_error,
+    }
+}
+
+/// runs `source` and describes every way it strayed from its expectations,
+/// an empty list means the script passed.
+pub fn check(source: &str) -> Vec<String> {
+    check_at(source, None)
+}
+
+/// like `check`, with the imports of `source` resolved relative to `path`.
+pub fn check_at(source: &str, path: Option<&Path>) -> Vec<String> {
+    let expected = Expectations::parse(source);
+    let outcome = run_at(source, path);
+    let mut failures = vec![];
+
+    let lines = expected.output.len().max(outcome.output.len());
+    for i in 0..lines {
+        match (expected.output.get(i), outcome.output.get(i)) {
+            (Some(want), Some(got)) if want == got => (),
+            (Some(want), Some(got)) => failures.push(format!(
+                "Expected output '{}' on line {} and got '{}'.",
+                want,
+                i + 1,
+                got
+            )),
+            (Some(want), None) => failures.push(format!("Missing expected output '{}'.", want)),
+            (None, Some(got)) => {
+                failures.push(format!("Got output '{}' when none was expected.", got))
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+
+    if expected.compile_errors != outcome.compile_errors {
+        failures.push(format!(
+            "Expected compile errors {:?} and got {:?}.",
+            expected.compile_errors, outcome.compile_errors
+        ));
+    }
+
+    match (&expected.runtime_error, &outcome.runtime_error) {
+        (Some(want), Some(got)) if want == got => (),
+        (Some((message, line)), Some((got, got_line))) => failures.push(format!(
+            "Expected runtime error '{}' on line {} and got '{}' on line {}.",
+            message, line, got, got_line
+        )),
+        (Some((message, _)), None) => failures.push(format!(
+            "Expected runtime error '{}' and got none.",
+            message
+        )),
+        (None, Some((got, line))) => failures.push(format!(
+            "Unexpected runtime error '{}' on line {}.",
+            got, line
+        )),
+        (None, None) => (),
+    }
+    failures
+}
+
+/// the result of running every `.lox` script under a directory.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, Vec<String>)>,
+    pub skipped: Vec<PathBuf>,
+}
+
+impl Report {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, failures) in &self.failed {
+            writeln!(f, "FAIL {}", path.display())?;
+            for failure in failures {
+                writeln!(f, "     {}", failure)?;
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped.",
+            self.passed.len(),
+            self.failed.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+/// checks every `.lox` script under `dir` and its subdirectories, in path order.
+pub fn run_dir(dir: &Path) -> io::Result<Report> {
+    let mut paths = vec![];
+    collect_scripts(dir, &mut paths)?;
+    paths.sort();
+
+    let mut report = Report::default();
+    for path in paths {
+        let source = fs::read_to_string(&path)?;
+        if Expectations::parse(&source).skip {
+            report.skipped.push(path);
+            continue;
+        }
+        let failures = check_at(&source, Some(&path));
+        if failures.is_empty() {
+            report.passed.push(path);
+        } else {
+            report.failed.push((path, failures));
+        }
+    }
+    Ok(report)
+}
+
+fn collect_scripts(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_scripts(&path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,8 @@
+pub mod cli;
 pub mod compile;
 pub mod core;
 pub mod data_structures;
+pub mod golden;
+pub mod repl;
 pub mod runtime;
 pub mod std;
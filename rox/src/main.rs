@@ -1,58 +1,208 @@
 use std::env;
 use std::fs;
-use std::io;
-use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+use rox::cli::{Cli, Command, USAGE};
+use rox::compile::compiler::Compiler;
+use rox::core::chunk::Chunk;
+use rox::core::value::Value;
+use rox::core::verify::verify;
+use rox::repl::{self, Repl};
+use rox::runtime::native::NativeContext;
 use rox::runtime::vm;
 use rox::runtime::vm::InterpretResult;
 use rox::runtime::vm::VM;
+use rox::std::VmResult;
+use rox::std::registry::{Arity, Native};
 
 pub const COMPILE_ERR_CODE: i32 = 65;
 pub const RUNTIME_ERR_CODE: i32 = 70;
 pub const FILEIO_ERR_CODE: i32 = 74;
+pub const USAGE_ERR_CODE: i32 = 64;
+/// extension of files holding compiled bytecode, see `Chunk::serialize`.
+pub const BYTECODE_EXT: &str = "roxc";
+
+/// file in the home directory the repl keeps its history in.
+pub const HISTORY_FILE: &str = ".rox_history";
+
+/// line editing for the repl: completes keywords, natives and globals and keeps
+/// reading lines until an entry is complete.
+#[derive(Default)]
+struct LoxHelper {
+    names: Vec<String>,
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(repl::complete(&self.names, line, pos))
+    }
+}
+
+impl Validator for LoxHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        if input.trim_start().starts_with(':') || repl::is_complete(input) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+}
+
+/// runs entries as soon as their brackets are balanced, a line starting with `:`
+/// is a command. Errors are reported and the session goes on.
+pub fn repl(vm: VM) {
+    let mut session = Repl::new(vm);
+    let mut editor: Editor<LoxHelper, DefaultHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Could not start the repl: {}", e);
+            std::process::exit(FILEIO_ERR_CODE);
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        // there is no history yet on the first run.
+        let _ = editor.load_history(path);
+    }
 
-pub fn repl(vm: &mut VM) {
+    // lines of an entry that is not complete yet, only used when stdin is not
+    // a terminal, otherwise the validator keeps the whole entry in the editor.
+    let mut source = String::new();
     loop {
-        println!(">> ");
-        let mut source = String::new();
-        let stdin = io::stdin();
-
-        loop {
-            let mut line = String::new();
-            match stdin.lock().read_line(&mut line) {
-                Ok(0) => break, 
-                Ok(_) => {
-                    if line.trim().is_empty() {
-                        break;
-                    }
-                    source.push_str(&line);
+        editor.set_helper(Some(LoxHelper {
+            names: session.completion_names(),
+        }));
+        let prompt = if source.is_empty() { ">> " } else { ".. " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                source.push_str(&line);
+                source.push('\n');
+                if !source.trim_start().starts_with(':') && !repl::is_complete(&source) {
+                    continue;
                 }
-                Err(error) => eprintln!("error: {error}"),
+            }
+            Err(ReadlineError::Interrupted) => {
+                source.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {e}");
+                break;
             }
         }
 
-        if source.is_empty() {
+        let entry = std::mem::take(&mut source);
+        if entry.trim().is_empty() {
             continue;
         }
-
-        match vm.interpret(source) {
-            InterpretResult::CompileError => std::process::exit(COMPILE_ERR_CODE),
-            InterpretResult::RuntimeError => std::process::exit(RUNTIME_ERR_CODE),
-            InterpretResult::Ok => (),
+        let _ = editor.add_history_entry(entry.trim_end());
+        match session.eval(&entry) {
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) => (),
+            Err(error) => eprintln!("{error}"),
         }
     }
+
+    if let Some(path) = &history
+        && let Err(e) = editor.save_history(path)
+    {
+        eprintln!("Could not save the history: {}", e);
+    }
 }
 
-pub fn run_file(path: &str, vm: &mut VM) {
-    let source = match fs::read_to_string(path) {
+fn read_source(path: &str) -> String {
+    match fs::read_to_string(path) {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Could not open the file: {}", e);
             std::process::exit(FILEIO_ERR_CODE);
         }
+    }
+}
+
+fn is_bytecode(path: &str) -> bool {
+    Path::new(path).extension() == Some(BYTECODE_EXT.as_ref())
+}
+
+/// reads a `.roxc` file, malformed bytecode exits like a compile error.
+fn read_chunk(path: &str) -> Chunk {
+    let bytes = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Could not open the file: {}", e);
+            std::process::exit(FILEIO_ERR_CODE);
+        }
     };
+    match Chunk::deserialize(&bytes) {
+        Ok(chunk) => chunk,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(COMPILE_ERR_CODE);
+        }
+    }
+}
 
-    let result: InterpretResult = vm.interpret(source);
+/// compiles a `.lox` file against the natives of `vm`, or loads and verifies a `.roxc` file.
+fn compile_path(path: &str, vm: &VM) -> Chunk {
+    if is_bytecode(path) {
+        let chunk = read_chunk(path);
+        if let Err(e) = verify(&chunk) {
+            eprintln!("{path}: {e}");
+            std::process::exit(COMPILE_ERR_CODE);
+        }
+        return chunk;
+    }
+
+    let source = read_source(path);
+    match Compiler::compile_with_natives(&source, Rc::new(vm.natives().clone())) {
+        Ok(function) => function.chunk.clone(),
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}\n", diagnostic.render(&source));
+            }
+            std::process::exit(COMPILE_ERR_CODE);
+        }
+    }
+}
+
+pub fn run_file(path: &str, vm: &mut VM) {
+    // the script's imports are resolved relative to it.
+    vm.set_script_path(path);
+    let result: InterpretResult = if is_bytecode(path) {
+        vm.interpret_chunk(read_chunk(path))
+    } else {
+        vm.interpret(read_source(path))
+    };
 
     match result {
         InterpretResult::CompileError => std::process::exit(COMPILE_ERR_CODE),
@@ -61,15 +211,76 @@ pub fn run_file(path: &str, vm: &mut VM) {
     }
 }
 
+/// `rox compile foo.lox [-o foo.roxc]`, the output defaults to the source path
+/// with the bytecode extension.
+pub fn compile_file(input: &str, output: Option<&str>, vm: &VM) {
+    let output: PathBuf = match output {
+        Some(output) => output.into(),
+        None => Path::new(input).with_extension(BYTECODE_EXT),
+    };
+    let chunk = compile_path(input, vm);
+
+    let bytes = match chunk.serialize() {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{input}: {e}");
+            std::process::exit(COMPILE_ERR_CODE);
+        }
+    };
+    if let Err(e) = fs::write(&output, bytes) {
+        eprintln!("Could not write {}: {}", output.display(), e);
+        std::process::exit(FILEIO_ERR_CODE);
+    }
+}
+
+/// `rox disasm foo.lox`, the top-level code followed by every function in it.
+pub fn disassemble(chunk: &Chunk, name: &str) {
+    Chunk::disassemble(chunk, name);
+    for constant in &chunk.constants {
+        if let Value::LoxFunction(function) = constant {
+            disassemble(&function.chunk, function.name.as_deref().unwrap_or(name));
+        }
+    }
+}
+
+/// `os::args()`, the arguments following `--` as a list of strings.
+fn define_os_args(vm: &mut VM, args: Vec<String>) {
+    vm.define_native(Native::host(
+        "os::args",
+        Arity::exactly(0),
+        "the arguments passed to the script after `--`.",
+        move |ctx: &mut NativeContext, _args: &[Value]| -> VmResult {
+            Ok(ctx.into_lox(args.clone()))
+        },
+    ));
+}
+
 fn main() {
-    let mut vm: VM = vm::VM::init();
     let args: Vec<String> = env::args().skip(1).collect::<Vec<String>>();
+    let cli = match Cli::parse(&args) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(USAGE_ERR_CODE);
+        }
+    };
 
-    if args.len() == 0 {
-        repl(&mut vm);
-    } else if args.len() == 1 {
-        run_file(&args[0], &mut vm);
-    } else {
-        eprintln!("Rox does not expect more than one argument.");
+    let mut vm: VM = vm::VM::init();
+    cli.configure(&mut vm);
+    let script_args = match &cli.command {
+        Command::Run { args, .. } => args.clone(),
+        _ => vec![],
+    };
+    define_os_args(&mut vm, script_args);
+
+    match &cli.command {
+        Command::Run { path, .. } => run_file(path, &mut vm),
+        Command::Repl => repl(vm),
+        Command::Help => println!("{USAGE}"),
+        Command::Disasm(path) => disassemble(&compile_path(path, &vm), "script"),
+        Command::Check(path) => {
+            compile_path(path, &vm);
+        }
+        Command::Compile { input, output } => compile_file(input, output.as_deref(), &vm),
     }
 }
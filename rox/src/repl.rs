@@ -0,0 +1,158 @@
+use crate::compile::scanner::{KEYWORDS, Scanner, UNTERMINATED_STRING};
+use crate::compile::token::Kind;
+use crate::core::chunk::Chunk;
+use crate::core::value::Value;
+use crate::runtime::vm::VM;
+use crate::std::lox_errors::{LoxError, VmError};
+
+pub const HELP: &str = "\
+Enter lox source to run it, the value of a trailing expression is printed.
+An entry runs once its brackets and braces are balanced.
+Globals declared in one entry can be used in the next ones.
+Commands:
+  :help          show this message
+  :globals       list the globals defined so far
+  :disasm <fn>   disassemble the function stored in a global
+  :reset         forget every global and start over";
+
+/// a repl session, every entry runs on the same vm.
+pub struct Repl {
+    vm: VM,
+}
+
+impl Repl {
+    pub fn new(vm: VM) -> Self {
+        Self { vm }
+    }
+
+    pub fn vm(&mut self) -> &mut VM {
+        &mut self.vm
+    }
+
+    /// runs one entry, a `:command` or lox source, and returns what should be
+    /// shown on stdout. Errors are returned rendered and leave the session usable.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, String> {
+        let input = input.trim();
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        if input.is_empty() {
+            return Ok(None);
+        }
+
+        // `1 + 2` is as good as `1 + 2;` at the prompt. The `;` goes on a line of its
+        // own so a trailing comment can't swallow it, and entries it doesn't fit
+        // (e.g a declaration ending in a block) are compiled as they were typed.
+        if !ends_with_semicolon(input) {
+            match self.vm.eval(&format!("{}\n;", input)) {
+                Err(LoxError {
+                    kind: VmError::Compile(_),
+                    ..
+                }) => (),
+                result => return Self::shown(result),
+            }
+        }
+        Self::shown(self.vm.eval(input))
+    }
+
+    fn shown(result: Result<Value, LoxError>) -> Result<Option<String>, String> {
+        match result {
+            Ok(Value::Nil) => Ok(None),
+            Ok(value) => Ok(Some(value.to_string())),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    /// keywords, natives and globals, the words tab completion offers.
+    pub fn completion_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = KEYWORDS.iter().map(|k| k.to_string()).collect();
+        names.extend(self.vm.natives().iter().map(|native| native.name.clone()));
+        names.extend(self.vm.script_globals().map(|(name, _)| name));
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn command(&mut self, command: &str) -> Result<Option<String>, String> {
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("help"), None) => Ok(Some(HELP.to_owned())),
+            (Some("globals"), None) => {
+                let mut globals: Vec<String> = self
+                    .vm
+                    .script_globals()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                globals.sort();
+                Ok(Some(globals.join("\n")))
+            }
+            (Some("disasm"), Some(name)) => {
+                let value = self
+                    .vm
+                    .get_global(name)
+                    .ok_or_else(|| format!("Undefined variable '{}'.", name))?;
+                let function = self
+                    .vm
+                    .function_of(&value)
+                    .ok_or_else(|| format!("`{}` is not a function.", name))?;
+                Chunk::disassemble(&function.chunk, name);
+                Ok(None)
+            }
+            (Some("reset"), None) => {
+                self.vm.reset_globals();
+                Ok(Some("Session reset.".to_owned()))
+            }
+            _ => Err(format!("Unknown command `:{}`, see `:help`.", command)),
+        }
+    }
+}
+
+/// true once every bracket and brace `source` opens is closed and no string is
+/// left open, the repl runs an entry as soon as it is complete.
+pub fn is_complete(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut depth: i32 = 0;
+    while let Some(token) = scanner.scan_token() {
+        match token.kind {
+            Kind::LeftParen | Kind::LeftSqBracket | Kind::LeftBrace => depth += 1,
+            Kind::RightParen | Kind::RightSqBracket | Kind::RightBrace => depth -= 1,
+            Kind::Error if token.lexeme == UNTERMINATED_STRING => return false,
+            Kind::EOF => break,
+            _ => (),
+        }
+    }
+    // too many closing brackets is left to the compiler to report.
+    depth <= 0
+}
+
+/// true if the last token of `source`, comments aside, is a `;`.
+fn ends_with_semicolon(source: &str) -> bool {
+    let mut scanner = Scanner::new(source);
+    let mut last = Kind::EOF;
+    while let Some(token) = scanner.scan_token() {
+        if token.kind == Kind::EOF {
+            break;
+        }
+        last = token.kind;
+    }
+    last == Kind::SemiColon
+}
+
+/// the start of the word being typed at `pos` and the `names` completing it.
+pub fn complete(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .find(|&(_, ch)| !(ch.is_alphanumeric() || ch == '_' || ch == ':'))
+        .map_or(0, |(i, ch)| i + ch.len_utf8());
+    let word = &line[start..pos];
+    if word.is_empty() {
+        return (pos, vec![]);
+    }
+    let candidates = names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .cloned()
+        .collect();
+    (start, candidates)
+}
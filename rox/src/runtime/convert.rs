@@ -0,0 +1,139 @@
+use crate::core::value::Value;
+use crate::data_structures::interner;
+use crate::runtime::heap::{GcValue, LoxVec};
+use crate::runtime::vm::VM;
+use crate::std::lox_errors::VmError;
+
+/// conversion of a rust value into a lox `Value`, e.g to pass it to `VM::call_global`.
+/// Heap objects (lists) created by the conversion stay alive until the next call
+/// into the vm has finished.
+pub trait IntoLox {
+    fn into_lox(self, vm: &mut VM) -> Value;
+}
+
+/// conversion of a lox `Value` back into a rust value, failing with a runtime
+/// error when the value has another type.
+pub trait FromLox: Sized {
+    fn from_lox(value: &Value, vm: &VM) -> Result<Self, VmError>;
+}
+
+fn mismatch(expected: &str, value: &Value) -> VmError {
+    VmError::Runtime(format!("Expected {} but got {}.", expected, value))
+}
+
+impl IntoLox for Value {
+    fn into_lox(self, _vm: &mut VM) -> Value {
+        self
+    }
+}
+
+impl FromLox for Value {
+    fn from_lox(value: &Value, _vm: &VM) -> Result<Self, VmError> {
+        Ok(value.clone())
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self, _vm: &mut VM) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Value, _vm: &VM) -> Result<Self, VmError> {
+        value.as_f64().ok_or_else(|| mismatch("a number", value))
+    }
+}
+
+impl IntoLox for i64 {
+    fn into_lox(self, _vm: &mut VM) -> Value {
+        Value::Int(self)
+    }
+}
+
+impl FromLox for i64 {
+    fn from_lox(value: &Value, _vm: &VM) -> Result<Self, VmError> {
+        value
+            .as_whole()
+            .ok_or_else(|| mismatch("a whole number", value))
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self, _vm: &mut VM) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: &Value, _vm: &VM) -> Result<Self, VmError> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            _ => Err(mismatch("a boolean", value)),
+        }
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self, _vm: &mut VM) -> Value {
+        Value::String(interner::intern(self))
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self, vm: &mut VM) -> Value {
+        self.as_str().into_lox(vm)
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Value, _vm: &VM) -> Result<Self, VmError> {
+        match value {
+            Value::String(symbol) => Ok(interner::get_string(*symbol).unwrap_or_default()),
+            _ => Err(mismatch("a string", value)),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self, vm: &mut VM) -> Value {
+        match self {
+            Some(value) => value.into_lox(vm),
+            None => Value::Nil,
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Value, vm: &VM) -> Result<Self, VmError> {
+        match value {
+            Value::Nil => Ok(None),
+            _ => T::from_lox(value, vm).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self, vm: &mut VM) -> Value {
+        // items are pinned as they are converted, nested lists must survive
+        // the allocation of their siblings.
+        let items: Vec<Value> = self
+            .into_iter()
+            .map(|item| {
+                let value = item.into_lox(vm);
+                vm.pin(value)
+            })
+            .collect();
+        let list = vm.alloc_value(GcValue::List(LoxVec(items)));
+        vm.pin(list)
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: &Value, vm: &VM) -> Result<Self, VmError> {
+        match vm.heap_value(value) {
+            Some(GcValue::List(items)) => items.0.iter().map(|v| T::from_lox(v, vm)).collect(),
+            _ => Err(mismatch("a list", value)),
+        }
+    }
+}
@@ -1,31 +1,35 @@
-pub trait Trace {
+pub(crate) trait Trace {
     fn trace(&self, heap: &mut super::heap::Heap);
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum GcMode {
+    /// collect before every allocation.
     Stress,
-    #[default]
+    /// collect on the allocation threshold and log every allocation and cycle.
     Log,
+    /// collect on the allocation threshold only.
+    #[default]
+    Off,
 }
 
 impl GcMode {
-    pub fn start(&self) {
+    pub fn start(&self, log: &mut Vec<String>) {
         if let Self::Log = self {
-            println!("{:-^15}", "gc_begin")
+            log.push(format!("{:-^15}", "gc_begin"))
         }
     }
 
-    pub fn end(&self) {
+    pub fn end(&self, log: &mut Vec<String>) {
         if let Self::Log = self {
-            println!("{:-^15}", "gc_end")
+            log.push(format!("{:-^15}", "gc_end"))
         }
     }
 
-    pub fn info(&self, info: &str) {
+    pub fn info(&self, log: &mut Vec<String>, info: &str) {
         if let Self::Log = self {
-            println!("{}", info)
+            log.push(info.to_owned())
         }
     }
 }
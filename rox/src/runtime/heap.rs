@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::hash::Hash;
 use std::ops::Bound;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 use crate::core::value::ObjId;
@@ -20,6 +21,8 @@ use string_interner::symbol::SymbolU32;
 
 /// Next threshold that triggers gc collection
 const GC_THRESHOLD: usize = 1024 * 1024;
+/// after each cycle the next threshold is scaled by this factor of the surviving bytes.
+const GC_HEAP_GROW_FACTOR: usize = 2;
 
 #[derive(Debug, Clone, Trace)]
 pub(crate) struct GcObject {
@@ -45,7 +48,7 @@ impl GcObject {
 
     // WARNING: always clone the GcOBject before use, because we consume
     // its value
-    pub(crate) fn as_class(self) -> Option<LoxClass> {
+    pub(crate) fn into_class(self) -> Option<LoxClass> {
         if let GcValue::Class(lc) = self.value {
             return Some(lc);
         }
@@ -72,6 +75,30 @@ impl GcObject {
         None
     }
 
+    pub(crate) fn is_map(&self) -> bool {
+        matches!(self.value, GcValue::Map(_))
+    }
+
+    /// a missing key reads as nil, returns None if `key` cannot be hashed.
+    pub(crate) fn get_map_item(&self, key: &Value) -> Option<Value> {
+        if let GcValue::Map(map) = &self.value {
+            let key = MapKey::from_value(key)?;
+            return Some(map.0.get(&key).cloned().unwrap_or_default());
+        }
+        None
+    }
+
+    /// inserts or overwrites the entry, returns false if `key` cannot be hashed.
+    pub(crate) fn set_map_item(&mut self, key: &Value, with: Value) -> bool {
+        if let GcValue::Map(map) = &mut self.value
+            && let Some(key) = MapKey::from_value(key)
+        {
+            map.0.insert(key, with);
+            return true;
+        }
+        false
+    }
+
     pub(crate) fn is_instance(&self) -> bool {
         matches!(self.value, GcValue::Instance(_))
     }
@@ -102,6 +129,51 @@ impl Trace for LoxVec {
     }
 }
 
+/// the hashable subset of `Value`, objects are keyed by identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum MapKey {
+    Nil,
+    Boolean(bool),
+    Number(u64), // bits of the f64, -0.0 is stored as 0.0
+    // ints, and numbers without a fraction, so `m[1]` and `m[1.0]` are the same entry.
+    Int(i64),
+    String(SymbolU32),
+    Object(ObjId),
+}
+
+impl MapKey {
+    pub(crate) fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Nil => Some(Self::Nil),
+            Value::Boolean(b) => Some(Self::Boolean(*b)),
+            // NaN never equals itself, so it could never be looked up again.
+            Value::Number(n) if n.is_nan() => None,
+            Value::Number(n) => match value.as_whole() {
+                Some(i) => Some(Self::Int(i)),
+                None => Some(Self::Number((n + 0.0).to_bits())),
+            },
+            Value::Int(i) => Some(Self::Int(*i)),
+            Value::String(s) => Some(Self::String(*s)),
+            Value::Object(id) => Some(Self::Object(*id)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct LoxMap(pub HashMap<MapKey, Value>);
+
+impl Trace for LoxMap {
+    fn trace(&self, heap: &mut super::heap::Heap) {
+        for (key, value) in self.0.iter() {
+            if let MapKey::Object(id) = key {
+                id.trace(heap);
+            }
+            value.trace(heap);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Trace)]
 pub(crate) struct BoundMethod {
     pub receiver: ObjId, // points to the LoxInstance
@@ -114,6 +186,44 @@ impl BoundMethod {
     }
 }
 
+/// a built-in method of a list or string bound to its receiver, e.g `var push = list.push;`.
+/// calling it dispatches on the receiver just like `list.push(x)` would.
+#[derive(Debug, Clone, Trace)]
+pub(crate) struct NativeMethod {
+    pub receiver: Value,
+    #[unsafe_ignore_trace]
+    pub name: SymbolU32,
+}
+
+/// a file loaded by `import`. Its top-level code runs with `globals` as its global
+/// scope, which importers read through `module.name` or `module::name`.
+#[derive(Debug, Clone)]
+pub(crate) struct LoxModule {
+    pub name: String,
+    pub path: PathBuf,
+    pub globals: HashTable,
+}
+
+impl LoxModule {
+    pub fn new(path: PathBuf) -> Self {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            name,
+            path,
+            globals: HashTable::new(),
+        }
+    }
+}
+
+impl Trace for LoxModule {
+    fn trace(&self, heap: &mut super::heap::Heap) {
+        heap.mark_table(&self.globals);
+    }
+}
+
 /// Classes : are how we create new instances, name required to get instance
 /// contain methods: behavior of Instances
 #[derive(Debug, Clone)]
@@ -196,6 +306,8 @@ pub(crate) struct LoxClosure {
     pub function: Rc<Function>,
     pub upvalues: Vec<ObjId>,
     pub upvalue_count: usize,
+    // the module whose globals the function uses, None for the vm's own globals.
+    pub module: Option<ObjId>,
 }
 
 // NOTE: Tests show its fine to collect closures / functions
@@ -208,6 +320,9 @@ impl Trace for LoxClosure {
         for id in &self.upvalues {
             heap.mark_object(*id);
         }
+        if let Some(module) = self.module {
+            heap.mark_object(module);
+        }
     }
 }
 
@@ -233,6 +348,16 @@ pub(crate) enum UpValueState {
     Closed(Value), // captured after close_upvalues()
 }
 
+// an open upvalue is reached through the stack, but once closed the upvalue
+// is the only owner of the captured value.
+impl Trace for UpValueState {
+    fn trace(&self, heap: &mut super::heap::Heap) {
+        if let Self::Closed(value) = self {
+            value.trace(heap);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Trace)]
 pub(crate) enum GcValue {
     Instance(LoxInstance),
@@ -242,27 +367,35 @@ pub(crate) enum GcValue {
     // will surely not be collected because they are also declared in the global table.
     Class(LoxClass),
     Closure(LoxClosure),
-    #[unsafe_ignore_trace]
     UpValue(UpValueState),
     List(LoxVec),
+    Map(LoxMap),
+    NativeMethod(NativeMethod),
+    Module(LoxModule),
 }
 
 pub(crate) struct Heap {
     pub objects: Vec<Option<GcObject>>,
+    // slots emptied by the last sweep, reused before the objects vec grows.
+    pub free_slots: Vec<usize>,
     pub grey_stack: Vec<ObjId>,
     pub bytes_allocated: usize,
     pub next_gc: usize,
     pub gc_mode: GcMode,
+    // lines logged under `GcMode::Log`, the vm writes them to its output.
+    pub log: Vec<String>,
 }
 
 impl Heap {
     pub fn new(gc_mode: GcMode) -> Self {
         Self {
             objects: vec![],
+            free_slots: vec![],
             grey_stack: vec![],
             bytes_allocated: 0,
             next_gc: GC_THRESHOLD,
             gc_mode,
+            log: vec![],
         }
     }
 
@@ -323,22 +456,25 @@ impl Heap {
     }
 
     pub fn sweep(&mut self) {
-        for slot in self.objects.iter_mut() {
+        let size: usize = std::mem::size_of::<GcObject>();
+        for (index, slot) in self.objects.iter_mut().enumerate() {
             match slot {
                 Some(obj) if obj.is_marked => {
                     obj.is_marked = false; // reset for next cycle
                 }
-                slot => {
+                Some(_) => {
                     if let GcMode::Log = self.gc_mode {
-                        let size: usize = std::mem::size_of::<GcObject>();
-                        println!(
+                        self.log.push(format!(
                             " collected {size} bytes (at {:p} for {:#?}",
                             slot,
                             slot.as_ref()
-                        );
+                        ));
                     }
                     *slot = None;
+                    self.free_slots.push(index);
+                    self.bytes_allocated -= size;
                 }
+                None => (),
             }
         }
     }
@@ -353,26 +489,30 @@ impl Heap {
         }
     }
 
+    /// true when the next allocation should be preceded by a collection.
+    /// The heap cannot see the roots held by the vm, so the vm asks before it allocates.
+    pub fn should_collect(&self) -> bool {
+        matches!(self.gc_mode, GcMode::Stress) || self.bytes_allocated > self.next_gc
+    }
+
+    /// NOTE: allocating never collects, `VM::alloc` runs a cycle first when
+    /// `should_collect()` so that every object reachable from the vm survives.
     pub(crate) fn alloc(&mut self, object: GcObject) -> ObjId {
-        if self.bytes_allocated > self.next_gc {
-            self.collect_garbage();
-        }
         let size = std::mem::size_of::<GcObject>();
-        let mut id: usize = 0;
         // Look for an empty slot first (from a previous sweep)
-        if let Some(slot) = self.objects.iter().position(|s| s.is_none()) {
+        let id: usize = if let Some(slot) = self.free_slots.pop() {
             self.objects[slot] = Some(object);
-            id = slot;
+            slot
         } else {
             // No free slots, grow the vec
             self.objects.push(Some(object));
-            id = self.objects.len() - 1;
-        }
+            self.objects.len() - 1
+        };
 
         self.bytes_allocated += size;
         // NOTE: refactor this, it wastes allocations for other gcmodes.
         let info = format!("allocate {size} bytes {:?}", self.objects[id].as_ref());
-        self.gc_mode.info(&info);
+        self.gc_mode.info(&mut self.log, &info);
         ObjId(id)
     }
 
@@ -415,20 +555,10 @@ impl Heap {
         }
     }
 
-    pub fn alloc_closure(&mut self, closure: LoxClosure) -> ObjId {
-        if self.bytes_allocated > self.next_gc {
-            self.collect_garbage();
-        }
-        self.alloc(GcObject {
-            is_marked: false,
-            value: GcValue::Closure(closure),
-        })
-    }
-
     pub fn orchestrate_inherit(&mut self, superclass: ObjId, subclass: ObjId) -> bool {
         let super_obj = self.objects[superclass.0].clone();
-        if super_obj.is_some() {
-            if let Some(supa) = super_obj.unwrap().as_class()
+        if let Some(super_obj) = super_obj {
+            if let Some(supa) = super_obj.into_class()
                 && let GcValue::Class(sub) = &mut self.objects[subclass.0].as_mut().unwrap().value
             {
                 sub.methods.add_all(supa.methods);
@@ -439,8 +569,25 @@ impl Heap {
         false
     }
 
-    fn collect_garbage(&mut self) {
-        self.gc_mode.start();
-        self.gc_mode.end();
+    /// mark-sweep cycle starting from `roots`, the caller (vm) is responsible
+    /// for handing over every object it can still reach.
+    pub fn collect_garbage(&mut self, roots: impl Iterator<Item = ObjId>) {
+        self.gc_mode.start(&mut self.log);
+        let before = self.bytes_allocated;
+
+        self.mark_roots(roots);
+        self.trace_references();
+        self.sweep();
+        self.next_gc = (self.bytes_allocated * GC_HEAP_GROW_FACTOR).max(GC_THRESHOLD);
+
+        let info = format!(
+            "collected {} bytes (from {} to {}) next at {}",
+            before - self.bytes_allocated,
+            before,
+            self.bytes_allocated,
+            self.next_gc
+        );
+        self.gc_mode.info(&mut self.log, &info);
+        self.gc_mode.end(&mut self.log);
     }
 }
@@ -1,6 +1,5 @@
 use crate::core::chunk::Chunk;
 use crate::core::value::ObjId;
-use crate::runtime::heap::{GcValue, Heap};
 use std::fmt::Display;
 
 /// NOTE: move to object.rs once complexity increases.
@@ -21,6 +20,8 @@ impl Function {
         self.chunk.code.shrink_to_fit();
         self.chunk.constants.shrink_to_fit();
         self.chunk.lines.shrink_to_fit();
+        self.chunk.columns.shrink_to_fit();
+        self.chunk.handlers.shrink_to_fit();
     }
 }
 
@@ -42,17 +43,8 @@ pub struct CallFrame {
     pub closure_id: ObjId, // object id as pointer into the Heap datastructure
     pub ip: usize,
     pub slots: usize, // offset
-}
-
-impl CallFrame {
-    /// this is required to know if the operand to an opcode is the
-    /// next byte or the next three bytes (lots of constants in chunks.)
-    pub fn is_long(&self, heap: &Heap) -> bool {
-        match heap.get(self.closure_id).value {
-            GcValue::Closure(ref closure) => self.ip >= closure.function.chunk.index_const24,
-            _ => panic!("Expected to find a closure"),
-        }
-    }
+    // the module of the closure, whose globals the frame uses instead of the vm's.
+    pub module: Option<ObjId>,
 }
 
 impl Display for Function {
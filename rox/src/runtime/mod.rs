@@ -1,4 +1,7 @@
+pub mod convert;
 pub mod gc;
 pub mod heap;
 pub mod lang;
+pub mod native;
+pub mod output;
 pub mod vm;
@@ -0,0 +1,93 @@
+use crate::core::value::Value;
+use crate::data_structures::interner;
+use crate::runtime::convert::{FromLox, IntoLox};
+use crate::runtime::heap::{GcValue, LoxVec};
+use crate::runtime::vm::VM;
+use crate::std::VmResult;
+use crate::std::lox_errors::VmError;
+
+/// what a `HostFn` gets to reach the vm while it runs: the heap, the interner and
+/// lox functions it may call back into.
+/// Objects handed out by the context (new lists, results of `call`) stay rooted
+/// until the native returns, so they are safe to hold on to during the call.
+pub struct NativeContext<'vm> {
+    vm: &'vm mut VM,
+}
+
+impl<'vm> NativeContext<'vm> {
+    pub(crate) fn new(vm: &'vm mut VM) -> Self {
+        Self { vm }
+    }
+
+    pub fn intern(&self, string: &str) -> Value {
+        Value::String(interner::intern(string))
+    }
+
+    /// the contents of a string value.
+    pub fn as_string(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::String(symbol) => interner::get_string(*symbol),
+            _ => None,
+        }
+    }
+
+    /// writes to the vm's stdout, which an embedder may have redirected.
+    pub fn write_out(&mut self, text: &str) -> Result<(), VmError> {
+        self.vm
+            .write_out(text)
+            .map_err(|e| VmError::Native(e.to_string()))
+    }
+
+    /// writes to the vm's stderr, which an embedder may have redirected.
+    pub fn write_err(&mut self, text: &str) -> Result<(), VmError> {
+        self.vm
+            .write_err(text)
+            .map_err(|e| VmError::Native(e.to_string()))
+    }
+
+    pub fn from_lox<T: FromLox>(&self, value: &Value) -> Result<T, VmError> {
+        T::from_lox(value, self.vm)
+    }
+
+    pub fn into_lox(&mut self, value: impl IntoLox) -> Value {
+        value.into_lox(self.vm)
+    }
+
+    pub fn alloc_list(&mut self, items: Vec<Value>) -> Value {
+        self.vm.alloc_for_native(GcValue::List(LoxVec(items)))
+    }
+
+    /// a copy of the items of a list value.
+    pub fn list_items(&self, list: &Value) -> Option<Vec<Value>> {
+        match self.vm.heap_value(list)? {
+            GcValue::List(items) => Some(items.0.clone()),
+            _ => None,
+        }
+    }
+
+    /// reads a field of an instance value.
+    pub fn get_field(&self, instance: &Value, name: &str) -> Option<Value> {
+        match self.vm.heap_value(instance)? {
+            GcValue::Instance(instance) => instance.get_field(interner::intern(name)),
+            _ => None,
+        }
+    }
+
+    /// sets a field of an instance value, false if `instance` is not one.
+    pub fn set_field(&mut self, instance: &Value, name: &str, value: Value) -> bool {
+        match self.vm.heap_value_mut(instance) {
+            Some(GcValue::Instance(instance)) => {
+                instance.set_field(interner::intern(name), value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// calls a lox function, class, bound method or native with `args` and
+    /// returns its result. A runtime error in the callee should be returned
+    /// from the native, the vm has already unwound by then.
+    pub fn call(&mut self, callee: &Value, args: &[Value]) -> VmResult {
+        self.vm.call_from_native(callee.clone(), args)
+    }
+}
@@ -1,31 +1,49 @@
 #![allow(unused)]
 use core::panic;
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::hash::Hash;
-use std::ops::{Add, Div, Mul, Sub};
+use std::io::{self, Write};
+use std::ops::{Add, Div, Mul, Rem, Sub};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use string_interner::Symbol;
 use string_interner::symbol::{self, SymbolU32};
 
-use crate::compile::compiler::{Compiler, LONG_ARG_INDEX};
+use crate::compile::compiler::{Compiler, KnownGlobals, LONG_ARG_INDEX};
+use crate::compile::diagnostic::Diagnostic;
 use crate::core::chunk::Chunk;
 use crate::core::opcode::OpCode;
 use crate::core::value::{NativeFn, ObjId, Value};
+use crate::core::verify;
 use crate::data_structures::interner::{self};
 use crate::data_structures::map::HashTable;
-use crate::runtime::gc::{self, Trace};
+use crate::runtime::gc::{self, GcMode, Trace};
 use crate::runtime::heap::{
-    GcObject, GcValue, Heap, LoxClass, LoxClosure, LoxInstance, LoxVec, UpValueState,
+    BoundMethod, GcObject, GcValue, Heap, LoxClass, LoxClosure, LoxInstance, LoxMap, LoxModule,
+    LoxVec, MapKey, NativeMethod, UpValueState,
 };
 use crate::runtime::lang::CallFrame;
 use crate::runtime::lang::Function;
-use crate::std::{io, math, strings, time};
+use crate::runtime::native::NativeContext;
+use crate::std::lists;
+use crate::std::lox_errors::{LoxError, TraceFrame, VmError};
+use crate::std::registry::{Native, NativeRegistry};
+use crate::std::strings;
+use crate::std::{NativeResult, NativeValue, VmResult};
 
 pub const DEBUG_TRACE: bool = false;
 pub const FRAMES_MAX: usize = 64;
-pub const STACK_MAX: usize = 256; // update to FRAMES_MAX * UINT8_COUNT
+pub const STACK_MAX: usize = FRAMES_MAX * 256; // FRAMES_MAX * UINT8_COUNT
 pub const INIT: &str = "init"; // update to FRAMES_MAX * UINT8_COUNT
+const INVALID_MAP_KEY: &str = "Map keys must be nil, booleans, numbers, strings or objects.";
+const INTEGER_OVERFLOW: &str = "Integer overflow.";
+const INVALID_LIST_ACCESS: &str = "Invalid array access operation";
+// the class of the instances runtime errors are caught as, and their fields.
+const ERROR_CLASS: &str = "Error";
+const ERROR_MESSAGE: &str = "message";
+const ERROR_STACK: &str = "stack";
 
 #[derive(Debug, PartialEq)]
 #[repr(u8)]
@@ -45,6 +63,41 @@ pub struct VM {
     pub open_upvalues: HashMap<usize, ObjId>,
     heap: Heap,             // dummy: *mut * mut Value
     init_symbol: SymbolU32, // `this` keyword
+    // the last runtime error raised by `run`, handed out by `interpret_with_diagnostics`.
+    error: Option<LoxError>,
+    // shared with the compiler, which resolves native names against it.
+    natives: Rc<NativeRegistry>,
+    // `run` returns once a `Return` brings the frames back down to this depth,
+    // it is raised while a native calls back into lox.
+    frame_floor: usize,
+    // globals compiled scripts have declared, so later scripts may use them.
+    known_globals: KnownGlobals,
+    // limits checked on every call, exceeding them is a stack overflow.
+    max_frames: usize,
+    stack_size: usize,
+    // disassemble every instruction with the stack before it runs.
+    trace: bool,
+    // where `print` and the gc log go, and where errors are reported.
+    out: Box<dyn Write>,
+    err: Box<dyn Write>,
+    // objects created by `IntoLox` for the embedder, rooted until the next call
+    // into the vm has finished.
+    pinned: Vec<Value>,
+    // the value of the `throw` that raised `error`, None for errors raised by the vm.
+    thrown: Option<Value>,
+    // the error (and thrown value) a failed call back into lox handed to a native,
+    // raised again with its own stack trace if the native returns it.
+    callback_error: Option<(LoxError, Option<Value>)>,
+    // created the first time a runtime error is caught.
+    error_class: Option<ObjId>,
+    // modules already imported by their canonical path, a file only runs once.
+    modules: HashMap<PathBuf, ObjId>,
+    // the modules whose top-level code is running, outermost first.
+    importing: Vec<PathBuf>,
+    // searched in order for a module that is not next to the file importing it.
+    module_paths: Vec<PathBuf>,
+    // the file scripts are read from, their imports are resolved relative to it.
+    script_path: Option<PathBuf>,
 }
 
 impl Default for VM {
@@ -56,17 +109,15 @@ impl Default for VM {
 impl VM {
     /// creates a new vm and defines some native functions supported
     pub fn init() -> Self {
+        Self::with_natives(NativeRegistry::standard())
+    }
+
+    /// a vm whose globals hold every native of `natives`.
+    pub fn with_natives(natives: NativeRegistry) -> Self {
         let mut v = Self::new();
-        v.define_native("io::readNumber".to_owned(), NativeFn(io::read_number));
-        v.define_native("io::readLine".to_owned(), NativeFn(io::read_line));
-        v.define_native("time::clock".to_owned(), NativeFn(time::clock));
-        v.define_native("math::sqrt".to_owned(), NativeFn(math::sqrt));
-        v.define_native("math::max".to_owned(), NativeFn(math::max));
-        v.define_native("math::pow".to_owned(), NativeFn(math::pow));
-        v.define_native("strings::str_cmp".to_owned(), NativeFn(strings::str_cmp));
-        v.define_native("strings::str_len".to_owned(), NativeFn(strings::str_len));
-        v.define_native("utils::list_len".to_owned(), NativeFn(strings::str_len));
-        v.define_native("clock".to_owned(), NativeFn(time::clock));
+        for native in natives.iter() {
+            v.define_native(native.clone());
+        }
         v.reset_stack();
 
         v
@@ -78,37 +129,261 @@ impl VM {
             globals: HashTable::new(),
             call_frames: Vec::with_capacity(FRAMES_MAX),
             open_upvalues: HashMap::new(),
-            heap: Heap::new(super::gc::GcMode::Stress),
+            heap: Heap::new(GcMode::default()),
             init_symbol: interner::intern(INIT),
+            error: None,
+            natives: Rc::new(NativeRegistry::new()),
+            frame_floor: 0,
+            known_globals: KnownGlobals::new(),
+            max_frames: FRAMES_MAX,
+            stack_size: STACK_MAX,
+            trace: cfg!(feature = "debug_trace_execution") && DEBUG_TRACE,
+            out: Box::new(io::stdout()),
+            err: Box::new(io::stderr()),
+            pinned: Vec::new(),
+            thrown: None,
+            callback_error: None,
+            error_class: None,
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            module_paths: Vec::new(),
+            script_path: None,
         }
     }
 
+    /// sends the output of `print` and `io::write`, and the gc log, to `out`, and
+    /// errors reported by `interpret` and `io::writeError` to `err`, instead of
+    /// stdout and stderr.
+    pub fn with_output(mut self, out: impl Write + 'static, err: impl Write + 'static) -> Self {
+        self.out = Box::new(out);
+        self.err = Box::new(err);
+        self
+    }
+
+    /// writes to the vm's stdout, see `with_output`.
+    pub fn write_out(&mut self, text: &str) -> std::io::Result<()> {
+        self.out.write_all(text.as_bytes())?;
+        self.out.flush()
+    }
+
+    /// writes to the vm's stderr, see `with_output`.
+    pub fn write_err(&mut self, text: &str) -> std::io::Result<()> {
+        self.err.write_all(text.as_bytes())?;
+        self.err.flush()
+    }
+
+    /// `GcMode::Stress` collects before every allocation which is useful to
+    /// flush out objects the vm forgot to root.
+    pub fn set_gc_mode(&mut self, gc_mode: GcMode) {
+        self.heap.gc_mode = gc_mode;
+    }
+
+    /// the deepest the call stack may get, 64 by default.
+    pub fn set_max_frames(&mut self, max_frames: usize) {
+        self.max_frames = max_frames;
+    }
+
+    /// the most values the stack may hold when a function is called.
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_size = stack_size;
+    }
+
+    /// prints every instruction, and the stack it runs on, to the vm's output.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// a directory `import` searches when a module is not found next to the file
+    /// importing it, directories are searched in the order they were added.
+    pub fn add_module_path(&mut self, dir: impl Into<PathBuf>) {
+        self.module_paths.push(dir.into());
+    }
+
+    /// the file the scripts run next were read from, so their imports are resolved
+    /// relative to it rather than to the working directory.
+    pub fn set_script_path(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        self.script_path = Some(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    }
+
+    /// bytes currently held by live (or not yet swept) heap objects.
+    pub fn bytes_allocated(&self) -> usize {
+        self.heap.bytes_allocated
+    }
+
     fn reset_stack(&mut self) {
         self.stack.clear();
     }
 
+    /// runs `source` and reports compile errors or runtime errors with their
+    /// stack trace on stderr.
+    pub fn interpret(&mut self, source: String) -> InterpretResult {
+        let result = self.interpret_with_diagnostics(source);
+        self.report(result)
+    }
+
+    /// runs the top-level chunk of a script compiled ahead of time (see `Chunk::deserialize`).
+    /// The chunk is verified first, malformed bytecode is a compile error.
+    pub fn interpret_chunk(&mut self, chunk: Chunk) -> InterpretResult {
+        let result = match verify::verify(&chunk) {
+            Ok(()) => self
+                .run_script(Rc::new(Function {
+                    chunk,
+                    ..Function::new()
+                }))
+                .map(|_| ()),
+            Err(e) => Err(LoxError::new(VmError::InvalidBytecode(e), vec![])),
+        };
+        self.report(result)
+    }
+
+    fn report(&mut self, result: Result<(), LoxError>) -> InterpretResult {
+        match result {
+            Ok(()) => InterpretResult::Ok,
+            Err(error) => {
+                let _ = self.write_err(&format!("{}\n", error));
+                match error.kind {
+                    VmError::Compile(_) | VmError::InvalidBytecode(_) => {
+                        InterpretResult::CompileError
+                    }
+                    _ => InterpretResult::RuntimeError,
+                }
+            }
+        }
+    }
+
+    /// like `interpret` but hands the error back to the caller instead of printing it.
+    /// The vm is left in a clean state and can be reused after an error.
+    /// Compile errors are rendered with their source snippet into `VmError::Compile`,
+    /// use `Compiler::compile` directly to get the `Diagnostic`s themselves.
+    pub fn interpret_with_diagnostics(&mut self, source: String) -> Result<(), LoxError> {
+        self.sync_known_globals();
+        let function =
+            Compiler::compile_with_globals(&source, self.natives.clone(), &mut self.known_globals)
+                .map_err(|diagnostics| Self::compile_error(&source, diagnostics))?;
+        self.run_script(function).map(|_| ())
+    }
+
+    /// runs `source` as one entry of a repl session and returns the value of
+    /// the expression statement ending it, nil if there is none.
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        self.sync_known_globals();
+        let function =
+            Compiler::compile_repl_entry(source, self.natives.clone(), &mut self.known_globals)
+                .map_err(|diagnostics| Self::compile_error(source, diagnostics))?;
+        self.run_script(function)
+    }
+
+    fn compile_error(source: &str, diagnostics: Vec<Diagnostic>) -> LoxError {
+        let rendered: Vec<String> = diagnostics.iter().map(|d| d.render(source)).collect();
+        LoxError::new(VmError::Compile(rendered.join("\n\n")), vec![])
+    }
+
+    /// calls the function, class or native stored in the global `name`, usually
+    /// one a script defined earlier, and returns its result.
+    /// NOTE: a returned object is only kept alive while something in lox refers to it.
+    pub fn call_global(&mut self, name: &str, args: &[Value]) -> Result<Value, LoxError> {
+        let Some(callee) = self.get_global(name) else {
+            let msg = format!("Undefined variable '{}'.", name);
+            return Err(LoxError::new(VmError::Runtime(msg), vec![]));
+        };
+        let result = if self.call_to_completion(callee, args) {
+            Ok(self.stack.pop().unwrap_or_default())
+        } else {
+            self.unwind();
+            Err(self.error.take().unwrap_or_else(|| {
+                LoxError::new(
+                    VmError::Runtime("Unknown runtime error.".to_string()),
+                    vec![],
+                )
+            }))
+        };
+        self.pinned.clear();
+        result
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(interner::get_symbol(name)?)
+    }
+
+    /// defines or overwrites the global `name`, scripts compiled afterwards can use it.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(interner::intern(name), value);
+    }
+
+    /// every global with its name, natives included.
+    pub fn globals(&self) -> impl Iterator<Item = (String, Value)> + '_ {
+        self.globals.iter().map(|entry| {
+            let name = interner::get_string(*entry.get_key()).unwrap_or_default();
+            (name, entry.get_value().clone())
+        })
+    }
+
+    /// the globals scripts or the embedder defined, natives are only included
+    /// if a script has redefined them.
+    pub fn script_globals(&self) -> impl Iterator<Item = (String, Value)> + '_ {
+        self.globals().filter(|(name, value)| {
+            !self
+                .natives
+                .get(name)
+                .is_some_and(|native| native.value() == *value)
+        })
+    }
+
+    /// the compiled function of a closure (or function) value.
+    pub fn function_of(&self, value: &Value) -> Option<Rc<Function>> {
+        match value {
+            Value::LoxFunction(function) => Some(function.clone()),
+            Value::Object(id) => self.heap.get(*id).as_function(),
+            _ => None,
+        }
+    }
+
+    /// records globals defined at runtime, e.g by `set_global`, as known to the
+    /// compiler. Natives are left to the registry, which also knows their arity.
+    fn sync_known_globals(&mut self) {
+        let defined: Vec<String> = self.script_globals().map(|(name, _)| name).collect();
+        for name in defined {
+            self.known_globals.entry(name).or_insert(false);
+        }
+    }
+
+    /// runs a compiled script and returns the value it returned.
+    fn run_script(&mut self, function: Rc<Function>) -> Result<Value, LoxError> {
+        let result = self.run_function(function);
+        self.pinned.clear();
+        match result {
+            // the script's return value was left on the stack.
+            InterpretResult::Ok => Ok(self.stack.pop().unwrap_or_default()),
+            _ => Err(self.error.take().unwrap_or_else(|| {
+                LoxError::new(
+                    VmError::Runtime("Unknown runtime error.".to_string()),
+                    vec![],
+                )
+            })),
+        }
+    }
+
     /// NOTE: `Clox` often pushes values on the stack to guard against garbage collection,
     /// `RlOX` however triggers garbage collection on only heap allocation, if `Heap::alloc()`
     /// is not called, garbage collection never happens. So we do not need this preemptive stack
-    pub fn interpret(&mut self, source: String) -> InterpretResult {
-        match Compiler::compile(&source) {
-            None => InterpretResult::CompileError,
-            Some(func) => {
-                #[cfg(feature = "")]
-                println!("{}", func.chunk);
-
-                let func_clone: Rc<Function> = Rc::clone(&func);
-                let cloj_id = self.heap.alloc_closure(LoxClosure {
-                    function: func.clone(),
-                    upvalues: vec![],
-                    upvalue_count: 0,
-                });
-
-                self.stack.push(Value::Object(cloj_id));
-                self.call(&func, ObjId(0), 0);
-                self.run()
-            }
+    fn run_function(&mut self, func: Rc<Function>) -> InterpretResult {
+        #[cfg(feature = "debug_print_code")]
+        let _ = self.write_out(&format!("{}\n", func.chunk));
+
+        let cloj_id = self.alloc(GcValue::Closure(LoxClosure {
+            function: func.clone(),
+            upvalues: vec![],
+            upvalue_count: 0,
+            module: None,
+        }));
+
+        self.stack.push(Value::Object(cloj_id));
+        if !self.call(&func, cloj_id, 0) {
+            self.unwind();
+            return InterpretResult::RuntimeError;
         }
+        self.run()
     }
 
     pub fn push_value(&mut self, value: Value) {
@@ -151,31 +426,261 @@ impl VM {
         }
     }
 
+    /// the globals the current frame defines, reads and assigns, those of its module
+    /// or the vm's own.
+    fn frame_globals(&self) -> &HashTable {
+        match self.get_current_frame().module {
+            Some(id) => match &self.heap.get(id).value {
+                GcValue::Module(module) => &module.globals,
+                _ => panic!("expected a module"),
+            },
+            None => &self.globals,
+        }
+    }
+
+    fn frame_globals_mut(&mut self) -> &mut HashTable {
+        match self.get_current_frame().module {
+            Some(id) => match &mut self.heap.get_mut(id).value {
+                GcValue::Module(module) => &mut module.globals,
+                _ => panic!("expected a module"),
+            },
+            None => &mut self.globals,
+        }
+    }
+
+    /// pushes the module loaded from `path`. The first import of a file compiles it
+    /// and runs its top-level code with the module's globals, later ones reuse it.
+    fn import(&mut self, path: SymbolU32) -> bool {
+        let path = interner::get_string(path).unwrap_or_default();
+        let Some(resolved) = self.resolve_module(&path) else {
+            self.runtime_error(&format!("Can't find module '{}'.", path));
+            return false;
+        };
+        if let Some(&module) = self.modules.get(&resolved) {
+            self.stack.push(Value::Object(module));
+            return true;
+        }
+
+        // the script itself is where the chain of imports starts.
+        let chain: Vec<&PathBuf> = self.script_path.iter().chain(&self.importing).collect();
+        if let Some(start) = chain.iter().position(|&p| *p == resolved) {
+            let cycle: Vec<String> = chain[start..]
+                .iter()
+                .chain([&&resolved])
+                .map(|p| p.display().to_string())
+                .collect();
+            self.runtime_error(&format!("Import cycle: {}.", cycle.join(" -> ")));
+            return false;
+        }
+
+        let source = match fs::read_to_string(&resolved) {
+            Ok(source) => source,
+            Err(e) => {
+                self.runtime_error(&format!("Could not read module '{}': {}.", path, e));
+                return false;
+            }
+        };
+        let function = match Compiler::compile_module(&source, self.natives.clone(), &resolved) {
+            Ok(function) => function,
+            Err(diagnostics) => {
+                let rendered: Vec<String> = diagnostics.iter().map(|d| d.render(&source)).collect();
+                let msg = format!("in module '{}':\n{}", path, rendered.join("\n\n"));
+                self.report_error(VmError::Compile(msg));
+                return false;
+            }
+        };
+
+        // the module sits where the import leaves it, rooted while its code runs.
+        let module = self.alloc(GcValue::Module(LoxModule::new(resolved.clone())));
+        self.stack.push(Value::Object(module));
+        let closure = self.alloc(GcValue::Closure(LoxClosure {
+            function,
+            upvalues: vec![],
+            upvalue_count: 0,
+            module: Some(module),
+        }));
+        self.importing.push(resolved.clone());
+        let ran = self.call_to_completion(Value::Object(closure), &[]);
+        self.importing.pop();
+        if !ran {
+            return false;
+        }
+        self.stack.pop(); // what the module's top-level code returned.
+        self.modules.insert(resolved, module);
+        true
+    }
+
+    /// finds `path` relative to the directory of the file importing it, then in each
+    /// module path. Modules are known by their canonical path.
+    fn resolve_module(&self, path: &str) -> Option<PathBuf> {
+        let importer = match self.get_current_frame().module {
+            Some(id) => match &self.heap.get(id).value {
+                GcValue::Module(module) => Some(module.path.as_path()),
+                _ => None,
+            },
+            None => self.script_path.as_deref(),
+        };
+        // an empty base leaves `path` relative to the working directory.
+        let base = importer.and_then(Path::parent).unwrap_or(Path::new(""));
+        std::iter::once(base)
+            .chain(self.module_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(path))
+            .find(|candidate| candidate.is_file())
+            .and_then(|found| found.canonicalize().ok())
+    }
+
+    /// runs until the frame that started this run returns, or an error no `catch`
+    /// handles unwinds it.
     fn run(&mut self) -> InterpretResult {
-        #[cfg(feature = "")]
+        loop {
+            match self.execute() {
+                InterpretResult::RuntimeError if self.catch_error() => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// looks for a handler of the error just raised in the frames of this run, innermost
+    /// first, and continues there with the error on the stack. Frames without one are
+    /// popped, all of them if no handler is found.
+    fn catch_error(&mut self) -> bool {
+        let catchable = self.error.is_some();
+        while catchable && self.call_frames.len() > self.frame_floor {
+            let frame = *self.get_current_frame();
+            let function = self.get_frame_closure(frame.closure_id).function.clone();
+            // ip is already past the instruction that failed.
+            if let Some(handler) = function.chunk.handler_for(frame.ip.saturating_sub(1)) {
+                let error = self.error_value();
+                let base = frame.slots + handler.depth as usize;
+                self.close_upvalues(base);
+                self.stack.truncate(base);
+                self.stack.push(error);
+                self.get_current_frame_mut().ip = handler.target as usize;
+                return true;
+            }
+            self.call_frames.pop();
+            self.close_upvalues(frame.slots);
+            self.stack.truncate(frame.slots);
+        }
+        // the native that started this run hands the error on to the frames below it.
+        if self.frame_floor == 0 {
+            self.unwind();
+        }
+        false
+    }
+
+    /// the value a `catch` receives: what was thrown, or an `Error` instance with the
+    /// message and stack trace of an error raised by the vm.
+    fn error_value(&mut self) -> Value {
+        let error = self.error.take();
+        if let Some(thrown) = self.thrown.take() {
+            return thrown;
+        }
+        let Some(error) = error else {
+            return Value::Nil;
+        };
+
+        let class = match self.error_class {
+            Some(class) => class,
+            None => {
+                let class = self.alloc(GcValue::Class(LoxClass::new(ERROR_CLASS.to_owned())));
+                self.error_class = Some(class);
+                class
+            }
+        };
+        let frames: Vec<Value> = error
+            .stack
+            .iter()
+            .map(|frame| Value::String(interner::intern(&frame.to_string())))
+            .collect();
+        let stack = self.alloc(GcValue::List(LoxVec(frames)));
+        let mut instance = LoxInstance::new(class);
+        instance.set_field(
+            interner::intern(ERROR_MESSAGE),
+            Value::String(interner::intern(&error.message)),
+        );
+        instance.set_field(interner::intern(ERROR_STACK), Value::Object(stack));
+        // the stack trace is only reachable from the stack while the instance is allocated.
+        self.stack.push(Value::Object(stack));
+        let id = self.alloc(GcValue::Instance(instance));
+        self.stack.pop();
+        Value::Object(id)
+    }
+
+    /// the error reported when `value` is thrown and never caught. A caught runtime
+    /// error thrown again keeps its message.
+    fn thrown_error(&self, value: &Value) -> VmError {
+        if let Value::Object(id) = value
+            && let GcValue::Instance(instance) = &self.heap.get(*id).value
+            && let Some(message) = instance.get_field(interner::intern(ERROR_MESSAGE))
+        {
+            return match message {
+                Value::String(symbol) if self.error_class == Some(instance.class) => {
+                    VmError::Runtime(interner::get_string(symbol).unwrap_or_default())
+                }
+                message => VmError::Thrown(message.to_string()),
+            };
+        }
+        VmError::Thrown(value.to_string())
+    }
+
+    /// clears what the failed run left behind so the vm can be reused.
+    fn unwind(&mut self) {
+        self.reset_stack();
+        self.call_frames.clear();
+        self.open_upvalues.clear();
+        self.thrown = None;
+        self.callback_error = None;
+        self.importing.clear();
+    }
+
+    fn execute(&mut self) -> InterpretResult {
+        #[cfg(feature = "debug_trace_execution")]
         if DEBUG_TRACE {
+            let mut dump = String::new();
             for v in &self.stack {
                 if let Value::Object(id) = v {
-                    println!("{} => {:?}", v, self.heap.get(*id));
+                    dump.push_str(&format!("{} => {:?}\n", v, self.heap.get(*id)));
                 } else {
-                    println!("{}", v);
+                    dump.push_str(&format!("{}\n", v));
                 }
             }
+            let _ = self.write_out(&dump);
         }
 
         loop {
-            #[cfg(feature = "")]
-            if DEBUG_TRACE {
+            if self.trace {
+                let stack: String = self.stack.iter().map(|v| format!("[ {} ]", v)).collect();
+                let mut trace = format!("          {}\n", stack);
                 let start = self.get_current_frame().ip;
-                Chunk::disassemble_instruction(self.current_chunk(), start);
+                Chunk::disassemble_instruction(self.current_chunk(), start, &mut trace);
+                // a trace that can't be written is dropped rather than failing the program.
+                let _ = self.write_out(&trace);
             }
 
             // short lived borrows because borrow checker complains about
             // when explicitly borrowed let s = last();
             // self.get_current_frame_mut().ip += 1; // point to the next instruction to read.
-            let instruction: OpCode = OpCode::try_from(self.read_byte()).expect("");
+            let Ok(mut instruction) = OpCode::try_from(self.read_byte()) else {
+                let ip = self.get_current_frame().ip;
+                let byte = self.current_chunk().code[ip - 1];
+                self.report_error(VmError::InvalidOpcode(byte));
+                return InterpretResult::RuntimeError;
+            };
+            // a `Wide` prefix widens the index operand of the instruction after it.
+            let wide = matches!(instruction, OpCode::Wide);
+            if wide {
+                match OpCode::try_from(self.read_byte()) {
+                    Ok(op) if !matches!(op, OpCode::Wide) => instruction = op,
+                    _ => {
+                        self.report_error(VmError::InvalidOpcode(OpCode::Wide as u8));
+                        return InterpretResult::RuntimeError;
+                    }
+                }
+            }
 
             match instruction {
+                OpCode::Wide => unreachable!("`Wide` is decoded with the instruction it prefixes."),
                 OpCode::Return => {
                     if let Some(result) = self.stack.pop() {
                         let frame = self.call_frames.pop().unwrap();
@@ -185,27 +690,30 @@ impl VM {
                         // truncate frame back to where this frame started
                         self.stack.truncate(base);
 
-                        if self.call_frames.is_empty() {
+                        if self.call_frames.len() == self.frame_floor {
+                            // hand the result to whoever started this run.
+                            self.stack.push(result);
                             return InterpretResult::Ok;
                         }
                         self.stack.push(result);
                     } else {
+                        self.runtime_error("Stack underflow.");
                         return InterpretResult::RuntimeError;
                     }
                 }
                 OpCode::Invoke => {
-                    let name = self.read_string().unwrap();
+                    let name = self.read_string(wide).unwrap();
                     let arg_count = self.read_byte();
                     if !self.invoke(name, arg_count) {
                         return InterpretResult::RuntimeError;
                     }
                 }
                 OpCode::Constant => {
-                    let constant: Value = self.read_constant();
+                    let constant: Value = self.read_constant(wide);
                     self.stack.push(constant);
                 }
                 OpCode::Constant24 => {
-                    let constant: Value = self.read_constant();
+                    let constant: Value = self.read_constant(true);
                     self.stack.push(constant);
                 }
                 OpCode::Negate => {
@@ -214,22 +722,44 @@ impl VM {
                         return InterpretResult::RuntimeError;
                     } else {
                         let num_value = self.stack.pop().unwrap();
-                        self.stack.push((-num_value).unwrap());
+                        match -num_value {
+                            Some(negated) => self.stack.push(negated),
+                            None => {
+                                self.runtime_error(INTEGER_OVERFLOW);
+                                return InterpretResult::RuntimeError;
+                            }
+                        }
                     }
                 }
                 OpCode::Add
                 | OpCode::Divide
                 | OpCode::Multiply
                 | OpCode::Subtract
+                | OpCode::Modulo
                 | OpCode::Greater
                 | OpCode::Less => {
                     // Clox uses peek here to guard against gc, but this is fine for us because
                     // collection cannot be triggered here.
                     let rhs = self.stack.pop().unwrap();
                     let lhs = self.stack.pop().unwrap();
+                    // only integer arithmetic fails on numbers.
+                    let ints = matches!((&lhs, &rhs), (Value::Int(_), Value::Int(_)));
+                    let by_zero = matches!(rhs, Value::Int(0));
                     match Self::binary_op(lhs, rhs, instruction) {
                         Some(result) => self.stack.push(result),
-                        None => return InterpretResult::RuntimeError,
+                        None => {
+                            let msg = if ints && by_zero {
+                                "Division by zero."
+                            } else if ints {
+                                INTEGER_OVERFLOW
+                            } else if let OpCode::Add = instruction {
+                                "Operands must be two numbers or two strings."
+                            } else {
+                                "Operands must be numbers."
+                            };
+                            self.runtime_error(msg);
+                            return InterpretResult::RuntimeError;
+                        }
                     }
                 }
                 OpCode::NIL => self.stack.push(Value::Nil),
@@ -239,6 +769,7 @@ impl VM {
                     let value: bool = if let Some(v) = self.stack.pop() {
                         v.is_falsey()
                     } else {
+                        self.runtime_error("Stack underflow.");
                         return InterpretResult::RuntimeError;
                     };
                     self.stack.push(Value::Boolean(value));
@@ -254,42 +785,55 @@ impl VM {
                 }
                 OpCode::Print => {
                     let value = self.stack.pop().unwrap();
-                    println!("{}", value);
+                    if let Err(e) = self.write_out(&format!("{}\n", value)) {
+                        self.report_error(VmError::Native(e.to_string()));
+                        return InterpretResult::RuntimeError;
+                    }
                 }
                 OpCode::Pop => {
                     let _ = self.stack.pop();
                 }
                 OpCode::PopN => {
                     // simple optimization to pop all elements at once.
-                    let n: u8 = self.read_byte();
-                    self.stack.truncate(n as usize);
+                    let n = self.read_byte() as usize;
+                    self.stack.truncate(self.stack.len() - n);
                 }
                 OpCode::DefineGlobal => {
                     // used to store the global Variable and Value pairs.
-                    let name = self.read_string().unwrap();
+                    let name = self.read_string(wide).unwrap();
                     // NOTE: Value is not popped directly off the stack.
                     // This is to ensure that the VM can still find the value after/during garbage collection.b
                     let value = self.peek(0);
-                    self.globals.insert(name, value);
+                    self.frame_globals_mut().insert(name, value);
                     self.stack.pop(); // value is associated with this variable and not needed on the stack. access with variable name
                 }
                 OpCode::GetGlobal => {
-                    let name = self.read_string().unwrap();
-                    let value: Value = match self.globals.get(name) {
+                    let name = self.read_string(wide).unwrap();
+                    // a module reaches natives through the vm's globals.
+                    let value = self.frame_globals().get(name);
+                    let value: Value = match value.or_else(|| self.globals.get(name)) {
                         Some(value) => value,
-                        None => return InterpretResult::RuntimeError,
+                        None => {
+                            let msg = format!(
+                                "Undefined variable '{}'.",
+                                interner::get_string(name).unwrap()
+                            );
+                            self.runtime_error(&msg);
+                            return InterpretResult::RuntimeError;
+                        }
                     };
                     self.stack.push(value);
                 }
                 OpCode::SetGlobal => {
-                    let symbol: SymbolU32 = self.read_string().unwrap();
+                    let symbol: SymbolU32 = self.read_string(wide).unwrap();
                     let current: Value = self.peek(0);
 
                     // Throw RuntimeError if assignment to an undeclared global variable.
                     // insert returns true if a no previous value was declared with this variable name.
                     // false otherwise.
-                    if self.globals.insert(symbol, current) {
-                        self.globals.delete(symbol);
+                    let globals = self.frame_globals_mut();
+                    if globals.insert(symbol, current) {
+                        globals.delete(symbol);
                         Self::runtime_error(
                             self,
                             format!(
@@ -304,16 +848,16 @@ impl VM {
                 OpCode::GetLocal => {
                     // reads the current frame slots array
                     // which meant it accessed the given numbered slot relative to the beginning of that frame.
-                    let offset = self.read_byte();
+                    let offset = self.read_index(wide);
                     let base: usize = self.get_current_frame_mut().slots;
-                    let value = self.read_local_slot(base + (offset as usize));
+                    let value = self.read_local_slot(base + offset);
                     self.push_value(value);
                 }
                 OpCode::SetLocal => {
-                    let slot = self.read_byte();
+                    let slot = self.read_index(wide);
                     let base: usize = self.get_current_frame_mut().slots;
                     let value: Value = self.peek(0);
-                    self.write_local_slot(base + slot as usize, value);
+                    self.write_local_slot(base + slot, value);
                 }
                 OpCode::JumpIfFalse => {
                     let offset = self.read_short();
@@ -325,6 +869,23 @@ impl VM {
                     let offset = self.read_short();
                     self.get_current_frame_mut().ip += offset as usize;
                 }
+                OpCode::JumpTable => {
+                    let low = self.read_byte() as usize;
+                    let high = self.read_byte() as usize;
+                    let default = self.read_short();
+                    let table = self.get_current_frame().ip;
+                    // non integers and numbers outside [low, high] take the default case.
+                    let value = self.stack.pop().and_then(|value| value.as_whole());
+                    let offset = match value {
+                        Some(n) if n >= low as i64 && n <= high as i64 => {
+                            self.get_current_frame_mut().ip = table + 2 * (n as usize - low);
+                            self.read_short()
+                        }
+                        _ => default,
+                    };
+                    self.get_current_frame_mut().ip =
+                        table + 2 * (high - low + 1) + offset as usize;
+                }
                 OpCode::Loop => {
                     let offset = self.read_short();
                     self.get_current_frame_mut().ip -= offset as usize;
@@ -335,11 +896,9 @@ impl VM {
                     if !self.call_value(function, arity) {
                         return InterpretResult::RuntimeError;
                     }
-                    // good time to collect garbage from a function
-                    self.collect_garbage();
                 }
                 OpCode::Closure => {
-                    let value = self.read_constant();
+                    let value = self.read_constant(wide);
                     let function = Value::as_function(&value);
                     let count = function.upvalue_count;
                     let mut upval_ids = vec![ObjId(0); count];
@@ -372,21 +931,23 @@ impl VM {
                         function: Rc::clone(&function),
                         upvalues: upval_ids,
                         upvalue_count: count,
+                        // functions belong to the module they are declared in.
+                        module: self.get_current_frame().module,
                     };
                     // allocate closure on heap, push ObjId onto stack
-                    let id = self.heap.alloc_closure(closure);
+                    let id = self.alloc(GcValue::Closure(closure));
                     self.stack.push(Value::Object(id));
                 }
                 OpCode::GetUpValue => {
                     // operand is the index into the current function's upvalue array.
-                    let slot = self.read_byte() as usize;
+                    let slot = self.read_index(wide);
                     let id = self.get_current_frame().closure_id;
                     let id: ObjId = self.get_frame_closure(id).upvalues[slot];
                     let value: Value = self.heap.get_upvalue(id, &self.stack);
                     self.stack.push(value);
                 }
                 OpCode::SetUpValue => {
-                    let slot = self.read_byte() as usize;
+                    let slot = self.read_index(wide);
                     let peek_value = self.peek(0);
                     let id = self.get_current_frame().closure_id;
                     let upval_id: ObjId = self.get_frame_closure(id).upvalues[slot];
@@ -399,15 +960,23 @@ impl VM {
                     self.stack.pop();
                 }
                 OpCode::Class => {
-                    let name = interner::get_string(self.read_string().unwrap()).unwrap();
-                    let clazz = GcObject::new(GcValue::Class(LoxClass::new(name)));
-                    let id = self.heap.alloc(clazz);
+                    let name = interner::get_string(self.read_string(wide).unwrap()).unwrap();
+                    let id = self.alloc(GcValue::Class(LoxClass::new(name)));
                     self.stack.push(Value::Object(id));
                 }
                 OpCode::GetProperty => {
-                    if let Value::Object(id) = self.peek(0) {
+                    if let Value::String(_) = self.peek(0) {
+                        let property: SymbolU32 = self.read_string(wide).unwrap();
+                        let field = interner::get_string(property).unwrap();
+                        if strings::method(&field).is_none() {
+                            let msg = format!("Undefined string method `{}`.", field);
+                            self.runtime_error(&msg);
+                            return InterpretResult::RuntimeError;
+                        }
+                        self.bind_native_method(property);
+                    } else if let Value::Object(id) = self.peek(0) {
                         // again, dribble to bypass big BC!!
-                        let property: SymbolU32 = self.read_string().unwrap();
+                        let property: SymbolU32 = self.read_string(wide).unwrap();
                         let field = interner::get_string(property).unwrap();
 
                         if let GcValue::Instance(li) = &self.heap.get(id).value {
@@ -425,6 +994,22 @@ impl VM {
                                     return InterpretResult::RuntimeError;
                                 }
                             }
+                        } else if let GcValue::Module(module) = &self.heap.get(id).value {
+                            let Some(value) = module.globals.get(property) else {
+                                let msg =
+                                    format!("Module `{}` has no global `{}`.", module.name, field);
+                                self.runtime_error(&msg);
+                                return InterpretResult::RuntimeError;
+                            };
+                            self.stack.pop();
+                            self.push_value(value);
+                        } else if let GcValue::List(_) = &self.heap.get(id).value {
+                            if lists::method(&field).is_none() {
+                                let msg = format!("Undefined list method `{}`.", field);
+                                self.runtime_error(&msg);
+                                return InterpretResult::RuntimeError;
+                            }
+                            self.bind_native_method(property);
                         } else {
                             self.runtime_error("Only instances have properties");
                             return InterpretResult::RuntimeError;
@@ -437,7 +1022,7 @@ impl VM {
                 OpCode::SetProperty => {
                     if let Value::Object(id) = self.peek(1) {
                         // NOTE: the object whose property is being set sits depth 1, 0 is the field
-                        let field: SymbolU32 = self.read_string().unwrap();
+                        let field: SymbolU32 = self.read_string(wide).unwrap();
                         let v = self.peek(0);
 
                         if let GcValue::Instance(li) = &mut self.heap.get_mut(id).value {
@@ -457,7 +1042,7 @@ impl VM {
                     }
                 }
                 OpCode::Method => {
-                    let name = self.read_string().unwrap();
+                    let name = self.read_string(wide).unwrap();
                     self.define_method(name);
                 }
                 OpCode::Inherit => {
@@ -468,24 +1053,31 @@ impl VM {
                             // are added, overwritten methods shadow the superclass's methods.
                             if !self.heap.orchestrate_inherit(super_id, sub_id) {
                                 self.runtime_error("Superclass must be a class.");
+                                return InterpretResult::RuntimeError;
                             }
                             self.pop();
                         }
                     } else {
                         self.runtime_error("Superclass must be a class.");
+                        return InterpretResult::RuntimeError;
                     }
                 }
                 OpCode::GetSuper => {
-                    let name = self.read_string().unwrap();
+                    let name = self.read_string(wide).unwrap();
                     if let Value::Object(sup_id) = self.pop().unwrap()
                         && !self.bind_method(sup_id, name)
                     {
+                        let msg = format!(
+                            "Undefined property `{}`.",
+                            interner::get_string(name).unwrap()
+                        );
+                        self.runtime_error(&msg);
                         return InterpretResult::RuntimeError;
                     }
                     // else method not required, compiler would have caught this error.
                 }
                 OpCode::SuperInvoke => {
-                    let name = self.read_string().unwrap();
+                    let name = self.read_string(wide).unwrap();
                     let arg_count = self.read_byte();
                     if let Value::Object(sup_id) = self.pop().unwrap()
                         && !self.invoke_from_class(sup_id, name, arg_count)
@@ -493,6 +1085,19 @@ impl VM {
                         return InterpretResult::RuntimeError;
                     }
                 }
+                OpCode::Throw => {
+                    let value = self.stack.pop().unwrap_or_default();
+                    let error = self.thrown_error(&value);
+                    self.report_error(error);
+                    self.thrown = Some(value);
+                    return InterpretResult::RuntimeError;
+                }
+                OpCode::Import => {
+                    let path = self.read_string(wide).unwrap();
+                    if !self.import(path) {
+                        return InterpretResult::RuntimeError;
+                    }
+                }
                 OpCode::Array => {
                     let items = if self.read_byte() == LONG_ARG_INDEX {
                         let mut buffer: [u8; 3] = [255, 255, 255];
@@ -503,22 +1108,62 @@ impl VM {
                     };
                     let item_start = self.stack.len() - items;
                     let list: Vec<Value> = self.stack[item_start..].to_vec();
-                    let gc_obj = GcObject::new(GcValue::List(LoxVec(list)));
-                    let heap_list = self.heap.alloc(gc_obj);
+                    let heap_list = self.alloc(GcValue::List(LoxVec(list)));
                     // remove existing objects and push the list ref onto the stack
                     self.stack.truncate(item_start);
                     self.stack.push(Value::Object(heap_list));
                 }
+                OpCode::Map => {
+                    let entries = if self.read_byte() == LONG_ARG_INDEX {
+                        let mut buffer: [u8; 3] = [255, 255, 255];
+                        self.read_3_bytes(&mut buffer);
+                        Chunk::inverse_resolve(buffer[0], buffer[1], buffer[2])
+                    } else {
+                        self.read_byte() as usize
+                    };
+                    let entry_start = self.stack.len() - 2 * entries;
+                    let mut map = HashMap::with_capacity(entries);
+                    for pair in self.stack[entry_start..].chunks(2) {
+                        match MapKey::from_value(&pair[0]) {
+                            Some(key) => map.insert(key, pair[1].clone()),
+                            None => {
+                                self.runtime_error(INVALID_MAP_KEY);
+                                return InterpretResult::RuntimeError;
+                            }
+                        };
+                    }
+                    // keys and values stay on the stack while allocating so the gc can reach them.
+                    let heap_map = self.alloc(GcValue::Map(LoxMap(map)));
+                    self.stack.truncate(entry_start);
+                    self.stack.push(Value::Object(heap_map));
+                }
                 OpCode::ArrayGetItem => {
                     // at this point the result of the expression [`expr`] is on the stack
                     let index = self.peek(0);
                     let arr = self.peek(1);
-                    if let Value::Number(n) = index
+                    if let Value::Object(id) = arr
+                        && self.heap.get(id).is_map()
+                    {
+                        match self.heap.get(id).get_map_item(&index) {
+                            Some(v) => {
+                                self.pop(); // pop key
+                                self.pop(); // pop map
+                                self.push_value(v);
+                            }
+                            None => {
+                                self.runtime_error(INVALID_MAP_KEY);
+                                return InterpretResult::RuntimeError;
+                            }
+                        }
+                    } else if Value::is_number(&index)
                         && let Value::Object(id) = arr
                     {
+                        let Some(n) = self.list_index(&index) else {
+                            return InterpretResult::RuntimeError;
+                        };
                         let o = self.heap.get(id);
 
-                        match o.get_list_item(n as usize) {
+                        match o.get_list_item(n) {
                             Some(v) => {
                                 // similar with GetProperty, we remove the array and index and leave the
                                 // gotten value on the stack.
@@ -527,7 +1172,7 @@ impl VM {
                                 self.push_value(v);
                             }
                             None => {
-                                self.runtime_error("Invalid array access operation");
+                                self.runtime_error(INVALID_LIST_ACCESS);
                                 return InterpretResult::RuntimeError;
                             }
                         }
@@ -541,19 +1186,34 @@ impl VM {
                     let new_val = self.peek(0);
                     let index = self.peek(1);
                     let arr = self.peek(2);
-                    if let Value::Number(n) = index
+                    if let Value::Object(id) = arr
+                        && self.heap.get(id).is_map()
+                    {
+                        if self.heap.get_mut(id).set_map_item(&index, new_val) {
+                            let new_val = self.pop().unwrap();
+                            let _ = self.pop(); // pop key
+                            let _ = self.pop(); // pop map
+                            self.push_value(new_val);
+                        } else {
+                            self.runtime_error(INVALID_MAP_KEY);
+                            return InterpretResult::RuntimeError;
+                        }
+                    } else if Value::is_number(&index)
                         && let Value::Object(id) = arr
                     {
+                        let Some(n) = self.list_index(&index) else {
+                            return InterpretResult::RuntimeError;
+                        };
                         let o = self.heap.get_mut(id);
 
-                        if o.set_list_item(n as usize, new_val) {
+                        if o.set_list_item(n, new_val) {
                             // like SetProperty, we leave the new_val on the stack and pop array and index
                             let new_val = self.pop().unwrap();
                             let _ = self.pop(); // pop index
                             let _ = self.pop(); // pop array
                             self.push_value(new_val);
                         } else {
-                            self.runtime_error("Invalid array access operation");
+                            self.runtime_error(INVALID_LIST_ACCESS);
                             return InterpretResult::RuntimeError;
                         }
                     } else {
@@ -565,25 +1225,74 @@ impl VM {
         }
     }
 
+    /// the position a list is indexed at, a runtime error unless `index` is a whole number.
+    /// Negative positions are out of range like those past the end of the list.
+    fn list_index(&mut self, index: &Value) -> Option<usize> {
+        let Some(n) = index.as_whole() else {
+            let msg = format!("List index must be a whole number but got {}.", index);
+            self.runtime_error(&msg);
+            return None;
+        };
+        match usize::try_from(n) {
+            Ok(n) => Some(n),
+            Err(_) => {
+                self.runtime_error(INVALID_LIST_ACCESS);
+                None
+            }
+        }
+    }
+
+    /// replaces the receiver on top of the stack with the method `name` bound to it.
+    /// returns false if the class has no such method.
     fn bind_method(&mut self, class: ObjId, name: SymbolU32) -> bool {
-        if let GcValue::Class(clazz) = &self.heap.get(class).value {
-            // again no need to get as closure, the call() function
-            // checks if the Object is either a Class, Closure or Nativefn etc
-            clazz.get_method(name).is_some_and(|v| {
-                self.push_value(v);
-                true
-            });
+        let method = match &self.heap.get(class).value {
+            GcValue::Class(clazz) => clazz.get_method(name),
+            _ => None,
+        };
+
+        // again no need to get as closure, the call() function
+        // checks if the Object is either a Class, Closure or Nativefn etc
+        if let Some(Value::Object(closure)) = method
+            && let Value::Object(receiver) = self.peek(0)
+        {
+            // the receiver stays on the stack while allocating so it is reachable by the gc.
+            let bound = self.alloc(GcValue::Method(BoundMethod::new(receiver, closure)));
+            self.pop();
+            self.push_value(Value::Object(bound));
+            return true;
         }
         false
     }
 
+    /// every heap allocation made by the vm goes through here, so a collection
+    /// can be triggered while all roots are still visible to the vm.
+    /// NOTE: the object being allocated is not yet reachable and must not be
+    /// referenced by anything other than the stack or the heap.
+    fn alloc(&mut self, value: GcValue) -> ObjId {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        let id = self.heap.alloc(GcObject::new(value));
+        self.write_gc_log();
+        id
+    }
+
+    /// writes out what `GcMode::Log` logged since the last time.
+    fn write_gc_log(&mut self) {
+        if self.heap.log.is_empty() {
+            return;
+        }
+        let mut log = std::mem::take(&mut self.heap.log).join("\n");
+        log.push('\n');
+        let _ = self.write_out(&log);
+    }
+
     /// marks all roots with allocations on the heap
     /// as grey
     pub fn collect_garbage(&mut self) {
         let roots: HashSet<ObjId> = self.find_roots();
-        self.heap.mark_roots(roots.into_iter());
-        self.heap.trace_references();
-        self.heap.sweep();
+        self.heap.collect_garbage(roots.into_iter());
+        self.write_gc_log();
     }
 
     fn find_roots(&self) -> HashSet<ObjId> {
@@ -601,32 +1310,218 @@ impl VM {
             }
         }
 
+        let callback_thrown = self.callback_error.iter().flat_map(|(_, thrown)| thrown);
+        for v in self
+            .pinned
+            .iter()
+            .chain(&self.thrown)
+            .chain(callback_thrown)
+        {
+            if let Value::Object(id) = v {
+                objects.insert(*id);
+            }
+        }
+        objects.extend(self.error_class);
+        objects.extend(self.modules.values().copied());
+
         for f in &self.call_frames {
             objects.insert(f.closure_id);
+            // constants baked into a chunk by the compiler live as long as the function.
+            for constant in &self
+                .get_frame_closure(f.closure_id)
+                .function
+                .chunk
+                .constants
+            {
+                if let Value::Object(id) = constant {
+                    objects.insert(*id);
+                }
+            }
         }
 
+        // upvalues still pointing into the stack may not be owned by a live closure yet
+        // e.g while `OpCode::Closure` is capturing, but they can be reused by later captures.
+        objects.extend(self.open_upvalues.values().copied());
+
         objects
     }
 
     fn invoke(&mut self, name: SymbolU32, arg_count: u8) -> bool {
-        if let Value::Object(recv) = self.peek(arg_count as usize) {
-            if let GcValue::Instance(i) = &self.heap.get(recv).value {
-                if let Some(v) = i.get_field(name) {
-                    // replace instance on the stack with it gotten property
-                    let idx = self.stack.len() - arg_count as usize - 1;
-                    self.stack[idx] = v.clone(); // inexpensive bounded method call
-                    return self.call_value(v, arg_count);
-                } else {
-                    return self.invoke_from_class(i.class, name, arg_count);
-                }
+        if let Value::Object(recv) = self.peek(arg_count as usize)
+            && let GcValue::Instance(i) = &self.heap.get(recv).value
+        {
+            if let Some(v) = i.get_field(name) {
+                // replace instance on the stack with it gotten property
+                let idx = self.stack.len() - arg_count as usize - 1;
+                self.stack[idx] = v.clone(); // inexpensive bounded method call
+                return self.call_value(v, arg_count);
             } else {
-                self.runtime_error("Only instances have methods.");
+                return self.invoke_from_class(i.class, name, arg_count);
+            }
+        }
+        if let Value::Object(recv) = self.peek(arg_count as usize)
+            && let GcValue::Module(module) = &self.heap.get(recv).value
+        {
+            let Some(value) = module.globals.get(name) else {
+                let msg = format!(
+                    "Module `{}` has no global `{}`.",
+                    module.name,
+                    interner::get_string(name).unwrap_or_default()
+                );
+                self.runtime_error(&msg);
                 return false;
+            };
+            // the function takes the module's place as the callee.
+            let idx = self.stack.len() - arg_count as usize - 1;
+            self.stack[idx] = value.clone();
+            return self.call_value(value, arg_count);
+        }
+        match self.peek(arg_count as usize) {
+            Value::String(_) => return self.invoke_native_method(name, arg_count),
+            Value::Object(recv) if matches!(self.heap.get(recv).value, GcValue::List(_)) => {
+                return self.invoke_native_method(name, arg_count);
             }
+            _ => (),
         }
+        self.runtime_error("Only instances have methods.");
         false
     }
 
+    /// replaces the list or string on top of the stack with its built-in method `name`.
+    fn bind_native_method(&mut self, name: SymbolU32) {
+        // the receiver stays on the stack while allocating so the gc can reach it.
+        let receiver = self.peek(0);
+        let method = self.alloc(GcValue::NativeMethod(NativeMethod { receiver, name }));
+        self.stack.pop();
+        self.push_value(Value::Object(method));
+    }
+
+    /// calls the built-in method `name` of the list or string sitting below the
+    /// arguments, the receiver and arguments are replaced with the result.
+    fn invoke_native_method(&mut self, name: SymbolU32, arg_count: u8) -> bool {
+        let receiver_slot = self.stack.len() - arg_count as usize - 1;
+        let method_name = interner::get_string(name).unwrap_or_default();
+        let result = match &self.stack[receiver_slot] {
+            Value::Object(id) => match &mut self.heap.get_mut(*id).value {
+                GcValue::List(list) => match lists::method(&method_name) {
+                    Some(method) => method(list, &self.stack[receiver_slot + 1..]),
+                    None => Err(VmError::Runtime(format!(
+                        "Undefined list method `{}`.",
+                        method_name
+                    ))),
+                },
+                _ => Err(VmError::Runtime("Only instances have methods.".to_string())),
+            },
+            Value::String(symbol) => {
+                let receiver = interner::get_string(*symbol).unwrap_or_default();
+                match strings::method(&method_name) {
+                    Some(method) => method(&receiver, &self.stack[receiver_slot + 1..]),
+                    None => Err(VmError::Runtime(format!(
+                        "Undefined string method `{}`.",
+                        method_name
+                    ))),
+                }
+            }
+            _ => Err(VmError::Runtime("Only instances have methods.".to_string())),
+        };
+
+        self.finish_native_call(result, receiver_slot)
+    }
+
+    /// replaces the callee (or receiver) at `slot` and the arguments above it with
+    /// the value returned by a native, lists are allocated here.
+    fn finish_native_call(&mut self, result: NativeResult, slot: usize) -> bool {
+        // a native that recovered from a failed call back into lox drops its error.
+        let callback_error = self.callback_error.take();
+        let value = match result {
+            Ok(NativeValue::Value(value)) => value,
+            // the callee and arguments are still on the stack, so the items stay reachable.
+            Ok(NativeValue::List(items)) => Value::Object(self.alloc(GcValue::List(LoxVec(items)))),
+            // the error of a failed callback is raised with the trace of where it happened.
+            Err(e) => {
+                match callback_error {
+                    Some((error, thrown)) if error.kind == e => {
+                        self.error = Some(error);
+                        self.thrown = thrown;
+                    }
+                    _ => self.report_error(e),
+                }
+                return false;
+            }
+        };
+        self.stack.truncate(slot);
+        self.push_value(value);
+        true
+    }
+
+    /// allocates `value` for a native, the object is kept on the stack until the native returns.
+    pub(crate) fn alloc_for_native(&mut self, value: GcValue) -> Value {
+        let object = Value::Object(self.alloc(value));
+        self.stack.push(object.clone());
+        object
+    }
+
+    pub(crate) fn heap_value(&self, value: &Value) -> Option<&GcValue> {
+        match value {
+            Value::Object(id) => Some(&self.heap.get(*id).value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn heap_value_mut(&mut self, value: &Value) -> Option<&mut GcValue> {
+        match value {
+            Value::Object(id) => Some(&mut self.heap.get_mut(*id).value),
+            _ => None,
+        }
+    }
+
+    /// calls `callee` from inside a native and runs it to completion.
+    pub(crate) fn call_from_native(&mut self, callee: Value, args: &[Value]) -> VmResult {
+        if !self.call_to_completion(callee, args) {
+            let Some(error) = self.error.take() else {
+                return Err(VmError::Runtime("Unknown runtime error.".to_owned()));
+            };
+            let kind = error.kind.clone();
+            self.callback_error = Some((error, self.thrown.take()));
+            return Err(kind);
+        }
+        // the result stays on the stack, rooted until the native returns.
+        Ok(self.peek(0))
+    }
+
+    /// calls `callee` with `args` and leaves its result on top of the stack.
+    fn call_to_completion(&mut self, callee: Value, args: &[Value]) -> bool {
+        let Ok(arg_count) = u8::try_from(args.len()) else {
+            self.runtime_error("Can't have more than 255 arguments.");
+            return false;
+        };
+        let depth = self.call_frames.len();
+        self.stack.push(callee.clone());
+        self.stack.extend_from_slice(args);
+        if !self.call_value(callee, arg_count) {
+            return false;
+        }
+        // a lox function only pushed its frame, natives and classes without
+        // an initializer already left their result on the stack.
+        if self.call_frames.len() == depth {
+            return true;
+        }
+        let floor = std::mem::replace(&mut self.frame_floor, depth);
+        let result = self.run();
+        self.frame_floor = floor;
+        result == InterpretResult::Ok
+    }
+
+    /// keeps `value` alive until the current call into the vm finishes.
+    pub(crate) fn pin(&mut self, value: Value) -> Value {
+        self.pinned.push(value.clone());
+        value
+    }
+
+    pub(crate) fn alloc_value(&mut self, value: GcValue) -> Value {
+        Value::Object(self.alloc(value))
+    }
+
     fn invoke_from_class(&mut self, class_id: ObjId, name: SymbolU32, arg_count: u8) -> bool {
         if let GcValue::Class(m) = &self.heap.get(class_id).value {
             if let Some(Value::Object(cloj)) = m.get_method(name) {
@@ -637,6 +1532,8 @@ impl VM {
                 let msg = format!("Undefined property {}", interner::get_string(name).unwrap());
                 self.runtime_error(&msg);
             }
+        } else {
+            self.runtime_error("Superclass must be a class.");
         }
         false
     }
@@ -644,18 +1541,32 @@ impl VM {
     fn call_value(&mut self, callee: Value, arity: u8) -> bool {
         if Value::is_object(&callee) {
             return match &callee {
-                Value::NativeFunction(func) => {
+                Value::NativeFunction(_, native_arity) | Value::HostFunction(_, native_arity)
+                    if !native_arity.accepts(arity as usize) =>
+                {
+                    let msg = format!("Expected {} arguments but got {}.", native_arity, arity);
+                    self.runtime_error(&msg);
+                    false
+                }
+                Value::NativeFunction(func, _) => {
                     let arg_start = self.stack.len() - arity as usize; // slot 0 irrelevant here, hence no -1
                     let args: &[Value] = &self.stack[arg_start..]; // send only the args the functions need
-                    match (func.0)(arity as usize, args) {
-                        Ok(result) => {
-                            self.stack.truncate(arg_start - 1); // remove function and its arguments.
-                            self.push_value(result);
-                            return true;
-                        }
-                        Err(e) => self.runtime_error(&e.to_string()),
-                    }
-                    false
+                    let result = (func.0)(arity as usize, args);
+                    // remove function and its arguments.
+                    self.finish_native_call(result, arg_start - 1)
+                }
+                Value::HostFunction(host, _) => {
+                    let arg_start = self.stack.len() - arity as usize;
+                    // the context needs the vm mutably, so the args are copied out of the stack.
+                    let args: Vec<Value> = self.stack[arg_start..].to_vec();
+                    let host = host.0.clone();
+                    let result = match host.try_borrow_mut() {
+                        Ok(mut function) => function(&mut NativeContext::new(self), &args),
+                        Err(_) => Err(VmError::Runtime(
+                            "A native function can't be called while it is running.".to_owned(),
+                        )),
+                    };
+                    self.finish_native_call(result.map(NativeValue::Value), arg_start - 1)
                 }
                 Value::Object(id) => {
                     match &self.heap.get(*id).value {
@@ -666,8 +1577,7 @@ impl VM {
                         GcValue::Class(klass) => {
                             let constructor: Option<Value> = klass.get_method(self.init_symbol);
                             let instance: LoxInstance = LoxInstance::new(*id);
-                            let new_obj: ObjId =
-                                self.heap.alloc(GcObject::new(GcValue::Instance(instance)));
+                            let new_obj: ObjId = self.alloc(GcValue::Instance(instance));
                             // store reference on the stack slot where local 0 would have been
                             let idx = self.stack.len() - arity as usize - 1;
                             self.stack[idx] = Value::Object(new_obj);
@@ -678,9 +1588,16 @@ impl VM {
                                 // when a no-args constructor is (implicitly) defined but constructor is called with args
                                 let msg = format!("Expected 0 arguments but got {}", arity);
                                 self.runtime_error(&msg);
+                                return false;
                             }
                             true
                         }
+                        GcValue::NativeMethod(m) => {
+                            let NativeMethod { receiver, name } = m.clone();
+                            let idx = self.stack.len() - arity as usize - 1;
+                            self.stack[idx] = receiver;
+                            return self.invoke_native_method(name, arity);
+                        }
                         GcValue::Method(m) => {
                             let obj = self.heap.get(m.closure);
                             let function = obj.as_function().unwrap();
@@ -689,10 +1606,16 @@ impl VM {
                             self.stack[idx] = Value::Object(m.receiver);
                             return self.call(&function, m.closure, arity);
                         }
-                        _ => false,
+                        _ => {
+                            self.runtime_error("Can only call functions and classes.");
+                            false
+                        }
                     }
                 }
-                _ => false,
+                _ => {
+                    self.runtime_error("Can only call functions and classes.");
+                    false
+                }
             };
         }
         self.runtime_error("Can only call functions, closures and constructors.");
@@ -753,23 +1676,41 @@ impl VM {
             return id;
         }
 
-        let value = GcValue::UpValue(UpValueState::Open(slot));
-        let id = self.heap.alloc(GcObject::new(value));
+        let id = self.alloc(GcValue::UpValue(UpValueState::Open(slot)));
         self.open_upvalues.insert(slot, id);
         id
     }
 
-    fn define_native(&mut self, name: String, function: NativeFn) {
-        let symbol = interner::intern(&name);
+    /// registers `native` and defines it as a global, scripts compiled by this vm
+    /// afterwards can call it by name.
+    pub fn define_native(&mut self, native: Native) {
+        let symbol = interner::intern(&native.name);
         // NOTE: `Clox` pushes here to guard against garbage collection,
         // `Rlox` however triggers garbage collection on only heap allocation, if `Heap::alloc()`
         // is not called, garbage collection never happens. The code is commented out for reference.
         // self.push_value(Value::String(symbol));
         // self.push_value(Value::NativeFunction(function));
-        self.globals.insert(symbol, Value::NativeFunction(function));
+        self.globals.insert(symbol, native.value());
         // same here
         // self.pop();
         // self.pop();
+        Rc::make_mut(&mut self.natives).register(native);
+    }
+
+    /// forgets every global scripts or the embedder defined and every imported
+    /// module, the natives and the vm's settings and writers are kept.
+    pub fn reset_globals(&mut self) {
+        self.globals = HashTable::new();
+        self.known_globals.clear();
+        self.modules.clear();
+        for native in self.natives.iter() {
+            self.globals
+                .insert(interner::intern(&native.name), native.value());
+        }
+    }
+
+    pub fn natives(&self) -> &NativeRegistry {
+        &self.natives
     }
 
     /// FIX: we already allocated the closure on the heap, ideally
@@ -805,61 +1746,63 @@ impl VM {
             return false;
         }
 
-        if self.call_frames.len() == FRAMES_MAX {
-            Self::runtime_error(self, "Stack overflow");
+        if self.call_frames.len() >= self.max_frames || self.stack.len() > self.stack_size {
+            self.report_error(VmError::StackOverflow);
             return false;
         }
         // [ fn ] [ arg0 ] [ arg1 ] [ arg2 ]  <-- stackTop
         // ^      | -------args to function ------
         // slots points here (slot 0 = the function being called)
+        let module = self.get_frame_closure(closure_id).module;
         self.call_frames.push(CallFrame {
             closure_id, // note rc cloned before passing in, use clojure.
             ip: 0,
             slots: self.stack.len() - arity as usize - 1,
+            module,
         });
         true
     }
 
     fn runtime_error(&mut self, msg: &str) {
-        eprintln!("{}", msg);
-        for i in (0..self.call_frames.len()).rev() {
-            let frame: &CallFrame = &self.call_frames[i];
+        self.report_error(VmError::Runtime(msg.to_owned()));
+    }
+
+    /// records `kind` together with the lox call stack, innermost frame first.
+    /// `run` then unwinds to a `catch` or out of the vm.
+    fn report_error(&mut self, kind: VmError) {
+        let mut stack: Vec<TraceFrame> = Vec::with_capacity(self.call_frames.len());
+        for frame in self.call_frames.iter().rev() {
+            let function = &self.get_frame_closure(frame.closure_id).function;
             // - 1 because ip points to the next instruction to be executed
             // but the failed instruction was the previous one.
-            let instruction: usize = frame.ip - 1;
-            let line = self
-                .get_frame_closure(frame.closure_id)
-                .function
-                .chunk
-                .lines[instruction];
-            let name = &self.get_frame_closure(frame.closure_id).function.name;
-
-            eprint!("[line {}] in ", line.0);
-            match &name {
-                Some(name) => eprintln!("{}", name),
-                None => eprintln!("Script"),
-            }
-            eprint!("[line {}] in ", line.0);
+            let instruction: usize = frame.ip.saturating_sub(1);
+            stack.push(TraceFrame {
+                function: function.name.clone(),
+                line: function.chunk.line_for_offset(instruction),
+            });
         }
-        self.reset_stack();
+        self.error = Some(LoxError::new(kind, stack));
+        self.thrown = None;
     }
 
-    // HACK: `is_long` is a fragile heuristic to determine when an oprand to opcode is OP_CONSTANT_LONG: Operand is 24bits.
-    fn read_constant(&mut self) -> Value {
-        let is_long = self.call_frames.last().unwrap().is_long(&self.heap);
-        let index = if is_long {
-            let b1 = self.read_byte() as u32;
-            let b2 = self.read_byte() as u32;
-            let b3 = self.read_byte() as u32;
+    /// reads the 1 byte, or 3 byte if `wide`, index operand of the current instruction.
+    fn read_index(&mut self, wide: bool) -> usize {
+        if wide {
+            let b1 = self.read_byte() as usize;
+            let b2 = self.read_byte() as usize;
+            let b3 = self.read_byte() as usize;
 
             b1 | (b2 << 8) | (b3 << 16)
         } else {
-            self.read_byte() as u32
-        };
+            self.read_byte() as usize
+        }
+    }
 
+    fn read_constant(&mut self, wide: bool) -> Value {
+        let index = self.read_index(wide);
         self.current_chunk()
             .constants
-            .get(index as usize)
+            .get(index)
             .expect("Invalid constant index.")
             .clone()
     }
@@ -907,17 +1850,15 @@ impl VM {
             OpCode::Divide => lhs.div(rhs),
             OpCode::Multiply => lhs.mul(rhs),
             OpCode::Subtract => lhs.sub(rhs),
+            OpCode::Modulo => lhs.rem(rhs),
             OpCode::Greater => Value::greater_than(&lhs, &rhs),
             OpCode::Less => Value::less_than(&lhs, &rhs),
             _ => None,
         }
     }
 
-    fn read_string(&mut self) -> Option<SymbolU32> {
-        // Because we have 2 constant-indexing Operands OpConstant and OpConstant24
-        // We need to resolve what operand was used to store this constant.
-        // so we know to read either the next byte or next 3 bytes.
-        match self.read_constant() {
+    fn read_string(&mut self, wide: bool) -> Option<SymbolU32> {
+        match self.read_constant(wide) {
             Value::String(symbol) => Some(symbol), // interner::get_string(symbol),
             _ => None,
         }
@@ -1,20 +1,41 @@
 use std::fmt;
+use std::path::Path;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum VmError {
     StackOverflow,
     InvalidOpcode(u8),
     Runtime(String),
     Native(String),
+    // a value thrown by `throw` that no `catch` handled, described for the user.
+    Thrown(String),
+    Compile(String),
+    InvalidBytecode(VerifyError),
+}
+
+impl VmError {
+    /// the bare message without the kind prefix used by `Display`.
+    pub fn message(&self) -> String {
+        match self {
+            VmError::Runtime(msg) | VmError::Native(msg) | VmError::Compile(msg) => msg.clone(),
+            VmError::Thrown(value) => format!("Uncaught exception: {}", value),
+            VmError::StackOverflow => "Stack overflow.".to_string(),
+            VmError::InvalidOpcode(op) => format!("Invalid opcode: {}", op),
+            VmError::InvalidBytecode(e) => e.to_string(),
+        }
+    }
 }
 
 impl fmt::Display for VmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            VmError::StackOverflow => write!(f, "Stackunderflow"),
+            VmError::StackOverflow => write!(f, "Stack overflow"),
             VmError::InvalidOpcode(op) => write!(f, "Invalid opcode: {}", op),
             VmError::Runtime(msg) => write!(f, "Runtime error: {}", msg),
             VmError::Native(msg) => write!(f, "Runtime error: {}", msg),
+            VmError::Thrown(value) => write!(f, "Runtime error: Uncaught exception: {}", value),
+            VmError::Compile(msg) => write!(f, "Compile error: {}", msg),
+            VmError::InvalidBytecode(e) => write!(f, "Invalid bytecode: {}", e),
         }
     }
 }
@@ -26,3 +47,159 @@ impl From<std::io::Error> for VmError {
         VmError::Runtime(err.to_string())
     }
 }
+
+/// Errors reading or writing compiled `.roxc` bytecode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnexpectedEof,
+    TrailingBytes(usize),
+    InvalidUtf8,
+    BadColumnTable,
+    UnknownConstant(u8),
+    UnsupportedConstant(String),
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::BadMagic => write!(f, "Not a rox bytecode file."),
+            BytecodeError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported bytecode version {}.", v)
+            }
+            BytecodeError::UnexpectedEof => write!(f, "Unexpected end of bytecode."),
+            BytecodeError::TrailingBytes(n) => write!(f, "{} unexpected bytes after the chunk.", n),
+            BytecodeError::InvalidUtf8 => write!(f, "String constant is not valid utf-8."),
+            BytecodeError::BadColumnTable => write!(f, "Malformed column table."),
+            BytecodeError::UnknownConstant(tag) => write!(f, "Unknown constant tag {}.", tag),
+            BytecodeError::UnsupportedConstant(value) => {
+                write!(f, "Constant {} cannot be serialized.", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BytecodeError {}
+
+/// A chunk rejected by `core::verify`, `offset` is the instruction at fault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub function: String,
+    pub offset: usize,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    InvalidOpcode(u8),
+    TruncatedOperand,
+    MalformedOperand,
+    ConstantOutOfRange(usize),
+    ExpectedString,
+    ExpectedFunction,
+    LocalOutOfRange(usize),
+    UpvalueOutOfRange(usize),
+    BadJumpTarget(usize),
+    StackUnderflow,
+    StackMismatch { expected: usize, found: usize },
+    FallsOffEnd,
+    LineTableMismatch,
+    BadHandler(usize),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}: ", self.function, self.offset)?;
+        match &self.kind {
+            VerifyErrorKind::InvalidOpcode(byte) => write!(f, "invalid opcode {}.", byte),
+            VerifyErrorKind::TruncatedOperand => {
+                write!(f, "operand runs past the end of the code.")
+            }
+            VerifyErrorKind::MalformedOperand => write!(f, "malformed operand."),
+            VerifyErrorKind::ConstantOutOfRange(i) => {
+                write!(f, "constant {} is out of range.", i)
+            }
+            VerifyErrorKind::ExpectedString => write!(f, "expected a string constant."),
+            VerifyErrorKind::ExpectedFunction => write!(f, "expected a function constant."),
+            VerifyErrorKind::LocalOutOfRange(slot) => {
+                write!(f, "local slot {} is not on the stack.", slot)
+            }
+            VerifyErrorKind::UpvalueOutOfRange(i) => write!(f, "upvalue {} is out of range.", i),
+            VerifyErrorKind::BadJumpTarget(target) => {
+                write!(f, "jump to {} is not an instruction.", target)
+            }
+            VerifyErrorKind::StackUnderflow => write!(f, "stack underflow."),
+            VerifyErrorKind::StackMismatch { expected, found } => write!(
+                f,
+                "stack depth {} does not match {} on another path.",
+                found, expected
+            ),
+            VerifyErrorKind::FallsOffEnd => write!(f, "execution runs past the end of the code."),
+            VerifyErrorKind::LineTableMismatch => {
+                write!(f, "line table does not cover the code.")
+            }
+            VerifyErrorKind::BadHandler(index) => {
+                write!(
+                    f,
+                    "try handler {} does not cover whole instructions.",
+                    index
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// one entry of a lox stack trace, innermost call first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    /// None for top-level script code.
+    pub function: Option<String>,
+    pub line: u32,
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            // the top-level code of a module is named after its file.
+            Some(name) if Path::new(name).extension().is_some() => {
+                write!(f, "[line {}] in {}", self.line, name)
+            }
+            Some(name) => write!(f, "[line {}] in {}()", self.line, name),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+/// Error returned to embedders by `VM::interpret_with_diagnostics`.
+/// `stack` is built from the vm's call frames at the point the error was raised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoxError {
+    pub message: String,
+    pub kind: VmError,
+    pub stack: Vec<TraceFrame>,
+}
+
+impl LoxError {
+    pub fn new(kind: VmError, stack: Vec<TraceFrame>) -> Self {
+        Self {
+            message: kind.message(),
+            kind,
+            stack,
+        }
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.stack {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoxError {}
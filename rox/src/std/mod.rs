@@ -1,27 +1,11 @@
 pub mod lox_errors;
+pub mod registry;
 
 use crate::{core::value::Value, std::lox_errors::VmError};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type VmResult = Result<Value, VmError>;
-
-// the compiler uses this at comptime to know if a named variable is
-// a call to a native function
-pub fn is_native_call(func_name: &str) -> bool {
-    [
-        "time::clock",
-        "clock",
-        "math::max",
-        "math::sqrt",
-        "math::pow",
-        "io::readLine",
-        "io::readNumber",
-        "strings::str_cmp",
-        "strings::str_len",
-        "utils::list_len",
-    ]
-    .contains(&func_name)
-}
+pub type NativeResult = Result<NativeValue, VmError>;
 
 fn validate_args(arg_count: usize, args: &[Value]) -> VmResult {
     match args.len().checked_sub(arg_count) {
@@ -30,11 +14,50 @@ fn validate_args(arg_count: usize, args: &[Value]) -> VmResult {
     }
 }
 
+/// an `Int` if `s` is a whole number that fits one, otherwise a `Number`.
+fn parse_number(s: &str) -> Option<Value> {
+    match s.parse::<i64>() {
+        Ok(i) => Some(Value::Int(i)),
+        Err(_) => s.parse::<f64>().ok().map(Value::Number),
+    }
+}
+
+/// what a native function or built-in method (`list.push(x)`, `"abc".upper()`)
+/// hands back to the vm. `NativeFn`s can't reach the heap, so new lists are returned
+/// as plain items and allocated by the vm.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeValue {
+    Value(Value),
+    List(Vec<Value>),
+}
+
+impl From<Value> for NativeValue {
+    fn from(value: Value) -> Self {
+        NativeValue::Value(value)
+    }
+}
+
+fn expect_args(args: &[Value], min: usize, max: usize) -> Result<(), VmError> {
+    if (min..=max).contains(&args.len()) {
+        return Ok(());
+    }
+    let expected = if min == max {
+        min.to_string()
+    } else {
+        format!("{} to {}", min, max)
+    };
+    Err(VmError::Runtime(format!(
+        "Expected {} arguments but got {}.",
+        expected,
+        args.len()
+    )))
+}
+
 pub mod time {
     use super::*;
-    pub fn clock(_arg_count: usize, _args: &[Value]) -> VmResult {
+    pub fn clock(_arg_count: usize, _args: &[Value]) -> NativeResult {
         match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(d) => Ok(Value::Number(d.as_secs_f64())),
+            Ok(d) => Ok(Value::Number(d.as_secs_f64()).into()),
             Err(e) => Err(VmError::Native(e.to_string())),
         }
     }
@@ -43,7 +66,7 @@ pub mod time {
 pub mod math {
     use super::*;
 
-    pub fn sqrt(arg_count: usize, nums: &[Value]) -> VmResult {
+    pub fn sqrt(arg_count: usize, nums: &[Value]) -> NativeResult {
         // let index = nums.len() - 1 - arg_count;
         // we would need somethin similar to get the args to the function
         // since we only need onw here. No need for this.
@@ -54,30 +77,49 @@ pub mod math {
         let v = validate_args(arg_count, nums)?;
         let start: usize = Value::as_sizet(&v);
 
-        if let Value::Number(double) = nums[start] {
-            Ok(Value::Number(double.sqrt()))
+        if let Some(double) = nums[start].as_f64() {
+            Ok(Value::Number(double.sqrt()).into())
         } else {
             Err(VmError::Runtime("Expects a double(f64).".to_string()))
         }
     }
 
-    pub fn pow(arg_count: usize, nums: &[Value]) -> VmResult {
+    pub fn pow(arg_count: usize, nums: &[Value]) -> NativeResult {
         let v = validate_args(arg_count, nums)?;
         let start: usize = Value::as_sizet(&v);
 
-        match (&nums[start], &nums[start + 1]) {
-            (Value::Number(p), Value::Number(q)) => Ok(Value::Number(p.powf(*q))),
+        match (nums[start].as_f64(), nums[start + 1].as_f64()) {
+            (Some(p), Some(q)) => Ok(Value::Number(p.powf(q)).into()),
             _ => Err(VmError::Runtime("Expected type number.".to_string())),
         }
     }
 
-    pub fn max(arg_count: usize, nums: &[Value]) -> VmResult {
+    /// `math::div(a, b)`, the quotient of two whole numbers rounded toward zero.
+    pub fn div(arg_count: usize, nums: &[Value]) -> NativeResult {
+        expect_args(nums, 2, 2)?;
+        let v = validate_args(arg_count, nums)?;
+        let start: usize = Value::as_sizet(&v);
+
+        match (nums[start].as_whole(), nums[start + 1].as_whole()) {
+            (Some(_), Some(0)) => Err(VmError::Runtime("Division by zero.".to_string())),
+            (Some(p), Some(q)) => match p.checked_div(q) {
+                Some(quotient) => Ok(Value::Int(quotient).into()),
+                None => Err(VmError::Runtime("Integer overflow.".to_string())),
+            },
+            _ => Err(VmError::Runtime("Expected whole numbers.".to_string())),
+        }
+    }
+
+    pub fn max(arg_count: usize, nums: &[Value]) -> NativeResult {
         let v = validate_args(arg_count, nums)?;
         let start: usize = Value::as_sizet(&v);
 
         match (&nums[start], &nums[start + 1]) {
-            (Value::Number(p), Value::Number(q)) => Ok(Value::Number(p.max(*q))),
-            _ => Err(VmError::Runtime("Expected type number.".to_string())),
+            (Value::Int(p), Value::Int(q)) => Ok(Value::Int(*p.max(q)).into()),
+            (p, q) => match (p.as_f64(), q.as_f64()) {
+                (Some(p), Some(q)) => Ok(Value::Number(p.max(q)).into()),
+                _ => Err(VmError::Runtime("Expected type number.".to_string())),
+            },
         }
     }
 }
@@ -86,14 +128,15 @@ pub mod io {
     use std::io;
 
     use crate::data_structures::interner;
+    use crate::runtime::native::NativeContext;
 
     use super::*;
 
-    pub fn read_line(_arg_count: usize, _args: &[Value]) -> VmResult {
+    pub fn read_line(_arg_count: usize, _args: &[Value]) -> NativeResult {
         match read() {
             Ok(buffer) => {
                 let symbol = interner::intern(buffer.trim());
-                Ok(Value::String(symbol))
+                Ok(Value::String(symbol).into())
             }
             Err(e) => Err(VmError::Native(e.to_string())),
         }
@@ -105,31 +148,152 @@ pub mod io {
         Ok(buffer)
     }
 
-    pub fn read_number(_arg_count: usize, _args: &[Value]) -> VmResult {
+    pub fn read_number(_arg_count: usize, _args: &[Value]) -> NativeResult {
         match read() {
-            Ok(s) => match s.parse::<f64>() {
-                Ok(num) => Ok(Value::Number(num)),
-                Err(e) => Err(VmError::Native(e.to_string())),
+            Ok(s) => match super::parse_number(s.trim()) {
+                Some(num) => Ok(num.into()),
+                None => Err(VmError::Native(format!("`{}` is not a number.", s.trim()))),
             },
             Err(e) => Err(VmError::Native(e.to_string())),
         }
     }
+
+    /// `io::write(value)` prints a value without a newline, e.g a prompt.
+    pub fn write(ctx: &mut NativeContext, args: &[Value]) -> VmResult {
+        expect_args(args, 1, 1)?;
+        ctx.write_out(&args[0].to_string())?;
+        Ok(Value::Nil)
+    }
+
+    /// `io::writeError(value)` prints a value and a newline to stderr.
+    pub fn write_error(ctx: &mut NativeContext, args: &[Value]) -> VmResult {
+        expect_args(args, 1, 1)?;
+        ctx.write_err(&format!("{}\n", args[0]))?;
+        Ok(Value::Nil)
+    }
 }
 
-pub mod files {}
+pub mod files {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use crate::data_structures::interner;
+
+    use super::*;
+
+    /// the arguments of a files native, all of them are strings.
+    fn string_args(arg_count: usize, args: &[Value], arity: usize) -> Result<Vec<String>, VmError> {
+        let v = validate_args(arg_count, args)?;
+        let start: usize = Value::as_sizet(&v);
+        expect_args(&args[start..], arity, arity)?;
+        args[start..]
+            .iter()
+            .map(|arg| match arg {
+                Value::String(symbol) => Ok(interner::get_string(*symbol).unwrap_or_default()),
+                _ => Err(VmError::Native("Expected a string argument.".to_string())),
+            })
+            .collect()
+    }
+
+    fn io_error(path: &str, e: std::io::Error) -> VmError {
+        VmError::Native(format!("{}: {}", path, e))
+    }
+
+    fn string(s: &str) -> NativeResult {
+        Ok(Value::String(interner::intern(s)).into())
+    }
+
+    /// `files::read(path)`, the whole file as a string.
+    pub fn read(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 1)?;
+        let path = &args[0];
+        let contents = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        string(&contents)
+    }
+
+    /// `files::write(path, s)` creates or truncates the file.
+    pub fn write(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 2)?;
+        let (path, contents) = (&args[0], &args[1]);
+        fs::write(path, contents).map_err(|e| io_error(path, e))?;
+        Ok(Value::Nil.into())
+    }
+
+    /// `files::append(path, s)` creates the file if it does not exist.
+    pub fn append(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 2)?;
+        let (path, contents) = (&args[0], &args[1]);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| io_error(path, e))?;
+        Ok(Value::Nil.into())
+    }
+
+    pub fn exists(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 1)?;
+        let path = &args[0];
+        Ok(Value::Boolean(fs::exists(path).map_err(|e| io_error(path, e))?).into())
+    }
+
+    /// `files::lines(path)`, a list of the lines without their line endings.
+    pub fn lines(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 1)?;
+        let path = &args[0];
+        let contents = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        let lines = contents
+            .lines()
+            .map(|line| Value::String(interner::intern(line)))
+            .collect();
+        Ok(NativeValue::List(lines))
+    }
+
+    /// `files::remove(path)` removes a file, not a directory.
+    pub fn remove(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 1)?;
+        let path = &args[0];
+        fs::remove_file(path).map_err(|e| io_error(path, e))?;
+        Ok(Value::Nil.into())
+    }
+
+    /// `files::list_dir(path)`, the sorted names of the directory's entries.
+    pub fn list_dir(arg_count: usize, args: &[Value]) -> NativeResult {
+        let args = string_args(arg_count, args, 1)?;
+        let path = &args[0];
+        let mut names = fs::read_dir(path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                    .collect::<Result<Vec<String>, std::io::Error>>()
+            })
+            .map_err(|e| io_error(path, e))?;
+        names.sort();
+        let names = names
+            .iter()
+            .map(|name| Value::String(interner::intern(name)))
+            .collect();
+        Ok(NativeValue::List(names))
+    }
+}
 
 pub mod strings {
     use crate::data_structures::interner;
 
     use super::*;
 
-    pub fn str_len(arg_count: usize, args: &[Value]) -> VmResult {
+    // the longest string `repeat` builds, rather than aborting on a failed allocation.
+    const MAX_REPEAT_LEN: usize = 1 << 30;
+
+    /// the number of characters of a string, like `s.len()`.
+    pub fn str_len(arg_count: usize, args: &[Value]) -> NativeResult {
         let v = validate_args(arg_count, args)?;
         let start: usize = Value::as_sizet(&v);
 
         if let Value::String(symbol) = args[start] {
             let s = interner::get_string(symbol).unwrap();
-            return Ok(Value::Number(s.len() as f64));
+            Ok(Value::Int(s.chars().count() as i64).into())
         } else {
             Err(VmError::Native(
                 "String length only computable for strings.".to_string(),
@@ -137,7 +301,7 @@ pub mod strings {
         }
     }
 
-    pub fn str_cmp(arg_count: usize, args: &[Value]) -> VmResult {
+    pub fn str_cmp(arg_count: usize, args: &[Value]) -> NativeResult {
         // NOTE: unlike other functions where the order is irrelevant.
         // the return value is like
         let v = validate_args(arg_count, args)?;
@@ -146,7 +310,7 @@ pub mod strings {
         match (&args[start], &args[start + 1]) {
             (Value::String(s_1), Value::String(s_2)) => {
                 match (interner::get_string(*s_1), interner::get_string(*s_2)) {
-                    (Some(sl), Some(sr)) => Ok(Value::Number((sl.cmp(&sr) as i8) as f64)),
+                    (Some(sl), Some(sr)) => Ok(Value::Int(sl.cmp(&sr) as i64).into()),
                     _ => Err(VmError::Native(
                         "One of the Strings passed in does not exist.".to_string(),
                     )),
@@ -157,22 +321,383 @@ pub mod strings {
             )),
         }
     }
+
+    // `strings::upper(s)` and friends, the string is passed as the first argument
+    // instead of being the receiver of the method.
+    macro_rules! string_natives {
+        ($($native:ident => $method:ident),* $(,)?) => {
+            $(
+                pub fn $native(arg_count: usize, args: &[Value]) -> NativeResult {
+                    call_as_native($method, arg_count, args)
+                }
+            )*
+        };
+    }
+
+    string_natives!(
+        str_substring => substring,
+        str_split => split,
+        str_chars => chars,
+        str_trim => trim,
+        str_upper => upper,
+        str_lower => lower,
+        str_find => find,
+        str_replace => replace,
+        str_starts_with => starts_with,
+        str_ends_with => ends_with,
+        str_repeat => repeat,
+        str_to_number => to_number,
+    );
+
+    /// `strings::to_string(value)` formats any value the way `print` does.
+    pub fn str_to_string(arg_count: usize, args: &[Value]) -> NativeResult {
+        let v = validate_args(arg_count, args)?;
+        let start: usize = Value::as_sizet(&v);
+        expect_args(&args[start..], 1, 1)?;
+        Ok(Value::String(interner::intern(&args[start].to_string())).into())
+    }
+
+    fn call_as_native(method: StringMethod, arg_count: usize, args: &[Value]) -> NativeResult {
+        let v = validate_args(arg_count, args)?;
+        let start: usize = Value::as_sizet(&v);
+
+        let Some(receiver) = args.get(start) else {
+            return Err(VmError::Runtime(
+                "Expected a string as the first argument.".to_string(),
+            ));
+        };
+        let receiver = string_arg(receiver)?;
+        method(&receiver, &args[start + 1..])
+    }
+
+    pub(crate) type StringMethod = fn(&str, &[Value]) -> NativeResult;
+
+    /// the built-in method `name` of strings, e.g `"abc".upper()`.
+    pub(crate) fn method(name: &str) -> Option<StringMethod> {
+        let method: StringMethod = match name {
+            "len" => len,
+            "substring" | "slice" => substring,
+            "split" => split,
+            "trim" => trim,
+            "upper" => upper,
+            "lower" => lower,
+            "find" => find,
+            "replace" => replace,
+            "starts_with" => starts_with,
+            "ends_with" => ends_with,
+            "chars" => chars,
+            "repeat" => repeat,
+            "to_number" => to_number,
+            "to_string" => to_string,
+            _ => return None,
+        };
+        Some(method)
+    }
+
+    fn string_arg(value: &Value) -> Result<String, VmError> {
+        match value {
+            Value::String(symbol) => Ok(interner::get_string(*symbol).unwrap_or_default()),
+            _ => Err(VmError::Runtime("Expected a string argument.".to_string())),
+        }
+    }
+
+    fn string(s: &str) -> NativeValue {
+        NativeValue::Value(Value::String(interner::intern(s)))
+    }
+
+    /// a whole number of characters in `0..=len`.
+    fn char_index(value: &Value, len: usize) -> Result<usize, VmError> {
+        match value.as_whole() {
+            _ if !Value::is_number(value) => Err(VmError::Runtime(
+                "String index must be a number.".to_string(),
+            )),
+            None => Err(VmError::Runtime(format!(
+                "String index {} is not a whole number.",
+                value
+            ))),
+            Some(n) if n >= 0 && n as usize <= len => Ok(n as usize),
+            Some(n) => Err(VmError::Runtime(format!(
+                "String index {} out of range for length {}.",
+                n, len
+            ))),
+        }
+    }
+
+    fn len(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(NativeValue::Value(Value::Int(s.chars().count() as i64)))
+    }
+
+    /// `s.substring(start)` or `s.substring(start, end)`, indices count characters
+    /// and end is exclusive.
+    fn substring(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 2)?;
+        let len = s.chars().count();
+        let start = char_index(&args[0], len)?;
+        let end = match args.get(1) {
+            Some(end) => char_index(end, len)?,
+            None => len,
+        };
+        if start > end {
+            return Err(VmError::Runtime(format!(
+                "Substring start {} is greater than its end {}.",
+                start, end
+            )));
+        }
+        let sub: String = s.chars().skip(start).take(end - start).collect();
+        Ok(string(&sub))
+    }
+
+    /// splitting on an empty separator yields the characters.
+    fn split(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let separator = string_arg(&args[0])?;
+        if separator.is_empty() {
+            return chars(s, &[]);
+        }
+        let parts = s
+            .split(separator.as_str())
+            .map(|part| Value::String(interner::intern(part)))
+            .collect();
+        Ok(NativeValue::List(parts))
+    }
+
+    fn trim(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(string(s.trim()))
+    }
+
+    fn upper(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(string(&s.to_uppercase()))
+    }
+
+    fn lower(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(string(&s.to_lowercase()))
+    }
+
+    /// character index of the first occurrence of the argument, nil if there is none.
+    fn find(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let needle = string_arg(&args[0])?;
+        let index = s.find(needle.as_str()).map_or(Value::Nil, |byte| {
+            Value::Int(s[..byte].chars().count() as i64)
+        });
+        Ok(NativeValue::Value(index))
+    }
+
+    /// replaces every occurrence of the first argument with the second.
+    fn replace(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 2, 2)?;
+        let from = string_arg(&args[0])?;
+        let to = string_arg(&args[1])?;
+        Ok(string(&s.replace(from.as_str(), &to)))
+    }
+
+    fn starts_with(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let prefix = string_arg(&args[0])?;
+        Ok(NativeValue::Value(Value::Boolean(s.starts_with(&prefix))))
+    }
+
+    fn ends_with(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let suffix = string_arg(&args[0])?;
+        Ok(NativeValue::Value(Value::Boolean(s.ends_with(&suffix))))
+    }
+
+    fn chars(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        let chars = s
+            .chars()
+            .map(|c| Value::String(interner::intern(c.encode_utf8(&mut [0; 4]))))
+            .collect();
+        Ok(NativeValue::List(chars))
+    }
+
+    fn repeat(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let count = match args[0].as_whole() {
+            Some(n) if n >= 0 => n as usize,
+            _ => {
+                return Err(VmError::Runtime(
+                    "Repeat count must be a whole number.".to_string(),
+                ));
+            }
+        };
+        match s.len().checked_mul(count) {
+            Some(len) if len <= MAX_REPEAT_LEN => Ok(string(&s.repeat(count))),
+            _ => Err(VmError::Runtime(format!(
+                "Repeated string would be longer than {} bytes.",
+                MAX_REPEAT_LEN
+            ))),
+        }
+    }
+
+    /// nil if the string is not a number.
+    fn to_number(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        let number = super::parse_number(s.trim()).unwrap_or_default();
+        Ok(NativeValue::Value(number))
+    }
+
+    fn to_string(s: &str, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(string(s))
+    }
 }
 
 pub mod utils {
+    use crate::runtime::native::NativeContext;
+
     use super::*;
 
-    // utility method for lox, to get length of strings, lists etc.
-    pub fn list_len(arg_count: usize, args: &[Value]) -> VmResult {
-        let v = validate_args(arg_count, args)?;
-        let start: usize = Value::as_sizet(&v);
+    /// `utils::list_len(list)`, the number of items in a list.
+    pub fn list_len(ctx: &mut NativeContext, args: &[Value]) -> VmResult {
+        expect_args(args, 1, 1)?;
+        match ctx.list_items(&args[0]) {
+            Some(items) => Ok(Value::Int(items.len() as i64)),
+            None => Err(VmError::Native(
+                "List length only computable for lists.".to_string(),
+            )),
+        }
+    }
+}
 
-        if let Value::String(symbol) = args[start] {
-            return Ok(Value::Nil);
-        } else {
-            Err(VmError::Native(
-                "String length only computable for strings.".to_string(),
-            ))
+pub mod lists {
+    use crate::{data_structures::interner, runtime::heap::LoxVec};
+
+    use super::*;
+
+    pub(crate) type ListMethod = fn(&mut LoxVec, &[Value]) -> NativeResult;
+
+    /// the built-in method `name` of lists, e.g `list.push(4)`.
+    pub(crate) fn method(name: &str) -> Option<ListMethod> {
+        let method: ListMethod = match name {
+            "push" => push,
+            "pop" => pop,
+            "insert" => insert,
+            "remove" => remove,
+            "slice" => slice,
+            "len" => len,
+            "contains" => contains,
+            "index_of" => index_of,
+            "reverse" => reverse,
+            "join" => join,
+            _ => return None,
+        };
+        Some(method)
+    }
+
+    /// a whole number in `0..len`, or `0..=len` for positions one past
+    /// the last item (insert, slice).
+    fn index(value: &Value, len: usize, past_end: bool) -> Result<usize, VmError> {
+        let bound = if past_end { len + 1 } else { len };
+        match value.as_whole() {
+            _ if !Value::is_number(value) => {
+                Err(VmError::Runtime("List index must be a number.".to_string()))
+            }
+            None => Err(VmError::Runtime(format!(
+                "List index {} is not a whole number.",
+                value
+            ))),
+            Some(n) if n >= 0 && (n as usize) < bound => Ok(n as usize),
+            Some(n) => Err(VmError::Runtime(format!(
+                "List index {} out of range for length {}.",
+                n, len
+            ))),
+        }
+    }
+
+    fn push(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        list.0.push(args[0].clone());
+        Ok(NativeValue::Value(Value::Nil))
+    }
+
+    fn pop(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        match list.0.pop() {
+            Some(value) => Ok(NativeValue::Value(value)),
+            None => Err(VmError::Runtime(
+                "Can't pop from an empty list.".to_string(),
+            )),
         }
     }
+
+    fn insert(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 2, 2)?;
+        let at = index(&args[0], list.0.len(), true)?;
+        list.0.insert(at, args[1].clone());
+        Ok(NativeValue::Value(Value::Nil))
+    }
+
+    fn remove(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let at = index(&args[0], list.0.len(), false)?;
+        Ok(NativeValue::Value(list.0.remove(at)))
+    }
+
+    /// `list.slice(start)` or `list.slice(start, end)`, end is exclusive.
+    fn slice(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 2)?;
+        let len = list.0.len();
+        let start = index(&args[0], len, true)?;
+        let end = match args.get(1) {
+            Some(end) => index(end, len, true)?,
+            None => len,
+        };
+        if start > end {
+            return Err(VmError::Runtime(format!(
+                "Slice start {} is greater than its end {}.",
+                start, end
+            )));
+        }
+        Ok(NativeValue::List(list.0[start..end].to_vec()))
+    }
+
+    fn len(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        Ok(NativeValue::Value(Value::Int(list.0.len() as i64)))
+    }
+
+    fn position(list: &LoxVec, value: &Value) -> Option<usize> {
+        list.0
+            .iter()
+            .position(|item| Value::values_equal(item.clone(), value.clone()))
+    }
+
+    fn contains(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let found = position(list, &args[0]).is_some();
+        Ok(NativeValue::Value(Value::Boolean(found)))
+    }
+
+    /// index of the first item equal to the argument, nil if there is none.
+    fn index_of(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let index = position(list, &args[0]).map_or(Value::Nil, |i| Value::Int(i as i64));
+        Ok(NativeValue::Value(index))
+    }
+
+    /// reverses the list in place.
+    fn reverse(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 0, 0)?;
+        list.0.reverse();
+        Ok(NativeValue::Value(Value::Nil))
+    }
+
+    fn join(list: &mut LoxVec, args: &[Value]) -> NativeResult {
+        expect_args(args, 1, 1)?;
+        let Value::String(separator) = args[0] else {
+            return Err(VmError::Runtime(
+                "List separator must be a string.".to_string(),
+            ));
+        };
+        let separator = interner::get_string(separator).unwrap_or_default();
+        let items: Vec<String> = list.0.iter().map(|item| item.to_string()).collect();
+        let joined = interner::intern(&items.join(&separator));
+        Ok(NativeValue::Value(Value::String(joined)))
+    }
 }
@@ -0,0 +1,330 @@
+use std::fmt::Display;
+
+use crate::core::value::{HostFn, NativeFn, Value};
+use crate::runtime::native::NativeContext;
+use crate::std::VmResult;
+use crate::std::{files, io, math, strings, time, utils};
+
+/// the number of arguments a native accepts, checked at compile time when
+/// the native is called by name and at runtime otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Arity {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl Arity {
+    pub const fn exactly(n: u8) -> Self {
+        Self { min: n, max: n }
+    }
+
+    pub const fn range(min: u8, max: u8) -> Self {
+        Self { min, max }
+    }
+
+    pub fn accepts(&self, arg_count: usize) -> bool {
+        (self.min as usize..=self.max as usize).contains(&arg_count)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{} to {}", self.min, self.max)
+        }
+    }
+}
+
+/// the two calling conventions of natives, a plain function pointer or a
+/// closure of the embedding application.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeImpl {
+    Fn(NativeFn),
+    Host(HostFn),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Native {
+    pub name: String,
+    pub arity: Arity,
+    pub doc: String,
+    pub function: NativeImpl,
+}
+
+impl Native {
+    pub fn new(name: &str, arity: Arity, doc: &str, function: NativeFn) -> Self {
+        Self {
+            name: name.to_owned(),
+            arity,
+            doc: doc.to_owned(),
+            function: NativeImpl::Fn(function),
+        }
+    }
+
+    /// a native backed by `function`, which may capture state and gets a
+    /// `NativeContext` to allocate, intern strings and call back into lox.
+    pub fn host(
+        name: &str,
+        arity: Arity,
+        doc: &str,
+        function: impl FnMut(&mut NativeContext, &[Value]) -> VmResult + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            arity,
+            doc: doc.to_owned(),
+            function: NativeImpl::Host(HostFn::new(function)),
+        }
+    }
+
+    /// the value a vm stores in the native's global.
+    pub fn value(&self) -> Value {
+        match &self.function {
+            NativeImpl::Fn(function) => Value::NativeFunction(*function, self.arity),
+            NativeImpl::Host(function) => Value::HostFunction(function.clone(), self.arity),
+        }
+    }
+}
+
+/// every native known to a vm. The compiler resolves native names against it,
+/// and the vm defines each of them as a global.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NativeRegistry {
+    natives: Vec<Native>,
+}
+
+impl NativeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the natives of the lox standard library.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        for (name, arity, doc, function) in STANDARD {
+            registry.register(Native::new(name, arity, doc, function));
+        }
+        // these write through the vm's output, so they need a `NativeContext`.
+        registry.register(Native::host(
+            "io::write",
+            Arity::exactly(1),
+            "prints a value without a newline.",
+            io::write,
+        ));
+        registry.register(Native::host(
+            "io::writeError",
+            Arity::exactly(1),
+            "prints a value and a newline to stderr.",
+            io::write_error,
+        ));
+        // lists live on the heap, which only a `NativeContext` can read.
+        registry.register(Native::host(
+            "utils::list_len",
+            Arity::exactly(1),
+            "the number of items in a list.",
+            utils::list_len,
+        ));
+        registry
+    }
+
+    /// adds `native`, replacing a native already registered under the same name.
+    pub fn register(&mut self, native: Native) {
+        match self.natives.iter_mut().find(|n| n.name == native.name) {
+            Some(existing) => *existing = native,
+            None => self.natives.push(native),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Native> {
+        self.natives.iter().find(|n| n.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Native> {
+        self.natives.iter()
+    }
+}
+
+const STANDARD: [(&str, Arity, &str, NativeFn); 30] = [
+    (
+        "clock",
+        Arity::exactly(0),
+        "seconds since the unix epoch.",
+        NativeFn(time::clock),
+    ),
+    (
+        "time::clock",
+        Arity::exactly(0),
+        "seconds since the unix epoch.",
+        NativeFn(time::clock),
+    ),
+    (
+        "math::sqrt",
+        Arity::exactly(1),
+        "square root of a number.",
+        NativeFn(math::sqrt),
+    ),
+    (
+        "math::max",
+        Arity::exactly(2),
+        "the larger of two numbers.",
+        NativeFn(math::max),
+    ),
+    (
+        "math::div",
+        Arity::exactly(2),
+        "the quotient of two whole numbers rounded toward zero.",
+        NativeFn(math::div),
+    ),
+    (
+        "math::pow",
+        Arity::exactly(2),
+        "the first number raised to the second.",
+        NativeFn(math::pow),
+    ),
+    (
+        "io::readLine",
+        Arity::exactly(0),
+        "reads a line from stdin without its line ending.",
+        NativeFn(io::read_line),
+    ),
+    (
+        "io::readNumber",
+        Arity::exactly(0),
+        "reads a number from stdin.",
+        NativeFn(io::read_number),
+    ),
+    (
+        "files::read",
+        Arity::exactly(1),
+        "the contents of a file.",
+        NativeFn(files::read),
+    ),
+    (
+        "files::write",
+        Arity::exactly(2),
+        "creates or truncates a file and writes a string to it.",
+        NativeFn(files::write),
+    ),
+    (
+        "files::append",
+        Arity::exactly(2),
+        "appends a string to a file, creating it if needed.",
+        NativeFn(files::append),
+    ),
+    (
+        "files::exists",
+        Arity::exactly(1),
+        "true if the path exists.",
+        NativeFn(files::exists),
+    ),
+    (
+        "files::lines",
+        Arity::exactly(1),
+        "the lines of a file as a list.",
+        NativeFn(files::lines),
+    ),
+    (
+        "files::remove",
+        Arity::exactly(1),
+        "removes a file.",
+        NativeFn(files::remove),
+    ),
+    (
+        "files::list_dir",
+        Arity::exactly(1),
+        "the sorted entry names of a directory.",
+        NativeFn(files::list_dir),
+    ),
+    (
+        "strings::str_cmp",
+        Arity::exactly(2),
+        "-1, 0 or 1 comparing two strings.",
+        NativeFn(strings::str_cmp),
+    ),
+    (
+        "strings::str_len",
+        Arity::exactly(1),
+        "the number of characters in a string.",
+        NativeFn(strings::str_len),
+    ),
+    (
+        "strings::substring",
+        Arity::range(2, 3),
+        "the characters of a string from start up to an optional end.",
+        NativeFn(strings::str_substring),
+    ),
+    (
+        "strings::split",
+        Arity::exactly(2),
+        "a list of the parts of a string between a separator.",
+        NativeFn(strings::str_split),
+    ),
+    (
+        "strings::chars",
+        Arity::exactly(1),
+        "a list of the characters of a string.",
+        NativeFn(strings::str_chars),
+    ),
+    (
+        "strings::trim",
+        Arity::exactly(1),
+        "a string without leading and trailing whitespace.",
+        NativeFn(strings::str_trim),
+    ),
+    (
+        "strings::upper",
+        Arity::exactly(1),
+        "a string in upper case.",
+        NativeFn(strings::str_upper),
+    ),
+    (
+        "strings::lower",
+        Arity::exactly(1),
+        "a string in lower case.",
+        NativeFn(strings::str_lower),
+    ),
+    (
+        "strings::find",
+        Arity::exactly(2),
+        "the character index of a substring, nil if absent.",
+        NativeFn(strings::str_find),
+    ),
+    (
+        "strings::replace",
+        Arity::exactly(3),
+        "replaces every occurrence of a substring.",
+        NativeFn(strings::str_replace),
+    ),
+    (
+        "strings::starts_with",
+        Arity::exactly(2),
+        "true if a string starts with a prefix.",
+        NativeFn(strings::str_starts_with),
+    ),
+    (
+        "strings::ends_with",
+        Arity::exactly(2),
+        "true if a string ends with a suffix.",
+        NativeFn(strings::str_ends_with),
+    ),
+    (
+        "strings::repeat",
+        Arity::exactly(2),
+        "a string repeated a number of times.",
+        NativeFn(strings::str_repeat),
+    ),
+    (
+        "strings::to_number",
+        Arity::exactly(1),
+        "the number a string holds, nil if it is not one.",
+        NativeFn(strings::str_to_number),
+    ),
+    (
+        "strings::to_string",
+        Arity::exactly(1),
+        "any value formatted the way print does.",
+        NativeFn(strings::str_to_string),
+    ),
+];
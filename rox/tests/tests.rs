@@ -1,9 +1,26 @@
 #[cfg(test)]
 pub mod test {
     use rox::{
+        cli::{Cli, Command},
         compile::compiler::Compiler,
+        core::value::{NativeFn, Value},
+        core::{
+            chunk::{Chunk, LineRun},
+            opcode::OpCode,
+            verify::verify,
+        },
+        golden::{self, Expectations},
+        repl::{self, Repl},
+        runtime::convert::{FromLox, IntoLox},
+        runtime::gc::GcMode,
+        runtime::native::NativeContext,
+        runtime::output::SharedBuffer,
         runtime::vm::{InterpretResult, VM},
+        std::lox_errors::{BytecodeError, TraceFrame, VerifyErrorKind, VmError},
+        std::registry::{Arity, Native, NativeRegistry},
+        std::{NativeResult, NativeValue, VmResult, math},
     };
+    use std::{cell::RefCell, rc::Rc};
 
     /// this tests in this test suite mostly follow the pattern
     /// ````
@@ -25,6 +42,30 @@ pub mod test {
         }};
     }
 
+    /// runs `src` and compares what it printed with `expected`, a failing script
+    /// reports its error.
+    macro_rules! assert_prints {
+        ($src:expr, $expected:expr) => {{
+            let (result, out, err) = run_captured(&$src);
+            assert_eq!(result, InterpretResult::Ok, "{}", err);
+            assert_eq!(out, $expected);
+        }};
+    }
+
+    /// runs `src` with its output captured, returns what was written to stdout and stderr.
+    fn run_captured(src: &str) -> (InterpretResult, String, String) {
+        let (out, err) = (SharedBuffer::new(), SharedBuffer::new());
+        let mut vm = VM::init().with_output(out.clone(), err.clone());
+        let result = vm.interpret(src.to_owned());
+        (result, out.contents(), err.contents())
+    }
+
+    /// `vm` with what it prints kept in the returned buffer.
+    fn captured(vm: VM) -> (VM, SharedBuffer) {
+        let out = SharedBuffer::new();
+        (vm.with_output(out.clone(), SharedBuffer::new()), out)
+    }
+
     #[test]
     pub(super) fn tests_arithmetic_expr() {
         // TODO: this also tests that a single '5' is stored in the constants pool.
@@ -58,7 +99,7 @@ pub mod test {
     // really annoying to append ';' to simple expressions.
     #[test]
     fn tests_string_concatenation() {
-        assert!(Compiler::compile("\"st\" + \"ring\";").is_some());
+        assert!(Compiler::compile("\"st\" + \"ring\";").is_ok());
     }
 
     #[test]
@@ -74,13 +115,13 @@ pub mod test {
     #[test]
     fn tests_valid_printstmt_successful() {
         let src = "print 1 + 2;";
-        assert!(Compiler::compile(src).is_some());
+        assert!(Compiler::compile(src).is_ok());
     }
 
     #[test]
     fn test_unnamed_variable_fails_compile() {
         let src = "foo = \"bar\";"; // variable foo is undeclared.
-        assert!(Compiler::compile(src).is_none());
+        assert!(Compiler::compile(src).is_err());
     }
 
     #[test]
@@ -89,7 +130,7 @@ pub mod test {
                          var beverage = \"capuccino\"; \n\
                          breakfast = \"beignets with \"+ beverage; \n\
                          print breakfast;";
-        assert!(Compiler::compile(_src).is_some());
+        assert!(Compiler::compile(_src).is_ok());
     }
 
     /// tests that a const declared variable should fail
@@ -428,4 +469,1870 @@ pub mod test {
             "
         )
     }
+
+    /// objects only reachable through a closed upvalue must survive a collection.
+    #[test]
+    fn tests_gc_stress_keeps_captured_objects() {
+        let mut vm = VM::init();
+        vm.set_gc_mode(GcMode::Stress);
+        let src = "
+                fun make() {
+                    var list = [1, 2, 3];
+                    fun get() { return list; }
+                    return get;
+                }
+                var get = make();
+                var junk = [0];
+                junk = [1];
+                junk = [2];
+                print get()[2];
+            ";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+    }
+
+    #[test]
+    fn tests_gc_sweeps_unreachable_objects() {
+        let src = "
+                var i = 0;
+                while (i < 100) {
+                    var garbage = [i, i];
+                    i = i + 1;
+                }
+            ";
+        let mut no_gc = VM::init();
+        assert_eq!(no_gc.interpret(src.to_owned()), InterpretResult::Ok);
+
+        let mut stress = VM::init();
+        stress.set_gc_mode(GcMode::Stress);
+        assert_eq!(stress.interpret(src.to_owned()), InterpretResult::Ok);
+        assert!(stress.bytes_allocated() * 10 < no_gc.bytes_allocated());
+    }
+
+    #[test]
+    fn tests_runtime_error_carries_stack_trace() {
+        let mut vm = VM::init();
+        let src = "fun inner() {
+                       return -\"oops\";
+                   }
+                   fun outer() {
+                       inner();
+                   }
+                   outer();";
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.message, "Operand must be a number.");
+        assert_eq!(
+            error.kind,
+            VmError::Runtime("Operand must be a number.".to_string())
+        );
+        assert_eq!(
+            error.stack,
+            vec![
+                TraceFrame {
+                    function: Some("inner".to_string()),
+                    line: 2
+                },
+                TraceFrame {
+                    function: Some("outer".to_string()),
+                    line: 5
+                },
+                TraceFrame {
+                    function: None,
+                    line: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tests_native_error_keeps_its_kind() {
+        let mut vm = VM::init();
+        let error = vm
+            .interpret_with_diagnostics("strings::str_len(1);".to_owned())
+            .unwrap_err();
+        assert!(matches!(error.kind, VmError::Native(_)));
+        assert_eq!(error.stack.len(), 1);
+    }
+
+    #[test]
+    fn tests_vm_reusable_after_runtime_error() {
+        let mut vm = VM::init();
+        let error = vm
+            .interpret_with_diagnostics("fun f() { return \"a\" * 2; } f();".to_owned())
+            .unwrap_err();
+        assert_eq!(error.stack.len(), 2);
+        assert_eq!(vm.interpret("print 1;".to_owned()), InterpretResult::Ok);
+    }
+
+    #[test]
+    fn tests_bound_method_property_access_ok() {
+        assert_interprets_ok!(
+            "
+            class A {
+              method() {
+                print \"A method\";
+              }
+            }
+            class B < A {
+              method() {
+                var m = super.method;
+                m();
+              }
+            }
+            var m = B().method;
+            m();
+            "
+        );
+    }
+
+    #[test]
+    fn tests_reports_every_statement_error() {
+        let src = "print 1 +;\nvar = 2;\nprint 3;\nprint (4;";
+        let diagnostics = Compiler::compile(src).unwrap_err();
+        let lines: Vec<u32> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn tests_diagnostic_points_at_column() {
+        let src = "var a = 1;\nprint a +;";
+        let diagnostics = Compiler::compile(src).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 10));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[line 2] Error at ';': expected an expression here."
+        );
+        assert!(
+            diagnostics[0]
+                .render(src)
+                .ends_with("2 | print a +;\n  |          ^")
+        );
+    }
+
+    #[test]
+    fn tests_unterminated_string_is_compile_error() {
+        let diagnostics = Compiler::compile("print \"oops;").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Unterminated string found.");
+    }
+
+    #[test]
+    fn tests_break_and_continue_pop_loop_locals() {
+        assert_prints!(
+            "
+            {
+              var outer = \"ok\";
+              var total = 0;
+              for (var i = 0; i < 10; i = i + 1) {
+                var a = i;
+                var b = a * 2;
+                if (i == 2) continue;
+                if (i == 6) break;
+                total = total + b;
+              }
+              print total;
+              print outer;
+            }
+            ",
+            "26\nok\n"
+        );
+    }
+
+    #[test]
+    fn tests_break_closes_captured_locals() {
+        assert_prints!(
+            "
+            var get = nil;
+            var i = 0;
+            while (true) {
+              var k = i;
+              fun f() { return k; }
+              get = f;
+              i = i + 1;
+              if (i == 3) break;
+            }
+            print get();
+            ",
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn tests_break_outside_loop_is_compile_error() {
+        let diagnostics =
+            Compiler::compile("break;\nfun f() { while (true) { fun g() { continue; } } }")
+                .unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[1].message,
+            "Can't use 'continue' outside of a loop."
+        );
+    }
+
+    #[test]
+    fn tests_switch_compares_case_values() {
+        assert_prints!(
+            "
+            fun kind(v) {
+              var r = \"none\";
+              switch (v) {
+                case \"a\", \"b\": var t = \"letter\"; r = t;
+                case nil: r = \"nil\";
+                case 1 + 1: r = \"two\";
+                default: r = \"other\";
+              }
+              return r;
+            }
+            print kind(\"b\");
+            print kind(nil);
+            print kind(2);
+            print kind(true);
+            ",
+            "letter\nnil\ntwo\nother\n"
+        );
+    }
+
+    #[test]
+    fn tests_switch_small_integers_use_jump_table() {
+        let src = "
+            fun name(n) {
+              switch (n) {
+                case 0: return \"zero\";
+                case 1, 2: return \"small\";
+                case 5: { var x = \"five\"; return x; }
+                default: return \"other\";
+              }
+            }
+            var names = [];
+            for (var n = 0; n < 6; n = n + 1) names.push(name(n));
+            print names.join(\" \");
+            print name(1.5) + \" \" + name(\"a\");
+            ";
+        assert_prints!(src, "zero small small other other five\nother other\n");
+    }
+
+    #[test]
+    fn tests_switch_rejects_duplicate_and_misplaced_cases() {
+        let diagnostics =
+            Compiler::compile("switch (1) { case 1: print 1; case 2, 1: print 2; }").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Duplicate case value in switch.");
+        let diagnostics =
+            Compiler::compile("switch (1) { default: print 1; case 2: print 2; }").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Can't have a case after the default case."
+        );
+    }
+
+    #[test]
+    fn tests_chunk_serialization_round_trips() {
+        let src = "
+            fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }
+            class A { init(x) { this.x = x; } }
+            var c = counter(); c();
+            print c();
+            print A(\"a\").x;
+            print 1.5 + 1;
+            ";
+        let function = Compiler::compile(src).unwrap();
+        let bytes = function.chunk.serialize().unwrap();
+        let chunk = Chunk::deserialize(&bytes).unwrap();
+        assert_eq!(chunk, function.chunk);
+
+        let (mut vm, out) = captured(VM::init());
+        assert_eq!(vm.interpret_chunk(chunk), InterpretResult::Ok);
+        assert_eq!(out.contents(), "2\na\n2.5\n");
+    }
+
+    #[test]
+    fn tests_chunk_deserialize_rejects_bad_input() {
+        let bytes = Compiler::compile("print 1;")
+            .unwrap()
+            .chunk
+            .serialize()
+            .unwrap();
+        assert_eq!(
+            Chunk::deserialize(b"print 1;"),
+            Err(BytecodeError::BadMagic)
+        );
+        assert_eq!(
+            Chunk::deserialize(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEof)
+        );
+        let mut newer = bytes.clone();
+        newer[4] = 0xFF;
+        assert!(matches!(
+            Chunk::deserialize(&newer),
+            Err(BytecodeError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn tests_verifier_accepts_compiled_programs() {
+        let src = "
+            class A { init(x) { this.x = x; } get() { return this.x; } }
+            class B < A { get() { return super.get() + 1; } peek() { var m = super.get; return m(); } }
+            fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }
+            var items = [1, [2, 3]];
+            items[1][0] = B(1).get();
+            for (var i = 0; i < 3; i = i + 1) {
+              var c = counter();
+              switch (i) { case 0: continue; case 1, 2: c(); default: break; }
+              while (true) { var t = c; fun f() { return t; } if (f()() > 2) break; }
+              switch (c) { case nil: print 1; default: print 2; }
+            }
+            ";
+        let function = Compiler::compile(src).unwrap();
+        assert_eq!(verify(&function.chunk), Ok(()));
+    }
+
+    #[test]
+    fn tests_verifier_rejects_malformed_chunks() {
+        let function = Compiler::compile("var a = 1; if (a) print a; else print 2;").unwrap();
+        let kind = |mut chunk: Chunk, patch: &dyn Fn(&mut Chunk)| {
+            patch(&mut chunk);
+            verify(&chunk).unwrap_err().kind
+        };
+
+        let chunk = function.chunk.clone();
+        assert_eq!(
+            kind(chunk.clone(), &|c| c.code[0] = 0xEE),
+            VerifyErrorKind::InvalidOpcode(0xEE)
+        );
+        assert!(matches!(
+            kind(chunk.clone(), &|c| c.constants.clear()),
+            VerifyErrorKind::ConstantOutOfRange(_)
+        ));
+        // a jump into the middle of the `Constant` instruction before it.
+        let jump = chunk
+            .code
+            .iter()
+            .position(|&b| b == OpCode::JumpIfFalse as u8)
+            .unwrap();
+        assert!(matches!(
+            kind(chunk.clone(), &|c| c.code[jump + 1] += 1),
+            VerifyErrorKind::BadJumpTarget(_) | VerifyErrorKind::StackMismatch { .. }
+        ));
+        assert_eq!(
+            kind(chunk.clone(), &|c| {
+                c.code.truncate(2);
+                c.lines.truncate(1);
+                c.code[0] = OpCode::Pop as u8;
+                c.code[1] = OpCode::Pop as u8;
+            }),
+            VerifyErrorKind::StackUnderflow
+        );
+
+        let mut vm = VM::init();
+        let mut bad = chunk.clone();
+        bad.code.pop();
+        assert_eq!(vm.interpret_chunk(bad), InterpretResult::CompileError);
+    }
+
+    #[test]
+    fn tests_line_table_is_run_length_encoded() {
+        let src = "var a = 1;\nvar b = a +\n  2;";
+        let chunk = &Compiler::compile(src).unwrap().chunk;
+        assert!(chunk.lines.len() < chunk.code.len());
+        assert_eq!(chunk.line_for_offset(0), 1);
+
+        let add = chunk
+            .code
+            .iter()
+            .position(|&b| b == OpCode::Add as u8)
+            .unwrap();
+        // the `+` is emitted once its right operand `2` has been compiled.
+        assert_eq!(chunk.position_for_offset(add), (3, 3));
+        assert_eq!(chunk.line_for_offset(chunk.code.len() - 1), 3);
+
+        // the tokens of a line share one run, their columns are kept apart.
+        let chunk = &Compiler::compile("print 1 + 2 * 3 - 4;").unwrap().chunk;
+        assert_eq!(chunk.lines, vec![LineRun { start: 0, line: 1 }]);
+        let multiply = chunk
+            .code
+            .iter()
+            .position(|&b| b == OpCode::Multiply as u8)
+            .unwrap();
+        assert_eq!(chunk.position_for_offset(multiply), (1, 15));
+        assert_eq!(chunk.position_for_offset(0), (1, 7));
+        let bytes = chunk.serialize().unwrap();
+        assert_eq!(&Chunk::deserialize(&bytes).unwrap(), chunk);
+    }
+
+    #[test]
+    fn tests_wide_operands_past_255() {
+        // more than 256 globals, constants and locals in a single chunk.
+        let mut src = String::new();
+        for i in 0..300 {
+            src.push_str(&format!("var g{i} = {i}.5;\n"));
+        }
+        src.push_str("{\n");
+        for i in 0..300 {
+            src.push_str(&format!("var l{i} = {};\n", i + 1000));
+        }
+        src.push_str(
+            "
+            l299 = l299 + 1;
+            fun f() { return l298 + l0; }
+            class C { m() { return g298; } }
+            print g299 + g0;
+            print l299;
+            print f();
+            print C().m();
+            }
+            ",
+        );
+
+        let function = Compiler::compile(&src).unwrap();
+        assert!(function.chunk.code.contains(&(OpCode::Wide as u8)));
+        assert_eq!(verify(&function.chunk), Ok(()));
+        assert_prints!(src, "300\n1300\n2298\n298.5\n");
+    }
+
+    #[test]
+    fn tests_functions_take_several_parameters() {
+        assert_prints!(
+            "
+            fun sub(a, b, c) { return a - b - c; }
+            print sub(10, 3, 2);
+            ",
+            "5\n"
+        );
+    }
+
+    #[test]
+    fn tests_lambda_expressions() {
+        assert_prints!(
+            "
+            fun apply(f, v) { return f(v); }
+            var add = fun (a, b) { return a + b; };
+            var twice = (x) => x * 2;
+            print add(1, 2);
+            print twice(4);
+            print apply(fun (x) { return x + 1; }, 9);
+            print apply((x) => x - 1, 9);
+            print (() => 7)();
+            print (1 + 2) * 3;
+            fun (x) { print x; }(1);
+            ",
+            "3\n8\n10\n8\n7\n9\n1\n"
+        );
+    }
+
+    #[test]
+    fn tests_lambdas_capture_upvalues() {
+        assert_prints!(
+            "
+            fun counter() {
+              var n = 0;
+              return () => n = n + 1;
+            }
+            var c = counter();
+            c();
+            print c();
+
+            class Box {
+              init(v) { this.v = v; }
+              getter() { return fun () { return this.v; }; }
+            }
+            print Box(5).getter()();
+            ",
+            "2\n5\n"
+        );
+    }
+
+    #[test]
+    fn tests_arrow_function_requires_parameter_list() {
+        let diagnostics = Compiler::compile("var f = (1) => 2;").unwrap_err();
+        // `(1)` is a grouping, so the error is reported at the `=>`.
+        assert_eq!(diagnostics[0].message, "Expect ';' after expression.");
+        assert_eq!(diagnostics[0].column, 13);
+    }
+
+    #[test]
+    fn tests_map_literals_and_indexing() {
+        assert_prints!(
+            "
+            var m = {\"a\": 1, 2: \"two\", true: false, nil: 3};
+            print m[\"a\"];
+            print m[2];
+            print m[true];
+            print m[nil];
+            print m[\"missing\"] == nil;
+            m[\"a\"] = m[\"a\"] + 10;
+            m[-0] = \"zero\";
+            print m[\"a\"];
+            print m[0];
+
+            var nested = {\"list\": [1, 2], \"map\": {}};
+            nested[\"list\"][1] = 5;
+            nested[\"map\"][nested] = \"by identity\";
+            print nested[\"list\"][1];
+            print nested[\"map\"][nested];
+            ",
+            "1\ntwo\nfalse\n3\ntrue\n11\nzero\n5\nby identity\n"
+        );
+    }
+
+    #[test]
+    fn tests_map_rejects_unhashable_keys() {
+        assert_interpreter_expects!("var m = {}; m[0 / 0] = 1;", InterpretResult::RuntimeError);
+        let diagnostics = Compiler::compile("var m = {\"a\" 1};").unwrap_err();
+        assert_eq!(diagnostics[0].message, "Expect ':' after map key.");
+    }
+
+    #[test]
+    fn tests_gc_stress_traces_map_keys_and_values() {
+        let (mut vm, out) = captured(VM::init());
+        vm.set_gc_mode(GcMode::Stress);
+        let src = "
+                class Key {}
+                fun make() {
+                    var m = {};
+                    m[Key()] = [1, 2, 3];
+                    m[\"list\"] = [4];
+                    return m;
+                }
+                var m = make();
+                var junk = [0];
+                junk = {1: [1]};
+                junk = [2];
+                print m[\"list\"][0];
+            ";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        assert_eq!(out.contents(), "4\n");
+    }
+
+    #[test]
+    fn tests_list_methods() {
+        assert_prints!(
+            "
+            var a = [1, 2, 3];
+            a.push(4);
+            print a.len();
+            print a.pop();
+            a.insert(0, 0);
+            print a.join(\", \");
+            print a.remove(1);
+            print a.len();
+
+            var tail = a.slice(1);
+            tail.push(9);
+            print tail.join(\"\");
+            print a.slice(0, 2).len();
+            print a.len();
+            print a.contains(3);
+            print a.contains(9);
+            print a.index_of(2);
+            print a.index_of(9) == nil;
+
+            a.reverse();
+            var push = a.push;
+            push(7);
+            print a.join(\"\");
+            print utils::list_len(a);
+            print utils::list_len([]);
+            ",
+            "4\n4\n0, 1, 2, 3\n1\n3\n239\n2\n3\ntrue\nfalse\n1\ntrue\n3207\n4\n0\n"
+        );
+    }
+
+    #[test]
+    fn tests_list_methods_report_bad_indices() {
+        for src in [
+            "[1, 2].remove(2);",
+            "[1, 2].insert(-1, 0);",
+            "[1, 2].slice(1.5);",
+            "[1, 2].slice(2, 1);",
+            "[].pop();",
+            "[].push();",
+            "[].missing();",
+            "utils::list_len(\"abc\");",
+        ] {
+            let mut vm = VM::init();
+            assert_eq!(
+                vm.interpret(src.to_owned()),
+                InterpretResult::RuntimeError,
+                "{src}"
+            );
+        }
+
+        let mut vm = VM::init();
+        let result = vm.interpret_with_diagnostics("var a = [1];\na.remove(3);".to_owned());
+        assert_eq!(
+            result.unwrap_err().kind,
+            VmError::Runtime("List index 3 out of range for length 1.".to_string())
+        );
+    }
+
+    #[test]
+    fn tests_gc_stress_keeps_sliced_lists() {
+        let (mut vm, out) = captured(VM::init());
+        vm.set_gc_mode(GcMode::Stress);
+        let src = "
+                var inner = [1];
+                var a = [inner, [2], [3]];
+                var s = a.slice(0, 2);
+                a = nil;
+                var junk = [0];
+                var get = s.len;
+                print get();
+                print s[0][0];
+            ";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        assert_eq!(out.contents(), "2\n1\n");
+    }
+
+    #[test]
+    fn tests_string_methods() {
+        assert_prints!(
+            "
+            var s = \"  Hello, World  \".trim();
+            print s.upper();
+            print s.lower();
+            print s.len();
+            print s.substring(7);
+            print s.slice(0, 5);
+            print s.find(\"World\");
+            print s.find(\"xyz\") == nil;
+            print s.replace(\"l\", \"L\");
+            print s.starts_with(\"Hell\");
+            print s.ends_with(\"ld\");
+            print s.ends_with(\"x\");
+            print \"a,b,c\".split(\",\").join(\"|\");
+            print \"ab\".repeat(3);
+            print \" 42 \".to_number() + 1;
+            print \"4x\".to_number() == nil;
+            print \"x\".to_string();
+
+            var chars = \"héllo\".chars();
+            print chars.len();
+            print chars[1];
+            print \"héllo\".find(\"llo\");
+            var upper = \"abc\".upper;
+            print upper();
+            ",
+            "HELLO, WORLD\nhello, world\n12\nWorld\nHello\n7\ntrue\nHeLLo, WorLd\n\
+             true\ntrue\nfalse\na|b|c\nababab\n43\ntrue\nx\n5\né\n2\nABC\n"
+        );
+    }
+
+    #[test]
+    fn tests_string_natives() {
+        assert_prints!(
+            "
+            print strings::upper(\"abc\");
+            print strings::trim(\" a \");
+            print strings::substring(\"hello\", 1, 3);
+            print strings::replace(\"aaa\", \"a\", \"b\");
+            print strings::to_string(12) + \"!\";
+            print strings::to_number(\"1.5\");
+            ",
+            "ABC\na\nel\nbbb\n12!\n1.5\n"
+        );
+        // both count characters, not bytes.
+        assert_prints!(
+            "print \"héllo\".len(); print strings::str_len(\"héllo\");",
+            "5\n5\n"
+        );
+    }
+
+    #[test]
+    fn tests_string_methods_report_errors() {
+        for src in [
+            "\"abc\".substring(4);",
+            "\"abc\".substring(2, 1);",
+            "\"abc\".repeat(-1);",
+            "\"abc\".split(1);",
+            "\"abc\".missing();",
+            "strings::upper(1);",
+        ] {
+            let mut vm = VM::init();
+            assert_eq!(
+                vm.interpret(src.to_owned()),
+                InterpretResult::RuntimeError,
+                "{src}"
+            );
+        }
+        for src in [
+            "\"ab\".repeat(9223372036854775807);",
+            "strings::repeat(\"ab\", 4611686018427387904);",
+        ] {
+            let error = VM::init()
+                .interpret_with_diagnostics(src.to_owned())
+                .unwrap_err();
+            assert_eq!(
+                error.message, "Repeated string would be longer than 1073741824 bytes.",
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn tests_files_module() {
+        let dir = std::env::temp_dir().join(format!("rox_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.txt");
+        std::fs::write(dir.join("input.txt"), "first\nsecond\n").unwrap();
+
+        let src = format!(
+            "
+            var dir = \"{dir}\";
+            var path = \"{path}\";
+            print files::exists(path);
+            var lines = files::lines(dir + \"/input.txt\");
+            print lines.len();
+            print lines[1];
+
+            files::write(path, lines[0]);
+            files::append(path, \",\" + lines[1]);
+            print files::exists(path);
+            print files::read(path);
+            print files::list_dir(dir).join(\" \");
+            files::remove(path);
+            print files::exists(path);
+            ",
+            dir = dir.display(),
+            path = path.display()
+        );
+        assert_prints!(
+            src,
+            "false\n2\nsecond\ntrue\nfirst,second\ninput.txt report.txt\nfalse\n"
+        );
+
+        let mut vm = VM::init();
+        let missing = format!("files::read(\"{}\");", path.display());
+        let error = vm.interpret_with_diagnostics(missing).unwrap_err();
+        assert!(matches!(error.kind, VmError::Native(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tests_natives_can_return_lists() {
+        assert_prints!(
+            "
+            var parts = strings::split(\"a-b-c\", \"-\");
+            parts.push(\"d\");
+            print parts.join(\"\");
+            print strings::chars(\"xy\").len();
+            ",
+            "abcd\n2\n"
+        );
+    }
+
+    #[test]
+    fn tests_native_arity_is_checked_at_compile_time() {
+        let diagnostics = Compiler::compile("math::sqrt(1, 2);").unwrap_err();
+        assert_eq!(
+            diagnostics[0].message,
+            "Expected 1 arguments but got 2 calling `math::sqrt`."
+        );
+        assert!(Compiler::compile("strings::substring(\"abc\", 1);").is_ok());
+        assert!(Compiler::compile("strings::substring(\"abc\");").is_err());
+        assert!(Compiler::compile("var f = math::sqrt; f(1, 2);").is_ok());
+    }
+
+    #[test]
+    fn tests_native_arity_is_checked_at_runtime() {
+        let mut vm = VM::init();
+        for (src, message) in [
+            (
+                "var p = math::pow; p(2);",
+                "Expected 2 arguments but got 1.",
+            ),
+            (
+                "var f = math::sqrt; f(1, 2);",
+                "Expected 1 arguments but got 2.",
+            ),
+            (
+                "var s = strings::substring; s(\"abc\");",
+                "Expected 2 to 3 arguments but got 1.",
+            ),
+            // host natives are checked the same way.
+            (
+                "var l = utils::list_len; l();",
+                "Expected 1 arguments but got 0.",
+            ),
+        ] {
+            let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+            assert_eq!(error.kind, VmError::Runtime(message.to_owned()), "{src}");
+        }
+        let (result, out, _) = run_captured("var p = math::pow; print p(2, 3);");
+        assert_eq!(result, InterpretResult::Ok);
+        assert_eq!(out, "8\n");
+    }
+
+    #[test]
+    fn tests_unknown_native_fails_compile() {
+        assert!(Compiler::compile("math::cbrt(8);").is_err());
+    }
+
+    fn double(_arg_count: usize, args: &[Value]) -> NativeResult {
+        match args[0].as_f64() {
+            Some(n) => Ok(NativeValue::Value(Value::Number(n * 2.0))),
+            _ => Ok(NativeValue::Value(Value::Nil)),
+        }
+    }
+
+    #[test]
+    fn tests_embedder_registered_native() {
+        let (mut vm, out) = captured(VM::init());
+        vm.define_native(Native::new(
+            "math::double",
+            Arity::exactly(1),
+            "twice a number.",
+            NativeFn(double),
+        ));
+        assert!(vm.natives().get("math::double").is_some());
+        vm.interpret_with_diagnostics("print math::double(21);".to_owned())
+            .unwrap();
+        assert_eq!(out.contents(), "42\n");
+        assert_eq!(
+            vm.interpret("math::double();".to_owned()),
+            InterpretResult::CompileError
+        );
+        // a fresh vm does not know about it.
+        assert_interpreter_expects!("math::double(1);", InterpretResult::CompileError);
+    }
+
+    #[test]
+    fn tests_vm_with_natives_only_knows_its_registry() {
+        let mut registry = NativeRegistry::new();
+        registry.register(Native::new(
+            "double",
+            Arity::exactly(1),
+            "twice a number.",
+            NativeFn(double),
+        ));
+        let (mut vm, out) = captured(VM::with_natives(registry));
+        vm.interpret_with_diagnostics("print double(2);".to_owned())
+            .unwrap();
+        assert_eq!(out.contents(), "4\n");
+        assert_eq!(
+            vm.interpret("clock();".to_owned()),
+            InterpretResult::CompileError
+        );
+    }
+
+    #[test]
+    fn tests_global_shadows_native() {
+        assert_prints!(
+            "
+            fun clock(a, b) { return a + b; }
+            print clock(1, 2);
+            ",
+            "3\n"
+        );
+    }
+
+    #[test]
+    fn tests_host_native_captures_state() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let seen = calls.clone();
+        let (mut vm, out) = captured(VM::init());
+        vm.define_native(Native::host(
+            "app::log",
+            Arity::exactly(1),
+            "records a message.",
+            move |ctx: &mut NativeContext, args: &[Value]| -> VmResult {
+                seen.borrow_mut()
+                    .push(ctx.as_string(&args[0]).unwrap_or_default());
+                Ok(Value::Number(seen.borrow().len() as f64))
+            },
+        ));
+        let src = "app::log(\"a\"); print app::log(\"b\");";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "2\n");
+        assert_eq!(*calls.borrow(), vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn tests_host_native_allocates_and_reads_the_heap() {
+        let (mut vm, out) = captured(VM::init());
+        vm.set_gc_mode(GcMode::Stress);
+        vm.define_native(Native::host(
+            "app::pair",
+            Arity::exactly(2),
+            "a list of two lists.",
+            |ctx: &mut NativeContext, args: &[Value]| -> VmResult {
+                let first = ctx.alloc_list(vec![args[0].clone()]);
+                let second = ctx.alloc_list(vec![args[1].clone(), ctx.intern("x")]);
+                Ok(ctx.alloc_list(vec![first, second]))
+            },
+        ));
+        vm.define_native(Native::host(
+            "app::name",
+            Arity::exactly(1),
+            "the name field of an instance, renamed.",
+            |ctx: &mut NativeContext, args: &[Value]| -> VmResult {
+                let name = ctx.get_field(&args[0], "name").unwrap_or_default();
+                let renamed = ctx.intern("bob");
+                ctx.set_field(&args[0], "name", renamed);
+                Ok(name)
+            },
+        ));
+        let src = "
+            var p = app::pair(1, 2);
+            print p[0][0];
+            print p[1][0];
+            print p[1][1];
+            class User { init(name) { this.name = name; } }
+            var u = User(\"ann\");
+            print app::name(u);
+            print u.name;
+            ";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "1\n2\nx\nann\nbob\n");
+    }
+
+    fn define_apply(vm: &mut VM) {
+        vm.define_native(Native::host(
+            "app::apply",
+            Arity::exactly(2),
+            "calls a function with a value.",
+            |ctx: &mut NativeContext, args: &[Value]| -> VmResult {
+                let result = ctx.call(&args[0], &[args[1].clone()])?;
+                Ok(ctx.alloc_list(vec![result]))
+            },
+        ));
+    }
+
+    #[test]
+    fn tests_host_native_calls_back_into_lox() {
+        let (mut vm, out) = captured(VM::init());
+        define_apply(&mut vm);
+        let src = "
+            var total = 0;
+            fun add(n) { total = total + n; return total; }
+            app::apply(add, 2);
+            print app::apply(add, 3)[0];
+            print app::apply((x) => x * 2, 4)[0];
+            print app::apply(math::sqrt, 9)[0];
+            class Box { init(v) { this.v = v; } }
+            print app::apply(Box, 7)[0].v;
+            ";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "5\n8\n3\n7\n");
+        // the closure is borrowed while it runs, so it can't be re-entered.
+        let src = "app::apply((x) => app::apply((y) => y, x), 1);";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::RuntimeError);
+    }
+
+    #[test]
+    fn tests_host_native_callback_error_keeps_its_trace() {
+        let (mut vm, out) = captured(VM::init());
+        define_apply(&mut vm);
+        let src = "fun bad(x) {\n return -x;\n}\napp::apply(bad, \"s\");";
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmError::Runtime("Operand must be a number.".to_string())
+        );
+        assert_eq!(error.stack[0].function, Some("bad".to_string()));
+        // the vm is reusable afterwards.
+        let src = "print app::apply((x) => x + 1, 1)[0];";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "2\n");
+    }
+
+    #[test]
+    fn tests_host_native_recovers_from_a_failed_callback() {
+        let (mut vm, out) = captured(VM::init());
+        vm.set_gc_mode(GcMode::Stress);
+        vm.define_native(Native::host(
+            "app::or_else",
+            Arity::exactly(3),
+            "calls a function with a value, or the fallback if it fails.",
+            |ctx: &mut NativeContext, args: &[Value]| -> VmResult {
+                match ctx.call(&args[0], &[args[1].clone()]) {
+                    Ok(result) => Ok(result),
+                    Err(_) => ctx.call(&args[2], &[args[1].clone()]),
+                }
+            },
+        ));
+        let src = "
+            fun bad(x) { return -x; }
+            fun worse(x) { throw x + \"!\"; }
+            print app::or_else(bad, \"s\", (x) => x + \"?\");
+            print app::or_else(worse, \"s\", (x) => x);
+            try { app::or_else(bad, \"s\", worse); } catch (e) { print e; }
+            ";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "s?\ns\ns!\n");
+        let error = vm
+            .interpret_with_diagnostics("app::or_else(bad, \"s\", bad);".to_owned())
+            .unwrap_err();
+        assert_eq!(error.message, "Operand must be a number.");
+        assert_eq!(error.stack[0].function, Some("bad".to_string()));
+    }
+
+    #[test]
+    fn tests_call_global_from_rust() {
+        let mut vm = VM::init();
+        let src = "
+            var events = 0;
+            fun on_event(name, weight) {
+                events = events + weight;
+                return name + \"!\";
+            }
+            ";
+        assert!(vm.interpret_with_diagnostics(src.to_owned()).is_ok());
+        for _ in 0..3 {
+            let args = ["tick".into_lox(&mut vm), 2.0.into_lox(&mut vm)];
+            let result = vm.call_global("on_event", &args).unwrap();
+            assert_eq!(String::from_lox(&result, &vm).unwrap(), "tick!");
+        }
+        let events = vm.get_global("events").unwrap();
+        assert_eq!(f64::from_lox(&events, &vm).unwrap(), 6.0);
+
+        let error = vm.call_global("missing", &[]).unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'missing'.");
+        let error = vm.call_global("on_event", &[]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmError::Runtime("Expected 2 arguments but got 0".to_string())
+        );
+        // still usable after an error.
+        let args = ["a".into_lox(&mut vm), 1.0.into_lox(&mut vm)];
+        assert!(vm.call_global("on_event", &args).is_ok());
+    }
+
+    #[test]
+    fn tests_globals_set_from_rust_are_visible_to_scripts() {
+        let mut vm = VM::init();
+        let limit = 3.0.into_lox(&mut vm);
+        vm.set_global("limit", limit);
+        let src = "fun over(n) { return n > limit; } var big = over(5);";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        let big = vm.get_global("big").unwrap();
+        assert!(bool::from_lox(&big, &vm).unwrap());
+        assert!(vm.globals().any(|(name, _)| name == "over"));
+        // a later script sees the globals of an earlier one.
+        vm.interpret_with_diagnostics("var small = over(2);".to_owned())
+            .unwrap();
+        assert_eq!(vm.get_global("small"), Some(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn tests_lists_convert_both_ways() {
+        let mut vm = VM::init();
+        vm.set_gc_mode(GcMode::Stress);
+        let src = "fun sums(rows) {
+                       var out = [];
+                       for (var i = 0; i < rows.len(); i = i + 1) {
+                           var total = 0;
+                           for (var j = 0; j < rows[i].len(); j = j + 1) total = total + rows[i][j];
+                           out.push(total);
+                       }
+                       out.push(nil);
+                       return out;
+                   }";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        let rows = vec![vec![1.0, 2.0], vec![], vec![3.0, 4.0, 5.0]].into_lox(&mut vm);
+        let result = vm.call_global("sums", &[rows]).unwrap();
+        let sums = Vec::<Option<f64>>::from_lox(&result, &vm).unwrap();
+        assert_eq!(sums, vec![Some(3.0), Some(0.0), Some(12.0), None]);
+        assert!(Vec::<f64>::from_lox(&result, &vm).is_err());
+        assert!(String::from_lox(&Value::Nil, &vm).is_err());
+    }
+
+    #[test]
+    fn tests_repl_keeps_globals_across_entries() {
+        let mut repl = Repl::new(VM::init());
+        assert_eq!(repl.eval("var x = 40;"), Ok(None));
+        assert_eq!(repl.eval("fun add(n) { return x + n; }"), Ok(None));
+        assert_eq!(repl.eval("add(2);"), Ok(Some("42".to_string())));
+        // the semicolon is optional for a lone expression.
+        assert_eq!(repl.eval("x = x + 1"), Ok(Some("41".to_string())));
+        assert_eq!(repl.eval("print x;"), Ok(None));
+        assert_eq!(repl.eval("\"a\" + \"b\""), Ok(Some("ab".to_string())));
+        assert_eq!(repl.eval("nil"), Ok(None));
+        // the `;` is also added after a lambda's body or before a trailing comment.
+        assert_eq!(repl.eval("var f = fun (a) { return a; }"), Ok(None));
+        assert_eq!(repl.eval("f(3)"), Ok(Some("3".to_string())));
+        assert_eq!(repl.eval("1 + 2 // sum"), Ok(Some("3".to_string())));
+        assert_eq!(repl.eval("class A {}"), Ok(None));
+    }
+
+    #[test]
+    fn tests_repl_recovers_from_errors() {
+        let mut repl = Repl::new(VM::init());
+        assert_eq!(repl.eval("const limit = 3;"), Ok(None));
+        // const-ness is remembered by later entries.
+        assert!(repl.eval("limit = 4;").is_err());
+        assert!(repl.eval("undeclared + 1;").is_err());
+        assert!(repl.eval("-\"oops\";").is_err());
+        // an entry that fails either way reports the errors of the entry as typed.
+        let error = repl.eval("var y = ").unwrap_err();
+        assert!(error.contains("--> 1:8"), "{error}");
+        assert_eq!(repl.eval("limit"), Ok(Some("3".to_string())));
+    }
+
+    #[test]
+    fn tests_repl_commands() {
+        let mut repl = Repl::new(VM::init());
+        assert!(repl.eval(":help").unwrap().unwrap().contains(":disasm"));
+        repl.eval("var b = 2; var a = 1; fun f() { return a; }")
+            .unwrap();
+        let globals = repl.eval(":globals").unwrap().unwrap();
+        let names: Vec<&str> = globals
+            .lines()
+            .map(|l| l.split(" = ").next().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a", "b", "f"]);
+        assert_eq!(repl.eval(":disasm f"), Ok(None));
+        assert!(repl.eval(":disasm a").is_err());
+        assert!(repl.eval(":disasm nope").is_err());
+        assert!(repl.eval(":nope").is_err());
+        assert_eq!(repl.eval(":reset"), Ok(Some("Session reset.".to_string())));
+        assert_eq!(repl.eval(":globals"), Ok(Some(String::new())));
+        assert!(repl.eval("a;").is_err());
+        // natives survive a reset.
+        assert_eq!(repl.eval("math::sqrt(4)"), Ok(Some("2".to_string())));
+    }
+
+    #[test]
+    fn tests_repl_reset_keeps_the_vm_settings() {
+        let out = SharedBuffer::new();
+        let mut vm = VM::init().with_output(out.clone(), SharedBuffer::new());
+        vm.set_max_frames(8);
+        vm.define_native(Native::new(
+            "app::answer",
+            Arity::exactly(0),
+            "the answer.",
+            NativeFn(|_, _| Ok(Value::Int(42).into())),
+        ));
+        let mut repl = Repl::new(vm);
+        repl.eval("var deep = 0;").unwrap();
+        repl.eval(":reset").unwrap();
+        assert!(repl.eval("deep").is_err());
+        repl.eval("print app::answer();").unwrap();
+        assert_eq!(out.contents(), "42\n");
+        let error = repl
+            .eval("fun f(n) { return f(n + 1); } f(0);")
+            .unwrap_err();
+        assert!(error.contains("Stack overflow"), "{error}");
+        // a reset session defines globals like a new one.
+        assert_eq!(repl.eval("var deep = 1; deep"), Ok(Some("1".to_string())));
+    }
+
+    #[test]
+    fn tests_repl_entry_is_complete_once_balanced() {
+        assert!(repl::is_complete("var x = 1;"));
+        assert!(repl::is_complete(""));
+        assert!(!repl::is_complete("fun f(a) {"));
+        assert!(!repl::is_complete("fun f(a) {\n  return [a,\n"));
+        assert!(repl::is_complete("fun f(a) {\n  return [a];\n}"));
+        // brackets inside strings and comments don't count.
+        assert!(repl::is_complete("print \"{(\"; // ["));
+        assert!(!repl::is_complete("var s = \"multi"));
+        assert!(repl::is_complete("}"));
+    }
+
+    #[test]
+    fn tests_repl_completion() {
+        let mut repl = Repl::new(VM::init());
+        repl.eval("var counter = 0; fun count() {}").unwrap();
+        let names = repl.completion_names();
+        assert_eq!(
+            repl::complete(&names, "print cou", 9),
+            (6, vec!["count".to_string(), "counter".to_string()])
+        );
+        assert_eq!(
+            repl::complete(&names, "math::s", 7),
+            (0, vec!["math::sqrt".to_string()])
+        );
+        assert_eq!(
+            repl::complete(&names, "wh", 2),
+            (0, vec!["while".to_string()])
+        );
+        assert_eq!(repl::complete(&names, "x = ", 4), (4, vec![]));
+        assert_eq!(
+            repl::complete(&names, "\"é\" + cou", 10),
+            (7, vec!["count".to_string(), "counter".to_string()])
+        );
+    }
+
+    fn parse_cli(args: &str) -> Result<Cli, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_owned).collect();
+        Cli::parse(&args)
+    }
+
+    #[test]
+    fn tests_cli_commands() {
+        assert_eq!(parse_cli("").unwrap().command, Command::Repl);
+        assert_eq!(parse_cli("repl").unwrap().command, Command::Repl);
+        assert_eq!(
+            parse_cli("run a.lox -- x --trace").unwrap().command,
+            Command::Run {
+                path: "a.lox".to_string(),
+                args: vec!["x".to_string(), "--trace".to_string()]
+            }
+        );
+        assert_eq!(
+            parse_cli("a.lox").unwrap().command,
+            Command::Run {
+                path: "a.lox".to_string(),
+                args: vec![]
+            }
+        );
+        assert_eq!(
+            parse_cli("disasm a.lox").unwrap().command,
+            Command::Disasm("a.lox".to_string())
+        );
+        assert_eq!(
+            parse_cli("check a.lox").unwrap().command,
+            Command::Check("a.lox".to_string())
+        );
+        assert_eq!(
+            parse_cli("compile a.lox -o b.roxc").unwrap().command,
+            Command::Compile {
+                input: "a.lox".to_string(),
+                output: Some("b.roxc".to_string())
+            }
+        );
+        assert!(parse_cli("a.lox b.lox").is_err());
+        assert!(parse_cli("check").is_err());
+        assert!(parse_cli("check a.lox -- x").is_err());
+    }
+
+    #[test]
+    fn tests_cli_help() {
+        for args in [
+            "help",
+            "-h",
+            "--help",
+            "--trace --help",
+            "run a.lox -h",
+            "--help -- x",
+        ] {
+            assert_eq!(parse_cli(args).unwrap().command, Command::Help, "{args}");
+        }
+        // after `--` it is an argument of the script.
+        assert_eq!(
+            parse_cli("a.lox -- --help").unwrap().command,
+            Command::Run {
+                path: "a.lox".to_string(),
+                args: vec!["--help".to_string()]
+            }
+        );
+        assert!(parse_cli("help a.lox").is_err());
+    }
+
+    #[test]
+    fn tests_cli_flags() {
+        let cli = parse_cli("--gc-mode=stress --trace --stack-size 512 run a.lox --max-frames=8")
+            .unwrap();
+        assert_eq!(cli.gc_mode, Some(GcMode::Stress));
+        assert!(cli.trace);
+        assert_eq!(cli.stack_size, Some(512));
+        assert_eq!(cli.max_frames, Some(8));
+        let cli = parse_cli("--module-path lib --module-path=vendor a.lox").unwrap();
+        assert_eq!(cli.module_paths, vec!["lib", "vendor"]);
+        assert!(parse_cli("--gc-mode=sometimes a.lox").is_err());
+        assert!(parse_cli("--max-frames=0 a.lox").is_err());
+        assert!(parse_cli("--stack-size a.lox").is_err());
+        assert!(parse_cli("--verbose a.lox").is_err());
+    }
+
+    #[test]
+    fn tests_vm_call_limits() {
+        let src = "fun f(n) { if (n > 0) { f(n - 1); } } f(20);";
+        let mut vm = VM::init();
+        vm.set_max_frames(10);
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.kind, VmError::StackOverflow);
+
+        let mut vm = VM::init();
+        vm.set_stack_size(16);
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.kind, VmError::StackOverflow);
+
+        assert_interprets_ok!(src);
+    }
+
+    #[test]
+    fn tests_print_goes_to_the_vm_output() {
+        let (result, out, err) = run_captured("print 1 + 2; print \"a\" + \"b\";");
+        assert_eq!(result, InterpretResult::Ok);
+        assert_eq!(out, "3\nab\n");
+        assert_eq!(err, "");
+    }
+
+    #[test]
+    fn tests_errors_go_to_the_vm_error_output() {
+        let (result, out, err) = run_captured("print 1;\n-\"oops\";");
+        assert_eq!(result, InterpretResult::RuntimeError);
+        assert_eq!(out, "1\n");
+        assert_eq!(err, "Operand must be a number.\n[line 2] in script\n");
+
+        let (result, _, err) = run_captured("print 1 +;");
+        assert_eq!(result, InterpretResult::CompileError);
+        assert!(err.contains("expected an expression here."));
+    }
+
+    #[test]
+    fn tests_io_write_natives() {
+        let (result, out, err) =
+            run_captured("io::write(\"name? \"); io::write(42); io::writeError(\"bad\");");
+        assert_eq!(result, InterpretResult::Ok);
+        assert_eq!(out, "name? 42");
+        assert_eq!(err, "bad\n");
+    }
+
+    #[test]
+    fn tests_gc_log_goes_to_the_vm_output() {
+        let out = SharedBuffer::new();
+        let mut vm = VM::init().with_output(out.clone(), SharedBuffer::new());
+        vm.set_gc_mode(GcMode::Log);
+        assert_eq!(
+            vm.interpret("var a = [1]; print a[0];".to_owned()),
+            InterpretResult::Ok
+        );
+        vm.collect_garbage();
+        let log = out.contents();
+        assert!(log.contains("allocate"));
+        assert!(log.contains("gc_begin"));
+        assert!(log.contains("1\n"));
+    }
+
+    #[test]
+    fn tests_trace_goes_to_the_vm_output() {
+        let out = SharedBuffer::new();
+        let mut vm = VM::init().with_output(out.clone(), SharedBuffer::new());
+        vm.set_trace(true);
+        assert_eq!(vm.interpret("print 1 + 2;".to_owned()), InterpretResult::Ok);
+        let trace = out.contents();
+        assert!(trace.contains("OP_Add"), "{trace}");
+        assert!(trace.contains("[ 1 ][ 2 ]"), "{trace}");
+        assert!(trace.contains("\n3\n"), "{trace}");
+        assert!(
+            Chunk::disassembly(&Compiler::compile("print 1;").unwrap().chunk, "s")
+                .starts_with("=====s=====\n")
+        );
+    }
+
+    #[test]
+    fn tests_disassembly_of_closures() {
+        let src = "fun outer(a) {\n  fun inner() { return a; }\n  return inner;\n}\nprint outer;";
+        let script = Compiler::compile(src).unwrap();
+        let outer = script
+            .chunk
+            .constants
+            .iter()
+            .find_map(|constant| match constant {
+                Value::LoxFunction(function) => Some(function.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let listing = Chunk::disassembly(&script.chunk, "script")
+            + &Chunk::disassembly(&outer.chunk, "outer");
+        let expected = "\
+=====script=====
+0000    4    OP_CLOSURE\t1\t<fn outer>
+0002     |    OP_DEFINE_GLOBAL\touter
+0004    5    OP_GET_GLOBAL\touter
+0006     |    OP_PRINT
+0007     |    OP_NIL
+0008     |  RETURN
+=====outer=====
+0000    2    OP_CLOSURE\t0\t<fn inner>
+0002     |               local 1
+0005    3 OP_GET_LOCAL \t2
+0007     |  RETURN
+0008    4    OP_NIL
+0009     |  RETURN
+";
+        assert_eq!(listing, expected);
+    }
+
+    #[test]
+    fn tests_golden_lox_scripts() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+        let report = golden::run_dir(&dir).unwrap();
+        assert!(report.is_success(), "{}", report);
+        assert!(!report.passed.is_empty());
+    }
+
+    #[test]
+    fn tests_golden_expectations_are_parsed() {
+        let src = "print 1; // expect: 1\n\
+                   var = 2; // Error at '=': Expect variable name.\n\
+                   // [line 9] Error at end: Expect '}' after block.\n\
+                   // [java line 9] Error at end: only jlox reports this.\n\
+                   -\"a\"; // expect runtime error: Operand must be a number.";
+        let expected = Expectations::parse(src);
+        assert_eq!(expected.output, vec!["1"]);
+        assert_eq!(
+            expected.compile_errors,
+            vec![
+                "[line 2] Error at '=': Expect variable name.",
+                "[line 9] Error at end: Expect '}' after block."
+            ]
+        );
+        assert_eq!(
+            expected.runtime_error,
+            Some(("Operand must be a number.".to_owned(), 5))
+        );
+        assert!(!expected.skip);
+        assert!(Expectations::parse("// nontest").skip);
+        // a `//` in a string is not the comment, a bare `// expect:` is an empty line.
+        let expected =
+            Expectations::parse("print \"a//b\"; // expect: a//b\nprint \"\"; // expect:");
+        assert_eq!(expected.output, vec!["a//b", ""]);
+    }
+
+    #[test]
+    fn tests_golden_check_reports_mismatches() {
+        assert!(golden::check("print 1 + 1; // expect: 2").is_empty());
+        assert_eq!(
+            golden::check("print 3; // expect: 2\nprint 4;"),
+            vec![
+                "Expected output '2' on line 1 and got '3'.",
+                "Got output '4' when none was expected."
+            ]
+        );
+        assert_eq!(
+            golden::check("print -nil; // expect: 1"),
+            vec![
+                "Missing expected output '1'.",
+                "Unexpected runtime error 'Operand must be a number.' on line 1."
+            ]
+        );
+    }
+
+    /// runs an external suite, e.g the upstream Crafting Interpreters tests:
+    /// `ROX_CONFORMANCE_DIR=craftinginterpreters/test cargo test -- --ignored conformance --nocapture`
+    #[test]
+    #[ignore]
+    fn tests_conformance_suite() {
+        let dir = std::env::var("ROX_CONFORMANCE_DIR").expect("ROX_CONFORMANCE_DIR is not set");
+        let report = golden::run_dir(std::path::Path::new(&dir)).unwrap();
+        println!("{}", report);
+    }
+
+    #[test]
+    fn tests_throw_is_caught() {
+        let src = "
+            var log = \"\";
+            try { log = log + \"a\"; throw \"b\"; log = log + \"x\"; } catch (e) { log = log + e; }
+            print log;
+
+            // the handler of the innermost try runs, in the function that threw or a caller.
+            fun thrower(n) { if (n == 0) { throw 42; } return thrower(n - 1); }
+            var caught = nil;
+            try { try { thrower(3); } catch (e) { caught = e; } } catch (e) { caught = -1; }
+            print caught;
+
+            // locals declared before the try survive, closures inside it are closed.
+            fun f() {
+              var kept = 1;
+              var closure;
+              try { var inner = 2; closure = fun () { return inner; }; throw nil; } catch (e) {}
+              return kept + closure();
+            }
+            print f();
+
+            for (var i = 0; i < 3; i = i + 1) { try { if (i == 1) { break; } } catch (e) {} log = log + i; }
+            print log;
+            ";
+        assert_prints!(src, "ab\n42\n3\nab0\n");
+    }
+
+    #[test]
+    fn tests_finally_runs_and_rethrows() {
+        let src = "
+            var log = \"\";
+            try { log = log + \"a\"; } finally { log = log + \"b\"; }
+            try { throw \"c\"; } catch (e) { log = log + e; } finally { log = log + \"d\"; }
+            try {
+              try { throw \"e\"; } finally { log = log + \"f\"; }
+            } catch (e) { log = log + e; }
+            try {
+              try { throw 1; } catch (e) { throw \"g\"; } finally { log = log + \"h\"; }
+            } catch (e) { log = log + e; }
+            print log;
+            ";
+        assert_prints!(src, "abcdfehg\n");
+    }
+
+    #[test]
+    fn tests_runtime_errors_are_caught_as_error_instances() {
+        let src = "
+            fun bad() {
+              return -\"x\";
+            }
+            var error;
+            try { bad(); } catch (e) { error = e; }
+            print error.message;
+            print error.stack[0];
+            print error.stack[1];
+            try { math::sqrt(\"nine\"); } catch (e) { error = e; }
+            print error.message;
+            ";
+        assert_prints!(
+            src,
+            "Operand must be a number.\n[line 3] in bad()\n[line 6] in script\n\
+             Expects a double(f64).\n"
+        );
+
+        // thrown again, a caught error keeps its message.
+        let mut vm = VM::init();
+        let error = vm
+            .interpret_with_diagnostics("try { nil(); } catch (e) { throw e; }".to_owned())
+            .unwrap_err();
+        assert_eq!(
+            error.kind,
+            VmError::Runtime("Can only call functions, closures and constructors.".to_owned())
+        );
+    }
+
+    #[test]
+    fn tests_uncaught_throw_is_a_runtime_error() {
+        let mut vm = VM::init();
+        let src = "fun f() {\n  throw \"boom\";\n}\nf();";
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.kind, VmError::Thrown("boom".to_owned()));
+        assert_eq!(error.message, "Uncaught exception: boom");
+        assert_eq!(
+            error.stack,
+            vec![
+                TraceFrame {
+                    function: Some("f".to_owned()),
+                    line: 2
+                },
+                TraceFrame {
+                    function: None,
+                    line: 4
+                },
+            ]
+        );
+        // the vm is reusable afterwards.
+        assert_eq!(vm.interpret("print 1;".to_owned()), InterpretResult::Ok);
+        assert_interpreter_expects!(
+            "class E { init(m) { this.message = m; } } throw E(\"mine\");",
+            InterpretResult::RuntimeError
+        );
+    }
+
+    #[test]
+    fn tests_errors_are_caught_across_host_natives() {
+        let (mut vm, out) = captured(VM::init());
+        define_apply(&mut vm);
+        let src = "
+            fun bad(x) { throw x; }
+            try { app::apply(bad, \"s\"); } catch (e) { print e; }
+            // a try inside the callback catches the error before the native sees it.
+            fun safe(x) { try { return -x; } catch (e) { return e.message; } }
+            print app::apply(safe, \"s\")[0];
+            ";
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "s\nOperand must be a number.\n");
+    }
+
+    #[test]
+    fn tests_try_compile_errors() {
+        let errors = |src: &str| -> Vec<String> {
+            Compiler::compile(src)
+                .unwrap_err()
+                .iter()
+                .map(|d| d.to_string())
+                .collect()
+        };
+        assert_eq!(
+            errors("try {} print 1;"),
+            vec!["[line 1] Error at 'print': Expect 'catch' or 'finally' after try block."]
+        );
+        // loops inside the try and returns without a finally are fine.
+        assert!(
+            Compiler::compile(
+                "fun f() { try { while (true) { break; } return 1; } catch (e) { return 2; } }
+                 try { for (;;) { continue; } } finally {}"
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn tests_exits_run_finally_blocks() {
+        let src = "
+            var log = \"\";
+            fun f(n) {
+                try {
+                    while (true) {
+                        try {
+                            if (n == 0) { return \"r\"; }
+                            if (n == 1) break;
+                            n = n - 2;
+                            continue;
+                        } finally {
+                            log = log + \"i\";
+                        }
+                    }
+                    return \"b\";
+                } finally {
+                    log = log + \"o\";
+                }
+            }
+            print f(0) + f(1) + f(4);
+            print log;
+            ";
+        let function = Compiler::compile(src).unwrap();
+        assert_eq!(verify(&function.chunk), Ok(()));
+        let (mut vm, out) = captured(VM::init());
+        vm.set_gc_mode(GcMode::Stress);
+        vm.interpret_with_diagnostics(src.to_owned()).unwrap();
+        assert_eq!(out.contents(), "rbr\nioioiiio\n");
+        // the finally block can still throw, which replaces the pending exit.
+        let mut vm = VM::init();
+        let src = "fun g() { try { return 1; } finally { throw \"f\"; } } g();";
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.kind, VmError::Thrown("f".to_string()));
+    }
+
+    #[test]
+    fn tests_try_handlers_are_serialized_and_verified() {
+        let src = "
+            fun f(x) { try { return -x; } catch (e) { return e.message; } }
+            var log = \"\";
+            try { throw f(\"s\"); } catch (e) { log = e; } finally { log = log + \"!\"; }
+            print log;
+            ";
+        let function = Compiler::compile(src).unwrap();
+        assert_eq!(function.chunk.handlers.len(), 2);
+        assert_eq!(verify(&function.chunk), Ok(()));
+        let bytes = function.chunk.serialize().unwrap();
+        let chunk = Chunk::deserialize(&bytes).unwrap();
+        assert_eq!(chunk, function.chunk);
+        let (mut vm, out) = captured(VM::init());
+        assert_eq!(vm.interpret_chunk(chunk.clone()), InterpretResult::Ok);
+        assert_eq!(out.contents(), "Operand must be a number.!\n");
+
+        let mut bad = chunk.clone();
+        bad.handlers[0].target += 1;
+        assert!(matches!(
+            verify(&bad).unwrap_err().kind,
+            VerifyErrorKind::BadHandler(0) | VerifyErrorKind::StackMismatch { .. }
+        ));
+        let mut bad = chunk;
+        bad.handlers[0].depth += 5;
+        assert!(matches!(
+            verify(&bad).unwrap_err().kind,
+            VerifyErrorKind::StackUnderflow | VerifyErrorKind::StackMismatch { .. }
+        ));
+    }
+
+    /// a fresh directory holding `files`, removed again by the caller.
+    fn module_dir(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rox_{}_{}", name, std::process::id()));
+        for (path, source) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn tests_modules_have_their_own_globals() {
+        let dir = module_dir(
+            "modules",
+            &[(
+                "lib/shapes.lox",
+                "var name = \"shapes\";
+                 var made = 0;
+                 class Square {
+                   init(side) { this.side = side; made = made + 1; }
+                   area() { return this.side * this.side; }
+                 }
+                 fun describe() { return name + \" \" + strings::to_string(made); }",
+            )],
+        );
+        let src = "
+            import \"lib/shapes.lox\" as shapes;
+            from \"lib/shapes.lox\" import Square, describe;
+            var name = \"main\";
+            print shapes::Square(3).area();
+            print Square(2).area();
+            print shapes.made;
+            print describe();
+            print name;
+            fun later() { import \"lib/shapes.lox\" as again; return again; }
+            print later() == shapes;
+            ";
+        for gc_mode in [GcMode::default(), GcMode::Stress] {
+            let (mut vm, out) = captured(VM::init());
+            vm.set_gc_mode(gc_mode);
+            vm.set_script_path(dir.join("main.lox"));
+            assert_eq!(vm.interpret_with_diagnostics(src.to_owned()), Ok(()));
+            assert_eq!(out.contents(), "9\n4\n2\nshapes 2\nmain\ntrue\n");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tests_modules_are_found_on_the_module_path() {
+        let dir = module_dir(
+            "module_path",
+            &[
+                ("vendor/text.lox", "fun shout(s) { return s + \"!\"; }"),
+                (
+                    "app/local.lox",
+                    "import \"text.lox\" as text; var greeting = text::shout(\"hi\");",
+                ),
+            ],
+        );
+        let src = "import \"local.lox\" as local; print local::greeting;";
+
+        let mut vm = VM::init();
+        vm.set_script_path(dir.join("app/main.lox"));
+        let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+        assert_eq!(error.message, "Can't find module 'text.lox'.");
+
+        let (mut vm, out) = captured(VM::init());
+        vm.set_script_path(dir.join("app/main.lox"));
+        vm.add_module_path(dir.join("vendor"));
+        assert_eq!(vm.interpret_with_diagnostics(src.to_owned()), Ok(()));
+        assert_eq!(out.contents(), "hi!\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tests_import_errors() {
+        let dir = module_dir(
+            "import_errors",
+            &[
+                ("a.lox", "import \"b.lox\" as b;"),
+                ("b.lox", "import \"a.lox\" as a;"),
+                ("main.lox", "import \"main.lox\" as me;"),
+                ("broken.lox", "var = 1;"),
+                ("failing.lox", "var ok = 1;\nnil();"),
+            ],
+        );
+        let import = |src: &str| {
+            let mut vm = VM::init().with_output(SharedBuffer::new(), SharedBuffer::new());
+            vm.set_script_path(dir.join("main.lox"));
+            vm.interpret_with_diagnostics(src.to_owned()).unwrap_err()
+        };
+
+        // cycles are reported with the paths of the files in them.
+        let root = format!("{}/", dir.canonicalize().unwrap().display());
+        let error = import("import \"a.lox\" as a;");
+        assert_eq!(
+            error.message.replace(&root, ""),
+            "Import cycle: a.lox -> b.lox -> a.lox."
+        );
+        // the script importing itself is a cycle too.
+        let error = import(&std::fs::read_to_string(dir.join("main.lox")).unwrap());
+        assert_eq!(
+            error.message.replace(&root, ""),
+            "Import cycle: main.lox -> main.lox."
+        );
+
+        // a failure inside a module is traced to the module's file.
+        let error = import("fun load() {\n  import \"failing.lox\" as failing;\n}\nload();");
+        let frames: Vec<String> = error
+            .stack
+            .iter()
+            .map(|frame| frame.to_string().replace(&root, ""))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                "[line 2] in failing.lox",
+                "[line 2] in load()",
+                "[line 4] in script"
+            ]
+        );
+        // and so is the stack of the error a catch receives.
+        let out = SharedBuffer::new();
+        let mut vm = VM::init().with_output(out.clone(), SharedBuffer::new());
+        vm.set_script_path(dir.join("main.lox"));
+        let src = "try { import \"failing.lox\" as failing; } catch (e) { print e.stack[0]; }";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        assert_eq!(
+            out.contents().replace(&root, ""),
+            "[line 2] in failing.lox\n"
+        );
+
+        let error = import("import \"broken.lox\" as broken;");
+        assert!(matches!(error.kind, VmError::Compile(_)));
+        assert!(error.message.contains("Expect variable name."));
+
+        let error = import("import \"missing.lox\" as missing;");
+        assert_eq!(error.message, "Can't find module 'missing.lox'.");
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let errors = |src: &str| -> Vec<String> {
+            Compiler::compile(src)
+                .unwrap_err()
+                .iter()
+                .map(|d| d.to_string())
+                .collect()
+        };
+        assert_eq!(
+            errors("import \"a.lox\" a;"),
+            vec!["[line 1] Error at 'a': Expect 'as' after module path."]
+        );
+        assert_eq!(
+            errors("from \"a.lox\" import a,;"),
+            vec!["[line 1] Error at ';': Expect name to import."]
+        );
+        assert_eq!(
+            errors("import \"a.lox\" as a; a::b = 1;"),
+            vec!["[line 1] Error at '=': Can't assign to `a::b`, module globals are read-only."]
+        );
+        // `from` and `as` are only keywords inside an import.
+        assert!(Compiler::compile("var from = 1; var as = from; print as;").is_ok());
+    }
+
+    #[test]
+    fn tests_integers_are_kept_apart_from_floats() {
+        let src = "
+            print 7 / 2;
+            print math::div(7, 2);
+            print -7 % 3;
+            print 9007199254740993 - 1;
+            var l = [1, 2, 3];
+            l[1.0] = 5;
+            print l[1];
+            print l.index_of(5);
+            ";
+        let function = Compiler::compile(src).unwrap();
+        let bytes = function.chunk.serialize().unwrap();
+        let chunk = Chunk::deserialize(&bytes).unwrap();
+        assert_eq!(chunk, function.chunk);
+        let (mut vm, out) = captured(VM::init());
+        assert_eq!(vm.interpret_chunk(chunk), InterpretResult::Ok);
+        assert_eq!(out.contents(), "3.5\n3\n-1\n9007199254740992\n5\n1\n");
+
+        let mut vm = VM::init();
+        let src = "var n = 6 * 7; var x = n / 4; var h = n / 2; var s = \"12\".to_number();";
+        assert_eq!(vm.interpret(src.to_owned()), InterpretResult::Ok);
+        assert_eq!(vm.get_global("n"), Some(Value::Int(42)));
+        assert_eq!(vm.get_global("x"), Some(Value::Number(10.5)));
+        assert_eq!(vm.get_global("h"), Some(Value::Int(21)));
+        assert_eq!(vm.get_global("s"), Some(Value::Int(12)));
+        assert_eq!(i64::from_lox(&Value::Number(3.0), &vm), Ok(3));
+        assert!(i64::from_lox(&Value::Number(3.5), &vm).is_err());
+        assert_eq!(f64::from_lox(&Value::Int(3), &vm), Ok(3.0));
+        for (src, message) in [
+            ("math::div(1, 0);", "Division by zero."),
+            ("math::div(-9223372036854775808, -1);", "Integer overflow."),
+            ("math::div(1.5, 1);", "Expected whole numbers."),
+            (
+                "var d = math::div; d(1);",
+                "Expected 2 arguments but got 1.",
+            ),
+        ] {
+            let error = vm.interpret_with_diagnostics(src.to_owned()).unwrap_err();
+            assert_eq!(error.message, message, "{src}");
+        }
+        // the native checks its own arguments when called from rust.
+        assert_eq!(
+            math::div(1, &[Value::Int(1)]),
+            Err(VmError::Runtime(
+                "Expected 2 arguments but got 1.".to_owned()
+            ))
+        );
+    }
 }